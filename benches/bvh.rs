@@ -0,0 +1,75 @@
+// The same `random_scene` render traced by testing every sphere in turn and by the
+// BVH, to keep an eye on what the hierarchy buys. Run with `cargo bench`.
+use criterion::{criterion_group, criterion_main, Criterion};
+use ray_tracer::bvh::Bvh;
+use ray_tracer::config::CameraConfig;
+use ray_tracer::hit::Hittable;
+use ray_tracer::image::Image;
+use ray_tracer::render::{render_with_options, RenderOptions};
+use ray_tracer::scene::random_scene;
+use ray_tracer::vector::{Scalar, Vector, VectorType};
+use ray_tracer::world::World;
+
+const SEED: u64 = 7;
+const HEIGHT: u32 = 20;
+const WIDTH: u32 = 30;
+const SAMPLES_PER_PIXEL: u32 = 2;
+const MAX_DEPTH: u64 = 10;
+
+// Renders `world` from the default viewpoint of the binary. Everything random comes
+// from `SEED`, so every world holding the same spheres does the same work.
+fn render(world: &World) -> Image {
+    let camera = CameraConfig {
+        look_from: Vector::new(13.0, 2.0, 3.0, VectorType::Point),
+        look_at: Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+        vup: Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+        vfov: 20.0,
+        aspect_ratio: WIDTH as Scalar / HEIGHT as Scalar,
+        aperture: 0.1,
+        focus_distance: 10.0,
+    }
+    .camera();
+    let options = RenderOptions {
+        seed: SEED,
+        ..RenderOptions::default()
+    };
+
+    render_with_options(
+        HEIGHT,
+        WIDTH,
+        SAMPLES_PER_PIXEL,
+        world,
+        camera,
+        MAX_DEPTH,
+        &options,
+    )
+}
+
+fn naive_and_bvh(c: &mut Criterion) {
+    let naive = World::from(random_scene(SEED));
+    let mut bvh = World::new();
+    bvh.add(Bvh::new(
+        random_scene(SEED)
+            .into_iter()
+            .map(|sphere| Box::new(sphere) as Box<dyn Hittable>)
+            .collect(),
+    ));
+
+    // Both paths find the same closest hits and draw the same random numbers, so the
+    // timings are only comparable while the images agree exactly
+    let (naive_image, bvh_image) = (render(&naive), render(&bvh));
+    assert!(naive_image
+        .pixels
+        .iter()
+        .zip(&bvh_image.pixels)
+        .all(|(a, b)| a.data == b.data));
+
+    let mut group = c.benchmark_group("random_scene");
+    group.sample_size(10);
+    group.bench_function("naive", |b| b.iter(|| render(&naive)));
+    group.bench_function("bvh", |b| b.iter(|| render(&bvh)));
+    group.finish();
+}
+
+criterion_group!(benches, naive_and_bvh);
+criterion_main!(benches);
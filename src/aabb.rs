@@ -0,0 +1,173 @@
+use crate::ray::Ray;
+use crate::vector::{Scalar, Vector, VectorType};
+
+// An axis-aligned bounding box from the corner `min` to the corner `max`
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector,
+    pub max: Vector,
+}
+
+impl Aabb {
+    // The box spanned by two opposite corners, in any order
+    pub fn new(a: Vector, b: Vector) -> Self {
+        Self {
+            min: Vector::new(
+                a.data.0.min(b.data.0),
+                a.data.1.min(b.data.1),
+                a.data.2.min(b.data.2),
+                VectorType::Point,
+            ),
+            max: Vector::new(
+                a.data.0.max(b.data.0),
+                a.data.1.max(b.data.1),
+                a.data.2.max(b.data.2),
+                VectorType::Point,
+            ),
+        }
+    }
+
+    // Smallest box containing both `self` and `other`
+    pub fn surrounding(&self, other: &Aabb) -> Self {
+        Self::new(
+            Vector::new(
+                self.min.data.0.min(other.min.data.0),
+                self.min.data.1.min(other.min.data.1),
+                self.min.data.2.min(other.min.data.2),
+                VectorType::Point,
+            ),
+            Vector::new(
+                self.max.data.0.max(other.max.data.0),
+                self.max.data.1.max(other.max.data.1),
+                self.max.data.2.max(other.max.data.2),
+                VectorType::Point,
+            ),
+        )
+    }
+
+    // Widens any side thinner than `2 * delta` to that, around its middle, so boxes of
+    // flat primitives still have volume for the slab test and for splitting. Sides
+    // already thick enough are left as they are.
+    pub fn pad(&self, delta: Scalar) -> Self {
+        let axis = |min: Scalar, max: Scalar| {
+            if max - min >= 2.0 * delta {
+                (min, max)
+            } else {
+                let middle = 0.5 * (min + max);
+                (middle - delta, middle + delta)
+            }
+        };
+        let (x, y, z) = (
+            axis(self.min.data.0, self.max.data.0),
+            axis(self.min.data.1, self.max.data.1),
+            axis(self.min.data.2, self.max.data.2),
+        );
+
+        Self::new(
+            Vector::new(x.0, y.0, z.0, VectorType::Point),
+            Vector::new(x.1, y.1, z.1, VectorType::Point),
+        )
+    }
+
+    // Whether the ray passes through the box anywhere in [t_min, t_max]
+    pub fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> bool {
+        let axis = |v: &Vector| [v.data.0, v.data.1, v.data.2];
+        let slabs = axis(&r.origin)
+            .into_iter()
+            .zip(axis(&r.direction))
+            .zip(axis(&self.min).into_iter().zip(axis(&self.max)));
+        let (mut t_min, mut t_max) = (t_min, t_max);
+
+        for ((origin, direction), (min, max)) in slabs {
+            let inverse = 1.0 / direction;
+            let (mut t0, mut t1) = ((min - origin) * inverse, (max - origin) * inverse);
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slab_test_hits_and_misses() {
+        let unit = Aabb::new(
+            Vector::new(1.0, 1.0, 1.0, VectorType::Point),
+            Vector::new(-1.0, -1.0, -1.0, VectorType::Point),
+        );
+        let origin = Vector::new(0.0, 0.0, 5.0, VectorType::Point);
+
+        // Axis-aligned directions divide by zero on the other axes
+        let straight = Ray::new(origin, Vector::new(0.0, 0.0, -1.0, VectorType::Vector));
+        let beside = Ray::new(origin, Vector::new(0.5, 0.0, -1.0, VectorType::Vector));
+
+        assert!(unit.hit(&straight, 0.0, Scalar::INFINITY));
+        assert!(!unit.hit(&straight, 0.0, 3.0));
+        assert!(!unit.hit(&beside, 0.0, Scalar::INFINITY));
+    }
+
+    #[test]
+    fn padding_gives_flat_boxes_volume() {
+        let flat = Aabb::new(
+            Vector::new(-1.0, 2.0, -1.0, VectorType::Point),
+            Vector::new(1.0, 2.0, 1.0, VectorType::Point),
+        );
+
+        let padded = flat.pad(0.01);
+
+        assert_eq!(padded.min.data, (-1.0, 1.99, -1.0));
+        assert_eq!(padded.max.data, (1.0, 2.01, 1.0));
+        // A ray in the plane of the flat box would slip through it
+        let along = Ray::new(
+            Vector::new(-5.0, 2.0, 0.0, VectorType::Point),
+            Vector::new(1.0, 0.0, 0.0, VectorType::Vector),
+        );
+        let down = Ray::new(
+            Vector::new(0.0, 5.0, 0.0, VectorType::Point),
+            Vector::new(0.0, -1.0, 0.0, VectorType::Vector),
+        );
+        assert!(padded.hit(&along, 0.0, Scalar::INFINITY));
+        assert!(padded.hit(&down, 0.0, Scalar::INFINITY));
+    }
+
+    #[test]
+    fn padding_leaves_thick_boxes_alone() {
+        let cube = Aabb::new(
+            Vector::new(-1.0, -1.0, -1.0, VectorType::Point),
+            Vector::new(1.0, 1.0, 1.0, VectorType::Point),
+        );
+
+        let padded = cube.pad(0.01);
+
+        assert_eq!(padded.min.data, cube.min.data);
+        assert_eq!(padded.max.data, cube.max.data);
+    }
+
+    #[test]
+    fn surrounding_box_contains_both() {
+        let a = Aabb::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(1.0, 1.0, 1.0, VectorType::Point),
+        );
+        let b = Aabb::new(
+            Vector::new(-2.0, 0.5, 0.5, VectorType::Point),
+            Vector::new(-1.0, 3.0, 0.5, VectorType::Point),
+        );
+
+        let both = a.surrounding(&b);
+
+        assert_eq!(both.min.data, (-2.0, 0.0, 0.0));
+        assert_eq!(both.max.data, (1.0, 3.0, 1.0));
+    }
+}
@@ -0,0 +1,498 @@
+use crate::camera::Camera;
+use crate::image::Image;
+use crate::render::{render_with_options, RenderOptions};
+use crate::vector::{Scalar, Vector, VectorType};
+use crate::world::World;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &str = "RTACCUM";
+
+// A partial render: the mean radiance of `samples` samples per pixel, taken with the
+// render seed `seed`
+pub struct Accumulation {
+    pub image: Image,
+    pub samples: u32,
+    pub seed: u64,
+}
+
+// Writes a partial render so it can later be combined with renders of the same frame
+// made elsewhere. Channels are stored as little-endian `Scalar`s; the header records
+// their size so either precision can read the file. The file is written next to
+// `path` first and then moved over it, so an interrupted write leaves any previous
+// file at `path` intact.
+pub fn save_accumulation<P: AsRef<Path>>(path: P, accumulation: &Accumulation) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".tmp");
+
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    encode_accumulation(&mut writer, accumulation)?;
+    writer
+        .into_inner()
+        .map_err(|error| error.into_error())?
+        .sync_all()?;
+    fs::rename(&partial, path)
+}
+
+pub fn encode_accumulation<W: Write>(
+    writer: &mut W,
+    accumulation: &Accumulation,
+) -> io::Result<()> {
+    let image = &accumulation.image;
+    write!(
+        writer,
+        "{}\n{} {} {} {} {}\n",
+        MAGIC,
+        image.width,
+        image.height,
+        accumulation.samples,
+        std::mem::size_of::<Scalar>(),
+        accumulation.seed
+    )?;
+
+    for pixel in &image.pixels {
+        for channel in [pixel.data.0, pixel.data.1, pixel.data.2] {
+            writer.write_all(&channel.to_le_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn load_accumulation<P: AsRef<Path>>(path: P) -> io::Result<Accumulation> {
+    decode_accumulation(BufReader::new(File::open(path)?))
+}
+
+pub fn decode_accumulation<R: BufRead>(mut reader: R) -> io::Result<Accumulation> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim_end() != MAGIC {
+        return Err(invalid_data("not an accumulation file"));
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let header: Vec<u64> = line
+        .split_whitespace()
+        .map(|field| field.parse().map_err(|_| invalid_data("bad header")))
+        .collect::<io::Result<_>>()?;
+    let (width, height, samples, size, seed) = match header[..] {
+        [width, height, samples, size @ (4 | 8), seed]
+            if [width, height, samples]
+                .iter()
+                .all(|&n| n <= u32::MAX as u64) =>
+        {
+            (width as u32, height as u32, samples as u32, size, seed)
+        }
+        _ => return Err(invalid_data("bad header")),
+    };
+
+    // The header is not trusted to size anything: the pixels are read first, so the
+    // buffer only grows as far as the file really goes, and the image is made after
+    let expected = (width as u64)
+        .checked_mul(height as u64)
+        .and_then(|pixels| pixels.checked_mul(3 * size))
+        .ok_or_else(|| invalid_data("bad header"))?;
+    let mut bytes = Vec::new();
+    reader.take(expected).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != expected {
+        return Err(invalid_data("pixel data is cut short"));
+    }
+
+    let channel = |bytes: &[u8]| match *bytes {
+        [a, b, c, d] => f32::from_le_bytes([a, b, c, d]) as Scalar,
+        [a, b, c, d, e, f, g, h] => f64::from_le_bytes([a, b, c, d, e, f, g, h]) as Scalar,
+        _ => unreachable!("channels are 4 or 8 bytes"),
+    };
+    let mut image = Image::new(width, height);
+    for (pixel, bytes) in image
+        .pixels
+        .iter_mut()
+        .zip(bytes.chunks_exact(3 * size as usize))
+    {
+        let mut channels = bytes.chunks_exact(size as usize).map(channel);
+        let mut next = || channels.next().unwrap();
+        *pixel = Vector::new(next(), next(), next(), VectorType::Color);
+    }
+
+    Ok(Accumulation {
+        image,
+        samples,
+        seed,
+    })
+}
+
+// Combines partial renders of the same frame into one, weighting each by its number
+// of samples. The sample indices of the parts should not overlap (see
+// `RenderOptions::first_sample`), or the merged noise will not go down.
+pub fn merge<P: AsRef<Path>>(paths: &[P]) -> io::Result<Image> {
+    let parts = paths
+        .iter()
+        .map(|path| load_accumulation(path).map(|part| (part.image, part.samples)))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    merge_images(&parts)
+}
+
+pub fn merge_images(parts: &[(Image, u32)]) -> io::Result<Image> {
+    let (first, _) = parts
+        .first()
+        .ok_or_else(|| invalid_data("nothing to merge"))?;
+    let (width, height) = (first.width, first.height);
+    if parts
+        .iter()
+        .any(|(image, _)| (image.width, image.height) != (width, height))
+    {
+        return Err(invalid_data("partial renders differ in resolution"));
+    }
+
+    let total: u64 = parts.iter().map(|(_, samples)| *samples as u64).sum();
+    if total == 0 {
+        return Err(invalid_data("partial renders have no samples"));
+    }
+
+    let mut merged = Image::new(width, height);
+    for (image, samples) in parts {
+        let weight = *samples as Scalar / total as Scalar;
+        for (sum, pixel) in merged.pixels.iter_mut().zip(image.pixels.iter()) {
+            *sum = *sum + weight * *pixel;
+        }
+        merged.non_finite_samples += image.non_finite_samples;
+    }
+
+    Ok(merged)
+}
+
+// Renders in passes of `checkpoint_every` samples per pixel, saving the accumulation
+// to `path` after each one. When `path` already holds a checkpoint of this frame the
+// render picks up after its samples, so an interrupted render loses at most one
+// pass. The result matches an uninterrupted render with the same options; a checkpoint
+// of another resolution or seed is refused rather than mixed in.
+#[allow(clippy::too_many_arguments)]
+pub fn render_with_checkpoints<P: AsRef<Path>>(
+    path: P,
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: &Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+    checkpoint_every: u32,
+) -> io::Result<Image> {
+    let path = path.as_ref();
+    let (mut image, mut done) = if path.exists() {
+        let checkpoint = load_accumulation(path)?;
+        if (checkpoint.image.width, checkpoint.image.height) != (width, height) {
+            return Err(invalid_data("checkpoint differs in resolution"));
+        }
+        if checkpoint.seed != options.seed {
+            return Err(invalid_data("checkpoint was rendered with another seed"));
+        }
+        (checkpoint.image, checkpoint.samples)
+    } else {
+        (Image::new(width, height), 0)
+    };
+
+    while done < samples_per_pixel {
+        let pass = checkpoint_every.max(1).min(samples_per_pixel - done);
+        let part = render_with_options(
+            height,
+            width,
+            pass,
+            world,
+            camera.clone(),
+            max_depth,
+            &RenderOptions {
+                first_sample: options.first_sample + done,
+                ..*options
+            },
+        );
+
+        image = if done == 0 {
+            part
+        } else {
+            merge_images(&[(image, done), (part, pass)])?
+        };
+        done += pass;
+        let checkpoint = Accumulation {
+            image,
+            samples: done,
+            seed: options.seed,
+        };
+        save_accumulation(path, &checkpoint)?;
+        image = checkpoint.image;
+    }
+
+    Ok(image)
+}
+
+// Renders progressively, stopping at each of the sample counts in `snapshots` to hand
+// the image so far to `on_snapshot` with its count, e.g. to save a quick preview and
+// then carry on to the final image without redoing its samples. Each snapshot matches
+// a render of that many samples with the same options, and so does the result. Counts
+// of 0 or above `samples_per_pixel` are skipped.
+#[allow(clippy::too_many_arguments)]
+pub fn render_with_snapshots(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: &Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+    snapshots: &[u32],
+    mut on_snapshot: impl FnMut(u32, &Image) -> io::Result<()>,
+) -> io::Result<Image> {
+    let mut stops: Vec<u32> = snapshots
+        .iter()
+        .copied()
+        .filter(|&samples| samples > 0 && samples <= samples_per_pixel)
+        .collect();
+    stops.push(samples_per_pixel.max(1));
+    stops.sort_unstable();
+    stops.dedup();
+
+    let mut image = Image::new(width, height);
+    let mut done = 0;
+    for stop in stops {
+        let pass = stop - done;
+        let part = render_with_options(
+            height,
+            width,
+            pass,
+            world,
+            camera.clone(),
+            max_depth,
+            &RenderOptions {
+                first_sample: options.first_sample + done,
+                ..*options
+            },
+        );
+
+        image = if done == 0 {
+            part
+        } else {
+            merge_images(&[(image, done), (part, pass)])?
+        };
+        done = stop;
+        if snapshots.contains(&stop) {
+            on_snapshot(stop, &image)?;
+        }
+    }
+
+    Ok(image)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Material, Metal};
+    use crate::sphere::Sphere;
+    use crate::utils::temp_path;
+    use crate::vector::fuzzy_equal;
+
+    fn camera() -> Camera {
+        Camera::new(
+            Vector::new(0.0, 0.5, 1.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            60.0,
+            1.5,
+            0.1,
+            3.0,
+        )
+    }
+
+    fn scene() -> World {
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            0.5,
+            Material::Metal(Metal::new(
+                Vector::new(0.9, 0.6, 0.3, VectorType::Color),
+                0.3,
+            )),
+        ));
+        world.add(Sphere::new(
+            Vector::new(0.0, -100.5, -2.0, VectorType::Point),
+            100.0,
+            Material::gray(),
+        ));
+        world
+    }
+
+    fn render_part(world: &World, samples: u32, first_sample: u32) -> Image {
+        let options = RenderOptions {
+            seed: 5,
+            first_sample,
+            ..RenderOptions::default()
+        };
+
+        render_with_options(12, 18, samples, world, camera(), 8, &options)
+    }
+
+    #[test]
+    fn merged_halves_match_a_full_render() {
+        let world = scene();
+        let paths = [
+            temp_path("accumulation-0.acc"),
+            temp_path("accumulation-1.acc"),
+        ];
+
+        for (path, first_sample) in paths.iter().zip([0, 3]) {
+            let part = Accumulation {
+                image: render_part(&world, 3, first_sample),
+                samples: 3,
+                seed: 5,
+            };
+            save_accumulation(path, &part).unwrap();
+        }
+        let merged = merge(&paths).unwrap();
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+        let full = render_part(&world, 6, 0);
+
+        for (a, b) in merged.pixels.iter().zip(full.pixels.iter()) {
+            assert!(fuzzy_equal(a.data.0, b.data.0));
+            assert!(fuzzy_equal(a.data.1, b.data.1));
+            assert!(fuzzy_equal(a.data.2, b.data.2));
+        }
+        // Without the offset the second half repeats the first one's samples
+        assert_ne!(
+            render_part(&world, 3, 0).to_rgb8(),
+            render_part(&world, 3, 3).to_rgb8()
+        );
+    }
+
+    #[test]
+    fn resumed_render_matches_an_uninterrupted_one() {
+        let world = scene();
+        let path = temp_path("checkpoint.acc");
+        let _ = std::fs::remove_file(&path);
+        let options = RenderOptions {
+            seed: 5,
+            ..RenderOptions::default()
+        };
+        let checkpointed = |samples: u32| {
+            render_with_checkpoints(&path, 12, 18, samples, &world, &camera(), 8, &options, 2)
+        };
+
+        // Stopped after 4 of the 7 samples, then resumed
+        checkpointed(4).unwrap();
+        assert_eq!(load_accumulation(&path).unwrap().samples, 4);
+        let resumed = checkpointed(7).unwrap();
+        let checkpoint = load_accumulation(&path).unwrap();
+        assert_eq!((checkpoint.samples, checkpoint.seed), (7, 5));
+        // Written beside the checkpoint and moved over it, with nothing left behind
+        assert!(!path.with_extension("acc.tmp").exists());
+        std::fs::remove_file(&path).unwrap();
+        let full = render_part(&world, 7, 0);
+
+        for (a, b) in resumed.pixels.iter().zip(full.pixels.iter()) {
+            assert!(fuzzy_equal(a.data.0, b.data.0));
+            assert!(fuzzy_equal(a.data.1, b.data.1));
+            assert!(fuzzy_equal(a.data.2, b.data.2));
+        }
+    }
+
+    #[test]
+    fn snapshots_match_standalone_renders() {
+        let world = scene();
+        let options = RenderOptions {
+            seed: 5,
+            ..RenderOptions::default()
+        };
+
+        let mut snapshots = vec![];
+        let last = render_with_snapshots(
+            12,
+            18,
+            7,
+            &world,
+            &camera(),
+            8,
+            &options,
+            &[2, 20, 5],
+            |samples, image| {
+                snapshots.push((samples, image.pixels.clone()));
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        let counts: Vec<u32> = snapshots.iter().map(|(samples, _)| *samples).collect();
+        assert_eq!(counts, vec![2, 5]);
+        // The preview is exactly the first pass
+        let preview = render_part(&world, 2, 0);
+        for (a, b) in snapshots[0].1.iter().zip(preview.pixels.iter()) {
+            assert_eq!(a.data, b.data);
+        }
+        for (pixels, samples) in [(&snapshots[1].1, 5), (&last.pixels, 7)] {
+            let standalone = render_part(&world, samples, 0);
+            for (a, b) in pixels.iter().zip(standalone.pixels.iter()) {
+                assert!(fuzzy_equal(a.data.0, b.data.0));
+                assert!(fuzzy_equal(a.data.1, b.data.1));
+                assert!(fuzzy_equal(a.data.2, b.data.2));
+            }
+        }
+    }
+
+    #[test]
+    fn checkpoints_of_another_frame_are_refused() {
+        let world = scene();
+        let path = temp_path("foreign-checkpoint.acc");
+        let options = RenderOptions {
+            seed: 5,
+            ..RenderOptions::default()
+        };
+        let resume = |height: u32, width: u32, seed: u64| {
+            let options = RenderOptions { seed, ..options };
+            render_with_checkpoints(&path, height, width, 3, &world, &camera(), 8, &options, 2)
+        };
+
+        save_accumulation(
+            &path,
+            &Accumulation {
+                image: render_part(&world, 2, 0),
+                samples: 2,
+                seed: 5,
+            },
+        )
+        .unwrap();
+        let other_seed = resume(12, 18, 6);
+        let other_size = resume(18, 12, 5);
+        let untouched = load_accumulation(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for refused in [other_seed, other_size] {
+            assert!(matches!(refused, Err(e) if e.kind() == io::ErrorKind::InvalidData));
+        }
+        assert_eq!(untouched.samples, 2);
+    }
+
+    #[test]
+    fn oversized_headers_are_refused_before_allocating() {
+        let decode =
+            |header: &str| decode_accumulation(format!("{}\n{}\n", MAGIC, header).as_bytes());
+
+        // Claims 240 GB of pixels but holds none, then overflows `u64` outright
+        for header in ["100000 100000 1 8 0", "4294967295 4294967295 1 8 0"] {
+            assert!(matches!(decode(header), Err(e) if e.kind() == io::ErrorKind::InvalidData));
+        }
+    }
+
+    #[test]
+    fn mismatched_resolutions_do_not_merge() {
+        let parts = [(Image::new(4, 2), 1), (Image::new(2, 4), 1)];
+
+        assert!(merge_images(&parts).is_err());
+    }
+}
@@ -0,0 +1,513 @@
+use crate::camera::Camera;
+use crate::image::Image;
+use crate::render::{path_tracer, PixelSampler, RenderOptions};
+use crate::vector::{Scalar, Vector, VectorType};
+use crate::world::World;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// When `render_adaptive` stops sampling a pixel: after at least `min_samples`, as
+// soon as the standard error of its mean luminance drops below `tolerance` times the
+// mean, and in any case after `max_samples`
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSettings {
+    pub min_samples: u32,
+    pub max_samples: u32,
+    pub tolerance: Scalar,
+}
+
+impl Default for AdaptiveSettings {
+    fn default() -> Self {
+        Self {
+            min_samples: 16,
+            max_samples: 1024,
+            tolerance: 0.01,
+        }
+    }
+}
+
+// An image whose pixels took different numbers of samples, adaptively or by region,
+// and the number each one ended up with, in the same order as `image.pixels`
+pub struct AdaptiveImage {
+    pub image: Image,
+    pub samples: Vec<u32>,
+}
+
+// Spends samples where the image is still noisy: flat regions such as the sky stop
+// early while edges and lit surfaces keep going. `options.first_sample` is respected
+// as in `render_with_options`.
+pub fn render_adaptive(
+    height: u32,
+    width: u32,
+    settings: &AdaptiveSettings,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+) -> AdaptiveImage {
+    let shade = path_tracer(world, max_depth, options);
+    let sampler = PixelSampler {
+        world: Some(world),
+        camera: &camera,
+        width,
+        height,
+        samples_per_pixel: settings.max_samples,
+        options,
+        shade: &shade,
+        discarded: AtomicU64::new(0),
+    };
+    let mut image = Image::new(width, height);
+    let mut samples = vec![0; width as usize * height as usize];
+
+    #[allow(clippy::type_complexity)]
+    let bands: Vec<(usize, (&mut [Vector], &mut [u32]))> = image
+        .pixels
+        .chunks_mut(width as usize)
+        .zip(samples.chunks_mut(width as usize))
+        .rev()
+        .enumerate()
+        .collect();
+
+    bands.into_par_iter().for_each(|(y, (band, counts))| {
+        for (x, (pixel, count)) in band.iter_mut().zip(counts.iter_mut()).enumerate() {
+            (*pixel, *count) = adaptive_pixel(&sampler, settings, x as u32, y as u32);
+        }
+    });
+    image.non_finite_samples = sampler.discarded.load(Ordering::Relaxed);
+
+    AdaptiveImage { image, samples }
+}
+
+// `render_adaptive`, returning a heatmap of the samples each pixel took instead of
+// the image itself. See `sample_heatmap`.
+pub fn render_sample_heatmap(
+    height: u32,
+    width: u32,
+    settings: &AdaptiveSettings,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+) -> Image {
+    sample_heatmap(&render_adaptive(
+        height, width, settings, world, camera, max_depth, options,
+    ))
+}
+
+// Colors every pixel by how many samples it took, from blue for the fewest in the
+// image through green to red for the most
+pub fn sample_heatmap(render: &AdaptiveImage) -> Image {
+    let fewest = render.samples.iter().copied().min().unwrap_or(0);
+    let most = render.samples.iter().copied().max().unwrap_or(0);
+    let range = (most - fewest).max(1) as Scalar;
+
+    let mut heatmap = Image::new(render.image.width, render.image.height);
+    for (pixel, &count) in heatmap.pixels.iter_mut().zip(render.samples.iter()) {
+        *pixel = heat_color((count - fewest) as Scalar / range);
+    }
+
+    heatmap
+}
+
+// Blue, cyan, green, yellow, red for `t` from 0 to 1
+fn heat_color(t: Scalar) -> Vector {
+    let ramp = |offset: Scalar| (2.0 - (4.0 * t - offset).abs()).clamp(0.0, 1.0);
+
+    Vector::new(ramp(4.0), ramp(2.0), ramp(0.0), VectorType::Color)
+}
+
+// A rectangle of the image, in pixels from the top left corner like `Image::get`,
+// that gets `factor` times the samples of the rest, e.g. around a glass object that
+// is still noisy when everything else is clean
+#[derive(Debug, Clone, Copy)]
+pub struct SampleBoost {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub factor: u32,
+}
+
+impl SampleBoost {
+    fn contains(&self, x: u32, y: u32) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+// Renders `samples_per_pixel` samples per pixel, and `boost.factor` times as many
+// inside `boost`. Every pixel is the mean of its own samples and the extra samples
+// continue the same sequence, so the region blends into the rest of the frame: its
+// pixels match a render with the boosted count everywhere.
+#[allow(clippy::too_many_arguments)]
+pub fn render_with_boost(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    boost: &SampleBoost,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+) -> AdaptiveImage {
+    let shade = path_tracer(world, max_depth, options);
+    let sampler = PixelSampler {
+        world: Some(world),
+        camera: &camera,
+        width,
+        height,
+        samples_per_pixel,
+        options,
+        shade: &shade,
+        discarded: AtomicU64::new(0),
+    };
+    let mut image = Image::new(width, height);
+    let mut samples = vec![0; width as usize * height as usize];
+
+    #[allow(clippy::type_complexity)]
+    let rows: Vec<(usize, (&mut [Vector], &mut [u32]))> = image
+        .pixels
+        .chunks_mut(width as usize)
+        .zip(samples.chunks_mut(width as usize))
+        .enumerate()
+        .collect();
+
+    rows.into_par_iter().for_each(|(row, (band, counts))| {
+        // The sampler counts rows up from the bottom
+        let y = height - 1 - row as u32;
+        for (x, (pixel, count)) in band.iter_mut().zip(counts.iter_mut()).enumerate() {
+            *count = if boost.contains(x as u32, row as u32) {
+                samples_per_pixel * boost.factor.max(1)
+            } else {
+                samples_per_pixel
+            };
+            *pixel = sampler.pixel_with(x as u32, y, *count);
+        }
+    });
+    image.non_finite_samples = sampler.discarded.load(Ordering::Relaxed);
+
+    AdaptiveImage { image, samples }
+}
+
+fn adaptive_pixel(
+    sampler: &PixelSampler,
+    settings: &AdaptiveSettings,
+    x: u32,
+    y: u32,
+) -> (Vector, u32) {
+    let first = sampler.options.first_sample;
+    let mut sum = Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+    let mut luminance = Welford::default();
+    let mut taken = 0;
+
+    // Non-finite samples are left out as in `PixelSampler::pixel_with`, but still
+    // count towards `max_samples` so a pixel that only produces them stops
+    while taken < settings.max_samples.max(1) {
+        let color = sampler.sample(x, y, first + taken);
+        taken += 1;
+        if !color.is_finite() {
+            sampler.discard(1);
+            continue;
+        }
+        sum = sum + color;
+        luminance.push(color.luminance());
+
+        if luminance.count() >= settings.min_samples.max(2)
+            && luminance.standard_error() <= settings.tolerance * luminance.mean()
+        {
+            break;
+        }
+    }
+
+    if luminance.count() == 0 {
+        return (sum, taken);
+    }
+
+    (sum / luminance.count() as Scalar, taken)
+}
+
+// Renders `samples_per_pixel` samples per pixel like `render_with_options`, but
+// returns the variance of each pixel's sample luminances as a grayscale image
+// instead of their mean: black where every sample agrees, bright where the noise is.
+pub fn render_variance_aov(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+) -> Image {
+    let shade = path_tracer(world, max_depth, options);
+    let sampler = PixelSampler {
+        world: Some(world),
+        camera: &camera,
+        width,
+        height,
+        samples_per_pixel,
+        options,
+        shade: &shade,
+        discarded: AtomicU64::new(0),
+    };
+    let mut image = Image::new(width, height);
+
+    let bands: Vec<(usize, &mut [Vector])> = image
+        .pixels
+        .chunks_mut(width as usize)
+        .rev()
+        .enumerate()
+        .collect();
+
+    bands.into_par_iter().for_each(|(y, band)| {
+        for (x, pixel) in band.iter_mut().enumerate() {
+            let first = options.first_sample;
+            let mut luminance = Welford::default();
+            for s in first..first + samples_per_pixel {
+                let color = sampler.sample(x as u32, y as u32, s);
+                if color.is_finite() {
+                    luminance.push(color.luminance());
+                } else {
+                    sampler.discard(1);
+                }
+            }
+
+            let variance = luminance.variance();
+            *pixel = Vector::new(variance, variance, variance, VectorType::Color);
+        }
+    });
+    image.non_finite_samples = sampler.discarded.load(Ordering::Relaxed);
+
+    image
+}
+
+// Running mean and sum of squared deviations of a stream of values (Welford), which
+// stays accurate where summing squares would cancel
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Welford {
+    n: u32,
+    mean: Scalar,
+    m2: Scalar,
+}
+
+impl Welford {
+    pub(crate) fn push(&mut self, value: Scalar) {
+        self.n += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.n as Scalar;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub(crate) fn count(&self) -> u32 {
+        self.n
+    }
+
+    pub(crate) fn mean(&self) -> Scalar {
+        self.mean
+    }
+
+    // Unbiased sample variance, 0 until there are two values
+    pub(crate) fn variance(&self) -> Scalar {
+        if self.n < 2 {
+            0.0
+        } else {
+            self.m2 / (self.n - 1) as Scalar
+        }
+    }
+
+    // Standard error of the mean
+    pub(crate) fn standard_error(&self) -> Scalar {
+        (self.variance() / self.n as Scalar).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::vector::fuzzy_equal;
+    use rand::rngs::StdRng;
+    use rand::Rng;
+
+    fn gray_sphere_world() -> World {
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -3.0, VectorType::Point),
+            1.0,
+            Material::gray(),
+        ));
+        world
+    }
+
+    fn camera() -> Camera {
+        Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            60.0,
+            1.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    #[test]
+    fn noisy_pixels_take_more_samples() {
+        let settings = AdaptiveSettings {
+            min_samples: 8,
+            max_samples: 256,
+            tolerance: 0.02,
+        };
+
+        let render = render_adaptive(
+            16,
+            16,
+            &settings,
+            &gray_sphere_world(),
+            camera(),
+            8,
+            &RenderOptions::default(),
+        );
+        let heatmap = sample_heatmap(&render);
+
+        // The sphere fills the middle of the frame, only sky shows in the corners
+        let average = |xs: std::ops::Range<usize>, ys: std::ops::Range<usize>| {
+            let count = (xs.len() * ys.len()) as Scalar;
+            let total: u32 = ys
+                .flat_map(|y| xs.clone().map(move |x| y * 16 + x))
+                .map(|i| render.samples[i])
+                .sum();
+            total as Scalar / count
+        };
+        assert!(average(5..11, 5..11) > 1.5 * average(0..4, 0..4));
+        // Spectral sampling leaves a trace of color noise in the sky, so the odd sky
+        // pixel takes a sample or two more
+        assert!(average(0..4, 0..4) < settings.min_samples as Scalar + 1.0);
+
+        // The sky is cold, the busiest pixels hot
+        let coldest = render
+            .samples
+            .iter()
+            .position(|&n| n == settings.min_samples)
+            .unwrap();
+        let hottest = render.samples.iter().position(|&n| n == 256).unwrap();
+        assert_eq!(heatmap.pixels[coldest].data, (0.0, 0.0, 1.0));
+        assert_eq!(heatmap.pixels[hottest].data, (1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn non_finite_samples_are_left_out_of_adaptive_pixels() {
+        let camera = camera();
+        let color = Vector::new(0.25, 0.5, 0.75, VectorType::Color);
+        let nan = Vector::new(Scalar::NAN, 0.0, 0.0, VectorType::Color);
+        // Roughly one sample in five is poisoned
+        let shade = |_: &Ray, rng: &mut StdRng| {
+            if rng.gen::<Scalar>() < 0.2 {
+                nan
+            } else {
+                color
+            }
+        };
+        let options = RenderOptions::default();
+        let sampler = PixelSampler {
+            world: None,
+            camera: &camera,
+            width: 4,
+            height: 4,
+            samples_per_pixel: 32,
+            options: &options,
+            shade: &shade,
+            discarded: AtomicU64::new(0),
+        };
+        let settings = AdaptiveSettings {
+            min_samples: 8,
+            max_samples: 32,
+            tolerance: 0.02,
+        };
+
+        for (x, y) in [(0, 0), (1, 2), (3, 3)] {
+            let (pixel, samples) = adaptive_pixel(&sampler, &settings, x, y);
+            assert_eq!(pixel, color);
+            assert!(samples <= settings.max_samples);
+        }
+        assert!(sampler.discarded.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn boosted_region_matches_a_render_with_more_samples() {
+        use crate::render::render_with_options;
+
+        let world = gray_sphere_world();
+        let options = RenderOptions::default();
+        let boost = SampleBoost {
+            x: 5,
+            y: 3,
+            width: 6,
+            height: 4,
+            factor: 4,
+        };
+
+        let boosted = render_with_boost(12, 14, 2, &boost, &world, camera(), 8, &options);
+        let base = render_with_options(12, 14, 2, &world, camera(), 8, &options);
+        let full = render_with_options(12, 14, 8, &world, camera(), 8, &options);
+
+        for y in 0..12 {
+            for x in 0..14 {
+                let i = (y * 14 + x) as usize;
+                let (expected, count) = if boost.contains(x, y) {
+                    (full.get(x, y), 8)
+                } else {
+                    (base.get(x, y), 2)
+                };
+
+                assert_eq!(boosted.samples[i], count);
+                assert_eq!(boosted.image.get(x, y).data, expected.data);
+            }
+        }
+    }
+
+    #[test]
+    fn welford_matches_the_two_pass_variance() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        let mut stats = Welford::default();
+        for value in values {
+            stats.push(value);
+        }
+
+        assert!(fuzzy_equal(stats.mean(), 5.0));
+        assert!(fuzzy_equal(stats.variance(), 32.0 / 7.0));
+        assert!(fuzzy_equal(
+            stats.standard_error(),
+            (32.0 / 7.0 / 8.0 as Scalar).sqrt()
+        ));
+    }
+
+    #[test]
+    fn variance_is_low_in_the_sky_and_high_on_edges() {
+        let variance = render_variance_aov(
+            16,
+            16,
+            32,
+            &gray_sphere_world(),
+            camera(),
+            8,
+            &RenderOptions::default(),
+        );
+
+        // Rows top first: the top left corner only sees sky, which barely changes
+        // across a pixel (short of the spectral renderer's color noise), while the
+        // sphere's rim mixes sky and shadowed surface
+        let corner = (0..4)
+            .flat_map(|y| (0..4).map(move |x| (x, y)))
+            .map(|(x, y)| variance.get(x, y).data.0)
+            .fold(0.0, Scalar::max);
+        let noisiest = variance
+            .pixels
+            .iter()
+            .map(|p| p.data.0)
+            .fold(0.0, Scalar::max);
+
+        assert!(corner < 0.01, "{}", corner);
+        assert!(noisiest > 10.0 * corner, "{} {}", noisiest, corner);
+    }
+}
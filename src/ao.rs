@@ -0,0 +1,189 @@
+use crate::camera::Camera;
+use crate::image::Image;
+use crate::ray::Ray;
+use crate::render::{render_with_options, Integrator, RenderOptions};
+use crate::vector::{Scalar, Vector};
+use crate::world::World;
+use rand::RngCore;
+
+// Fraction of cosine-weighted hemisphere rays around `normal` that travel `radius`
+// without hitting anything: 1 is fully open, 0 fully enclosed. The rays are traced
+// at `time`, that of the ray that found `point`, so moving objects occlude from
+// where they were then.
+pub fn ambient_occlusion(
+    world: &World,
+    point: &Vector,
+    normal: &Vector,
+    samples: u32,
+    radius: Scalar,
+    time: Scalar,
+) -> Scalar {
+    ambient_occlusion_with(
+        world,
+        point,
+        normal,
+        samples,
+        radius,
+        time,
+        &mut rand::thread_rng(),
+    )
+}
+
+// `ambient_occlusion` drawing its rays from `rng`
+pub fn ambient_occlusion_with(
+    world: &World,
+    point: &Vector,
+    normal: &Vector,
+    samples: u32,
+    radius: Scalar,
+    time: Scalar,
+    rng: &mut dyn RngCore,
+) -> Scalar {
+    let mut unoccluded = 0;
+
+    for _ in 0..samples {
+        let mut direction = *normal + Vector::random_in_unit_sphere_with(rng).get_unit_vector();
+        if direction.near_zero() {
+            direction = *normal;
+        }
+
+        let ray = Ray::new(*point, direction.get_unit_vector()).with_time(time);
+        if world.hit(&ray, world.t_min(&ray), radius).is_none() {
+            unoccluded += 1;
+        }
+    }
+
+    unoccluded as Scalar / samples as Scalar
+}
+
+// Renders a grayscale ambient occlusion pass: no materials or lights, just how open
+// the geometry is at the first hit. Misses are white.
+pub fn render_ao(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    ao_samples: u32,
+    radius: Scalar,
+    world: &World,
+    camera: &Camera,
+) -> Image {
+    // The ambient occlusion integrator, seeded per sample like every render
+    let options = RenderOptions {
+        integrator: Integrator::AmbientOcclusion {
+            samples: ao_samples,
+            radius,
+        },
+        ..RenderOptions::default()
+    };
+
+    render_with_options(
+        height,
+        width,
+        samples_per_pixel,
+        world,
+        camera.clone(),
+        1,
+        &options,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::quad::Quad;
+    use crate::sphere::Sphere;
+    use crate::vector::VectorType;
+
+    #[test]
+    fn top_of_isolated_sphere_is_open() {
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            1.0,
+            Material::gray(),
+        ));
+
+        let top = Vector::new(0.0, 1.0, 0.0, VectorType::Point);
+        let up = Vector::new(0.0, 1.0, 0.0, VectorType::Vector);
+
+        assert!(ambient_occlusion(&world, &top, &up, 256, 10.0, 0.0) > 0.99);
+    }
+
+    #[test]
+    fn floor_next_to_wall_is_darker() {
+        let mut world = World::new();
+        // Floor on y = 0 and a wall on x = 0, both 20 units across
+        world.add(Quad::new(
+            Vector::new(-10.0, 0.0, -10.0, VectorType::Point),
+            Vector::new(0.0, 0.0, 20.0, VectorType::Vector),
+            Vector::new(20.0, 0.0, 0.0, VectorType::Vector),
+            Material::gray(),
+        ));
+        world.add(Quad::new(
+            Vector::new(0.0, 0.0, -10.0, VectorType::Point),
+            Vector::new(0.0, 10.0, 0.0, VectorType::Vector),
+            Vector::new(0.0, 0.0, 20.0, VectorType::Vector),
+            Material::gray(),
+        ));
+
+        let up = Vector::new(0.0, 1.0, 0.0, VectorType::Vector);
+        let open = Vector::new(8.0, 0.0, 0.0, VectorType::Point);
+        let corner = Vector::new(0.01, 0.0, 0.0, VectorType::Point);
+
+        let open_ao = ambient_occlusion(&world, &open, &up, 512, 1.0, 0.0);
+        let corner_ao = ambient_occlusion(&world, &corner, &up, 512, 1.0, 0.0);
+
+        assert!(open_ao > 0.99);
+        assert!(corner_ao < 0.75);
+    }
+
+    #[test]
+    fn occluders_are_where_they_were_at_the_time() {
+        use crate::matrix::Matrix4;
+        use crate::transform::MovingTransform;
+
+        // A roof over the origin at the end of the exposure, far off at its start
+        let mut world = World::new();
+        world.add(MovingTransform::new(
+            Quad::new(
+                Vector::new(-5.0, 0.5, -5.0, VectorType::Point),
+                Vector::new(0.0, 0.0, 10.0, VectorType::Vector),
+                Vector::new(10.0, 0.0, 0.0, VectorType::Vector),
+                Material::gray(),
+            ),
+            Matrix4::translation(100.0, 0.0, 0.0),
+            Matrix4::identity(),
+            0.0,
+            1.0,
+        ));
+
+        let origin = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+        let up = Vector::new(0.0, 1.0, 0.0, VectorType::Vector);
+
+        assert!(ambient_occlusion(&world, &origin, &up, 256, 1.0, 0.0) > 0.99);
+        assert!(ambient_occlusion(&world, &origin, &up, 256, 1.0, 1.0) < 0.5);
+    }
+
+    #[test]
+    fn render_ao_covers_image() {
+        let world = World::new();
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+
+        let image = render_ao(4, 6, 1, 4, 1.0, &world, &camera);
+
+        assert_eq!(image.pixels.len(), 24);
+        assert!(image
+            .pixels
+            .iter()
+            .all(|p| *p == Vector::new(1.0, 1.0, 1.0, VectorType::Color)));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::log;
+use crate::png;
+use crate::postprocess::PostProcess;
+use crate::render::render;
+use crate::scene::{load_scene, SceneError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub struct BatchResult {
+    pub scene: PathBuf,
+    pub output: PathBuf,
+    pub outcome: Result<Timings, SceneError>,
+}
+
+// Where the time for one scene went, to compare the cost of setting a scene up with
+// the cost of tracing it
+#[derive(Debug, Clone, Copy)]
+pub struct Timings {
+    // Loading the scene and building the world, including any acceleration structures
+    pub build: Duration,
+    // Tracing the image
+    pub render: Duration,
+}
+
+// Renders every `*.json` scene in `dir` to a PNG with the same name in `out_dir`.
+// Scenes are rendered one after another, each one in parallel. A scene that fails
+// is reported and skipped without stopping the rest of the batch.
+pub fn render_batch<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir: P,
+    out_dir: Q,
+) -> io::Result<Vec<BatchResult>> {
+    let out_dir = out_dir.as_ref();
+    std::fs::create_dir_all(out_dir)?;
+
+    let mut scenes: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    scenes.sort();
+
+    let mut results = vec![];
+
+    for scene in scenes {
+        let output = out_dir
+            .join(scene.file_stem().unwrap_or_default())
+            .with_extension("png");
+
+        let outcome = render_scene(&scene, &output);
+        match &outcome {
+            Ok(timings) => log::info(format_args!(
+                "{}: built in {:.2?}, rendered in {:.2?}",
+                scene.display(),
+                timings.build,
+                timings.render
+            )),
+            Err(error) => log::error(format_args!("{}: {}", scene.display(), error)),
+        }
+
+        results.push(BatchResult {
+            scene,
+            output,
+            outcome,
+        });
+    }
+
+    Ok(results)
+}
+
+fn render_scene(scene: &Path, output: &Path) -> Result<Timings, SceneError> {
+    let start = Instant::now();
+    let description = load_scene(scene)?;
+    let build = start.elapsed();
+
+    let start = Instant::now();
+    let image = render(
+        description.height,
+        description.width,
+        description.samples_per_pixel,
+        &description.world,
+        description.camera,
+        description.max_depth,
+    );
+    let render = start.elapsed();
+    png::write_png(output, &image, &PostProcess::default())?;
+
+    Ok(Timings { build, render })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_every_scene_in_directory() {
+        let dir = crate::utils::temp_path("batch-test");
+        let scenes = dir.join("scenes");
+        let out = dir.join("out");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&scenes).unwrap();
+
+        let scene = r#"{
+            "width": 8, "height": 6, "samples_per_pixel": 1, "max_depth": 4,
+            "camera": { "look_from": [0, 0, 1], "look_at": [0, 0, 0] },
+            "objects": [
+                { "type": "sphere", "center": [0, 0, -1], "radius": 0.5,
+                  "material": { "type": "lambertian", "albedo": [0.5, 0.2, 0.2] } }
+            ]
+        }"#;
+        std::fs::write(scenes.join("first.json"), scene).unwrap();
+        std::fs::write(scenes.join("second.json"), scene).unwrap();
+        std::fs::write(scenes.join("broken.json"), "{ not json").unwrap();
+        std::fs::write(scenes.join("notes.txt"), "ignored").unwrap();
+
+        let results = render_batch(&scenes, &out).unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].outcome.is_err());
+        for result in &results[1..] {
+            let timings = result.outcome.as_ref().unwrap();
+            assert!(timings.build > Duration::ZERO);
+            assert!(timings.render > Duration::ZERO);
+        }
+        assert!(out.join("first.png").exists());
+        assert!(out.join("second.png").exists());
+        assert!(!out.join("broken.png").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
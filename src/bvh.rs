@@ -0,0 +1,478 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hittable};
+use crate::json::JsonValue;
+use crate::ray::Ray;
+use crate::validate::Problem;
+use crate::vector::{Scalar, Vector};
+
+enum Node {
+    // Index into `Bvh::objects`
+    Leaf(usize),
+    Branch {
+        bounds: Aabb,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+}
+
+impl Node {
+    // Splits `indices` at the median centroid along the axis where the centroids
+    // spread the most. `boxes` holds every object's bounding box, computed once up
+    // front, since `bounding_box` can be expensive and sorting asks for it O(n log n)
+    // times per level.
+    fn build(boxes: &[Option<Aabb>], indices: &mut [usize]) -> (Node, Aabb) {
+        let bounds = |i: usize| boxes[i].expect("only bounded objects are in the tree");
+        if let [index] = indices {
+            return (Node::Leaf(*index), bounds(*index));
+        }
+
+        let centroids: Vec<Vector> = indices
+            .iter()
+            .map(|&i| (bounds(i).min + bounds(i).max) / 2.0)
+            .collect();
+        let split_axis = (0..3)
+            .max_by(|&a, &b| extent(&centroids, a).total_cmp(&extent(&centroids, b)))
+            .unwrap();
+
+        indices.sort_by(|&a, &b| {
+            let centroid = |i: usize| axis((bounds(i).min + bounds(i).max) / 2.0, split_axis);
+            centroid(a).total_cmp(&centroid(b))
+        });
+        let (left, right) = indices.split_at_mut(indices.len() / 2);
+
+        let (left, left_bounds) = Node::build(boxes, left);
+        let (right, right_bounds) = Node::build(boxes, right);
+        let bounds = left_bounds.surrounding(&right_bounds);
+
+        (
+            Node::Branch {
+                bounds,
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            bounds,
+        )
+    }
+
+    fn hit<'a>(
+        &self,
+        objects: &'a [Box<dyn Hittable>],
+        r: &Ray,
+        t_min: Scalar,
+        t_max: Scalar,
+    ) -> Option<HitRecord<'a>> {
+        match self {
+            Node::Leaf(index) => objects[*index].hit(r, t_min, t_max),
+            Node::Branch {
+                bounds,
+                left,
+                right,
+            } => {
+                if !bounds.hit(r, t_min, t_max) {
+                    return None;
+                }
+
+                let left_hit = left.hit(objects, r, t_min, t_max);
+                let closest = left_hit.as_ref().map_or(t_max, |hit| hit.t);
+
+                right.hit(objects, r, t_min, closest).or(left_hit)
+            }
+        }
+    }
+
+    // Appends this node and its subtree to `nodes` in pre-order, returning its index
+    fn dump(&self, objects: &[Box<dyn Hittable>], nodes: &mut Vec<JsonValue>) -> usize {
+        let index = nodes.len();
+        nodes.push(JsonValue::Null);
+
+        let (bounds, members) = match self {
+            Node::Leaf(primitive) => {
+                let bounds = objects[*primitive]
+                    .bounding_box()
+                    .expect("only bounded objects are in the tree");
+                (
+                    bounds,
+                    vec![(
+                        "primitives".to_string(),
+                        JsonValue::Array(vec![JsonValue::Number(*primitive as f64)]),
+                    )],
+                )
+            }
+            Node::Branch {
+                bounds,
+                left,
+                right,
+            } => {
+                let left = left.dump(objects, nodes);
+                let right = right.dump(objects, nodes);
+                (
+                    *bounds,
+                    vec![(
+                        "children".to_string(),
+                        JsonValue::Array(vec![
+                            JsonValue::Number(left as f64),
+                            JsonValue::Number(right as f64),
+                        ]),
+                    )],
+                )
+            }
+        };
+
+        let mut node = vec![
+            ("min".to_string(), point(&bounds.min)),
+            ("max".to_string(), point(&bounds.max)),
+        ];
+        node.extend(members);
+        nodes[index] = JsonValue::Object(node);
+        index
+    }
+
+    #[cfg(test)]
+    fn leaves(&self, order: &mut Vec<usize>) {
+        match self {
+            Node::Leaf(index) => order.push(*index),
+            Node::Branch { left, right, .. } => {
+                left.leaves(order);
+                right.leaves(order);
+            }
+        }
+    }
+}
+
+fn axis(v: Vector, axis: usize) -> Scalar {
+    match axis {
+        0 => v.data.0,
+        1 => v.data.1,
+        _ => v.data.2,
+    }
+}
+
+// The cast is only needed with the `f32` feature
+#[allow(clippy::unnecessary_cast)]
+fn point(p: &Vector) -> JsonValue {
+    JsonValue::Array(
+        [p.data.0, p.data.1, p.data.2]
+            .iter()
+            .map(|&c| JsonValue::Number(c as f64))
+            .collect(),
+    )
+}
+
+fn extent(points: &[Vector], a: usize) -> Scalar {
+    let values = points.iter().map(|&p| axis(p, a));
+    let min = values.clone().fold(Scalar::INFINITY, Scalar::min);
+    let max = values.fold(Scalar::NEG_INFINITY, Scalar::max);
+    max - min
+}
+
+// A bounding volume hierarchy of axis-aligned boxes over any primitives. Objects
+// without a bounding box, such as infinite planes, are tested on every ray instead.
+pub struct Bvh {
+    objects: Vec<Box<dyn Hittable>>,
+    root: Option<Node>,
+    bounds: Option<Aabb>,
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Box<dyn Hittable>>) -> Self {
+        let boxes: Vec<Option<Aabb>> = objects.iter().map(|o| o.bounding_box()).collect();
+        let (mut bounded, unbounded): (Vec<usize>, Vec<usize>) =
+            (0..objects.len()).partition(|&i| boxes[i].is_some());
+
+        let (root, bounds) = if bounded.is_empty() {
+            (None, None)
+        } else {
+            let (root, bounds) = Node::build(&boxes, &mut bounded);
+            (Some(root), Some(bounds))
+        };
+
+        Self {
+            objects,
+            root,
+            bounds,
+            unbounded,
+        }
+    }
+
+    // The tree as JSON, for inspecting splits and balance. `nodes` lists every node
+    // in pre-order starting at the root, each with its box as `min` and `max` and
+    // either the indices of its two `children` in `nodes` or, for leaves, the
+    // `primitives` it holds as indices into the objects the tree was built from.
+    // `unbounded` lists the objects kept outside the tree.
+    pub fn dump(&self) -> String {
+        let mut nodes = Vec::new();
+        if let Some(root) = &self.root {
+            root.dump(&self.objects, &mut nodes);
+        }
+        let unbounded = self
+            .unbounded
+            .iter()
+            .map(|&index| JsonValue::Number(index as f64))
+            .collect();
+
+        JsonValue::Object(vec![
+            ("nodes".to_string(), JsonValue::Array(nodes)),
+            ("unbounded".to_string(), JsonValue::Array(unbounded)),
+        ])
+        .to_string()
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        let mut closest = self
+            .root
+            .as_ref()
+            .and_then(|root| root.hit(&self.objects, r, t_min, t_max));
+
+        for &index in &self.unbounded {
+            let t_max = closest.as_ref().map_or(t_max, |hit| hit.t);
+            if let Some(hit) = self.objects[index].hit(r, t_min, t_max) {
+                closest = Some(hit);
+            }
+        }
+
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.unbounded.is_empty() {
+            self.bounds
+        } else {
+            None
+        }
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        self.objects.iter().flat_map(|o| o.problems()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::material::Material;
+    use crate::sphere::Sphere;
+    use crate::vector::VectorType;
+    use crate::world::World;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Counts the calls to `bounding_box` of the sphere it wraps
+    struct Counted {
+        sphere: Sphere,
+        calls: &'static AtomicUsize,
+    }
+
+    impl Hittable for Counted {
+        fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+            self.sphere.hit(r, t_min, t_max)
+        }
+
+        fn bounding_box(&self) -> Option<Aabb> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            self.sphere.bounding_box()
+        }
+    }
+
+    fn cluster(seed: u64) -> Vec<Sphere> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..60)
+            .map(|_| {
+                Sphere::new(
+                    Vector::new(
+                        rng.gen_range(-3.0..3.0),
+                        rng.gen_range(-3.0..3.0),
+                        rng.gen_range(-3.0..3.0),
+                        VectorType::Point,
+                    ),
+                    rng.gen_range(0.05..0.6),
+                    Material::gray(),
+                )
+            })
+            .collect()
+    }
+
+    fn boxed(spheres: Vec<Sphere>) -> Vec<Box<dyn Hittable>> {
+        spheres
+            .into_iter()
+            .map(|s| Box::new(s) as Box<dyn Hittable>)
+            .collect()
+    }
+
+    // The leaf order of the straightforward build, asking every object for its box
+    // inside the comparator
+    fn naive_leaves(objects: &[Box<dyn Hittable>], mut indices: Vec<usize>) -> Vec<usize> {
+        if indices.len() == 1 {
+            return indices;
+        }
+
+        let centroid = |i: usize| {
+            let bounds = objects[i].bounding_box().unwrap();
+            (bounds.min + bounds.max) / 2.0
+        };
+        let centroids: Vec<Vector> = indices.iter().map(|&i| centroid(i)).collect();
+        let split_axis = (0..3)
+            .max_by(|&a, &b| extent(&centroids, a).total_cmp(&extent(&centroids, b)))
+            .unwrap();
+
+        indices.sort_by(|&a, &b| {
+            axis(centroid(a), split_axis).total_cmp(&axis(centroid(b), split_axis))
+        });
+        let right = indices.split_off(indices.len() / 2);
+
+        let mut order = naive_leaves(objects, indices);
+        order.extend(naive_leaves(objects, right));
+        order
+    }
+
+    #[test]
+    fn matches_the_naively_sorted_tree() {
+        let bvh = Bvh::new(boxed(cluster(3)));
+
+        let mut leaves = Vec::new();
+        bvh.root.as_ref().unwrap().leaves(&mut leaves);
+
+        assert_eq!(leaves, naive_leaves(&bvh.objects, (0..60).collect()));
+    }
+
+    #[test]
+    fn asks_each_object_for_its_box_once() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let objects = cluster(5)
+            .into_iter()
+            .map(|sphere| {
+                Box::new(Counted {
+                    sphere,
+                    calls: &CALLS,
+                }) as Box<dyn Hittable>
+            })
+            .collect();
+
+        Bvh::new(objects);
+
+        assert_eq!(CALLS.load(Ordering::Relaxed), 60);
+    }
+
+    #[test]
+    fn dump_lists_every_node_with_its_box() {
+        let spheres: Vec<Sphere> = [(-4.0, 0.5), (-1.0, 1.0), (2.0, 0.25), (5.0, 2.0)]
+            .iter()
+            .map(|&(x, radius)| {
+                Sphere::new(
+                    Vector::new(x, 1.0, -2.0, VectorType::Point),
+                    radius,
+                    Material::gray(),
+                )
+            })
+            .collect();
+        let bvh = Bvh::new(boxed(spheres));
+
+        let dump = crate::json::parse(&bvh.dump()).unwrap();
+        let nodes = dump.get("nodes").unwrap().as_array().unwrap();
+        let number = |v: &JsonValue| v.as_f64().unwrap() as Scalar;
+        let corner = |node: &JsonValue, key: &str| {
+            let c = node.get(key).unwrap().as_array().unwrap();
+            Vector::new(
+                number(&c[0]),
+                number(&c[1]),
+                number(&c[2]),
+                VectorType::Point,
+            )
+        };
+
+        // Four leaves joined by three branches
+        assert_eq!(nodes.len(), 7);
+        let leaves: Vec<&JsonValue> = nodes
+            .iter()
+            .filter(|n| n.get("primitives").is_some())
+            .collect();
+        assert_eq!(leaves.len(), 4);
+
+        for leaf in leaves {
+            let primitives = leaf.get("primitives").unwrap().as_array().unwrap();
+            assert_eq!(primitives.len(), 1);
+            let expected = bvh.objects[number(&primitives[0]) as usize]
+                .bounding_box()
+                .unwrap();
+
+            assert_eq!(corner(leaf, "min"), expected.min);
+            assert_eq!(corner(leaf, "max"), expected.max);
+        }
+
+        // The root encloses everything
+        assert_eq!(corner(&nodes[0], "min").data.0, -4.5);
+        assert_eq!(corner(&nodes[0], "max").data.0, 7.0);
+        assert!(dump
+            .get("unbounded")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn matches_naive_closest_hit() {
+        let bvh = Bvh::new(boxed(cluster(7)));
+        let world = World::from(cluster(7));
+        let mut rng = StdRng::seed_from_u64(9);
+
+        for _ in 0..500 {
+            let origin = Vector::new(
+                rng.gen_range(-8.0..8.0),
+                rng.gen_range(-8.0..8.0),
+                rng.gen_range(-8.0..8.0),
+                VectorType::Point,
+            );
+            let target = Vector::new(
+                rng.gen_range(-2.0..2.0),
+                rng.gen_range(-2.0..2.0),
+                rng.gen_range(-2.0..2.0),
+                VectorType::Point,
+            );
+            let ray = Ray::new(origin, target - origin);
+
+            let expected = world.hit(&ray, 0.001, Scalar::INFINITY).map(|hit| hit.t);
+            let actual = bvh.hit(&ray, 0.001, Scalar::INFINITY).map(|hit| hit.t);
+
+            match (expected, actual) {
+                (Some(e), Some(a)) => assert!((e - a).abs() < 1e-6),
+                (None, None) => {}
+                _ => panic!("bvh and naive loop disagree: {:?} {:?}", expected, actual),
+            }
+        }
+    }
+
+    #[test]
+    fn renders_random_scene_like_the_naive_loop() {
+        use crate::render::{render_with_options, RenderOptions};
+        use crate::scene::random_scene;
+
+        let camera = Camera::new(
+            Vector::new(13.0, 2.0, 3.0, VectorType::Point),
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            20.0,
+            1.5,
+            0.1,
+            10.0,
+        );
+        let options = RenderOptions {
+            seed: 7,
+            ..RenderOptions::default()
+        };
+        let render =
+            |world: &World| render_with_options(20, 30, 2, world, camera.clone(), 10, &options);
+        let mut bvh = World::new();
+        bvh.add(Bvh::new(boxed(random_scene(7))));
+
+        // Same closest hits and the same random numbers, so the very same image
+        let (naive, accelerated) = (render(&World::from(random_scene(7))), render(&bvh));
+        for (a, b) in naive.pixels.iter().zip(&accelerated.pixels) {
+            assert_eq!(a.data, b.data);
+        }
+    }
+}
@@ -1,14 +1,112 @@
+use crate::config::CameraConfig;
+use crate::perlin::Perlin;
 use crate::ray::Ray;
+use crate::texture::{ImageTexture, Texture};
 use crate::utils;
-use crate::vector::Vector;
-use crate::vector::VectorType;
+use crate::vector::{Scalar, Vector, VectorType};
+use rand::Rng;
+use std::sync::Arc;
 
+// How far open the shutter is over the exposure, which decides how the moments the
+// rays of a frame see are spread over it. Times run from 0 as the shutter starts to
+// open to 1 as it has closed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ShutterProfile {
+    // Fully open the whole time, so every moment counts the same
+    #[default]
+    Box,
+    // Opens steadily until halfway, then closes the same way, which softens both
+    // ends of motion trails
+    Triangle,
+    // Any other shutter, given by the fraction of the exposure gathered by each of
+    // a set of evenly spaced times: rising from 0 at time 0 to 1 at time 1
+    Cdf(Arc<[Scalar]>),
+}
+
+impl ShutterProfile {
+    // The time at which a fraction `u` of the exposure has been gathered. Feeding it
+    // uniform `u` in [0, 1) draws times distributed like the shutter.
+    pub fn time(&self, u: Scalar) -> Scalar {
+        match self {
+            ShutterProfile::Box => u,
+            ShutterProfile::Triangle => {
+                if u < 0.5 {
+                    (0.5 * u).sqrt()
+                } else {
+                    1.0 - (0.5 * (1.0 - u)).sqrt()
+                }
+            }
+            ShutterProfile::Cdf(cdf) => {
+                let segments = cdf.len().saturating_sub(1);
+                if segments == 0 {
+                    return u;
+                }
+
+                // The segment the fraction falls in, then linearly along it
+                let i = cdf.partition_point(|&c| c <= u).clamp(1, segments) - 1;
+                let width = cdf[i + 1] - cdf[i];
+                let along = if width > 0.0 {
+                    ((u - cdf[i]) / width).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+
+                (i as Scalar + along) / segments as Scalar
+            }
+        }
+    }
+}
+
+// Lens samples `ApertureMask` draws before giving up on a mostly dark mask
+const MASK_ATTEMPTS: usize = 64;
+
+// The shape of the lens opening as a grayscale image spanning the aperture, for
+// bokeh shaped like the bright part of the image: a heart, a star and so on. Lens
+// points are kept in proportion to the mask's luminance there.
+#[derive(Clone)]
+pub struct ApertureMask(pub Arc<ImageTexture>);
+
+impl ApertureMask {
+    pub fn new(mask: ImageTexture) -> Self {
+        Self(Arc::new(mask))
+    }
+
+    // A point in the square from (-1, -1) to (1, 1) covering the mask. Should every
+    // draw land in the dark, the lens center is used.
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Vector {
+        for _ in 0..MASK_ATTEMPTS {
+            let (x, y): (Scalar, Scalar) = (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+            let p = Vector::new(x, y, 0.0, VectorType::Point);
+            let opening = self
+                .0
+                .value(0.5 * (x + 1.0), 0.5 * (y + 1.0), &p)
+                .luminance();
+
+            if rng.gen::<Scalar>() < opening {
+                return p;
+            }
+        }
+
+        Vector::new(0.0, 0.0, 0.0, VectorType::Point)
+    }
+}
+
+#[derive(Clone)]
 pub struct Camera {
     origin: Vector,
     lower_left_corner: Vector,
     horizontal: Vector,
     vertical: Vector,
-    lens_radius: f64,
+    lens_radius: Scalar,
+    focus_distance: Scalar,
+    // How much wider than the lens highlights brighter than white are spread, 0 for
+    // the plain thin lens
+    bloom: Scalar,
+    // Strength of the per-channel lens offset, 0 for a perfect lens
+    aberration: Scalar,
+    shutter: ShutterProfile,
+    // The shape of the lens opening, a disc when not set
+    aperture_mask: Option<ApertureMask>,
     u: Vector,
     v: Vector,
     w: Vector,
@@ -19,10 +117,10 @@ impl Camera {
         look_from: Vector,
         look_at: Vector,
         vup: Vector,
-        vfov: f64,
-        aspect_ratio: f64,
-        aperture: f64,
-        focus_distance: f64,
+        vfov: Scalar,
+        aspect_ratio: Scalar,
+        aperture: Scalar,
+        focus_distance: Scalar,
     ) -> Self {
         let theta = utils::degrees_to_radians(vfov);
         let h = (theta / 2.0).tan();
@@ -49,16 +147,535 @@ impl Camera {
             v,
             u,
             lens_radius,
+            focus_distance,
+            bloom: 0.0,
+            aberration: 0.0,
+            shutter: ShutterProfile::Box,
+            aperture_mask: None,
+        }
+    }
+
+    // Rolls the camera by `roll` degrees around its view axis, tilting the horizon.
+    // Positive angles rotate the image counter-clockwise as seen through the camera.
+    pub fn with_roll(mut self, roll: Scalar) -> Self {
+        let theta = utils::degrees_to_radians(roll);
+        let (sin_theta, cos_theta) = theta.sin_cos();
+
+        let center = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+
+        let u = self.u * cos_theta + self.w.cross(&self.u) * sin_theta;
+        let v = self.w.cross(&u);
+
+        self.horizontal = self.horizontal.len() * u;
+        self.vertical = self.vertical.len() * v;
+        self.lower_left_corner = center - self.horizontal / 2.0 - self.vertical / 2.0;
+        self.u = u;
+        self.v = v;
+
+        self
+    }
+
+    // Lateral chromatic aberration: the red image is magnified by `1 + aberration` and
+    // the blue one by `1 - aberration` about the center of the frame, and their rays
+    // leave the lens displaced radially to match. Edges away from the center fringe
+    // purple and green, even in focus. 0 disables it.
+    pub fn with_aberration(mut self, aberration: Scalar) -> Self {
+        self.aberration = aberration;
+        self
+    }
+
+    pub fn aberration(&self) -> Scalar {
+        self.aberration
+    }
+
+    // Highlight bloom: the part of a sample brighter than white is partly gathered
+    // through a lens `bloom` times wider again, so out of focus highlights spread into
+    // larger, softer discs. The thin lens geometry already grows the blur with the
+    // distance from the focus plane, this only exaggerates it for bright spots.
+    pub fn with_bloom(mut self, bloom: Scalar) -> Self {
+        self.bloom = bloom;
+        self
+    }
+
+    pub fn bloom(&self) -> Scalar {
+        self.bloom
+    }
+
+    // Spreads the moments sampled over the exposure like `shutter`, see `shutter_time`
+    pub fn with_shutter(mut self, shutter: ShutterProfile) -> Self {
+        self.shutter = shutter;
+        self
+    }
+
+    pub fn shutter(&self) -> &ShutterProfile {
+        &self.shutter
+    }
+
+    // Shapes the lens opening, and so the out of focus highlights, like `mask`. The
+    // mask spans a square as wide as the aperture.
+    pub fn with_aperture_mask(mut self, mask: ApertureMask) -> Self {
+        self.aperture_mask = Some(mask);
+        self
+    }
+
+    // A point on the lens, scaled to the unit disc or the square around it
+    fn lens_point<R: Rng + ?Sized>(&self, rng: &mut R) -> Vector {
+        match &self.aperture_mask {
+            Some(mask) => mask.sample(rng),
+            None => utils::random_in_unit_disk_with(rng),
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * utils::random_in_unit_disk();
+    // A moment during the exposure, from 0 as the shutter opens to 1 as it closes,
+    // drawn in proportion to how far open the shutter is then
+    pub fn shutter_time<R: Rng + ?Sized>(&self, rng: &mut R) -> Scalar {
+        self.shutter.time(rng.gen())
+    }
+
+    // Radius of the disc a point `distance` in front of the camera is blurred into,
+    // measured on the focus plane in the same units as the viewport. 0 in focus.
+    pub fn defocus_radius(&self, distance: Scalar) -> Scalar {
+        self.lens_radius * (distance - self.focus_distance).abs() / distance
+    }
+
+    // Left and right eye cameras `ipd` apart along the camera's horizontal axis,
+    // centered on this one. The views stay parallel, so both share the focus plane.
+    pub fn stereo_pair(&self, ipd: Scalar) -> (Self, Self) {
+        let eye = |offset: Scalar| {
+            let shift = offset * ipd * self.u;
+            Self {
+                origin: self.origin + shift,
+                lower_left_corner: self.lower_left_corner + shift,
+                ..self.clone()
+            }
+        };
+
+        (eye(-0.5), eye(0.5))
+    }
+
+    // A ray through the viewport point (`s`, `t`), drawing the lens sample and the
+    // moment from the thread's RNG. For one-off rays; renders use
+    // `get_ray_differential` with their seeded RNG so they can be reproduced.
+    pub fn get_ray(&self, s: Scalar, t: Scalar) -> Ray {
+        let mut rng = rand::thread_rng();
+        let rd = self.lens_radius * self.lens_point(&mut rng);
+        let offset = self.u * rd.data.0 + self.v * rd.data.1;
+
+        Ray::new(self.origin + offset, self.direction(s, t, offset))
+            .with_time(self.shutter_time(&mut rng))
+    }
+
+    // The ray through the center of pixel (`x`, `y`) of a `width` by `height` frame,
+    // counting up from the bottom left like the renderer. It leaves from the center of
+    // the lens, so it is the same every time.
+    pub fn ray_through_pixel(&self, x: u32, y: u32, width: u32, height: u32) -> Ray {
+        let s = (x as Scalar + 0.5) / (width as Scalar - 1.0);
+        let t = (y as Scalar + 0.5) / (height as Scalar - 1.0);
+        let center = Vector::new(0.0, 0.0, 0.0, VectorType::Vector);
+
+        Ray::new(self.origin, self.direction(s, t, center))
+    }
+
+    // Like `get_ray`, but also carries the directions through the points `ds` and `dt`
+    // further along the viewport, normally one pixel over. The lens sample and the
+    // moment the ray is traced at are drawn from `rng`.
+    pub fn get_ray_differential<R: Rng + ?Sized>(
+        &self,
+        s: Scalar,
+        t: Scalar,
+        ds: Scalar,
+        dt: Scalar,
+        rng: &mut R,
+    ) -> Ray {
+        let rd = self.lens_radius * self.lens_point(rng);
         let offset = self.u * rd.data.0 + self.v * rd.data.1;
 
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+        self.ray_from_lens(s, t, ds, dt, offset)
+            .with_time(self.shutter_time(rng))
+    }
+
+    // `get_ray_differential` through a lens widened by the bloom, see `with_bloom`
+    pub fn get_bloom_ray_differential<R: Rng + ?Sized>(
+        &self,
+        s: Scalar,
+        t: Scalar,
+        ds: Scalar,
+        dt: Scalar,
+        rng: &mut R,
+    ) -> Ray {
+        let rd = (1.0 + self.bloom) * self.lens_radius * self.lens_point(rng);
+        let offset = self.u * rd.data.0 + self.v * rd.data.1;
+
+        self.ray_from_lens(s, t, ds, dt, offset)
+            .with_time(self.shutter_time(rng))
+    }
+
+    // One differential ray per color channel, red, green and blue, sharing a lens
+    // sample and a moment. Without aberration all three are the same ray.
+    pub fn get_channel_rays<R: Rng + ?Sized>(
+        &self,
+        s: Scalar,
+        t: Scalar,
+        ds: Scalar,
+        dt: Scalar,
+        rng: &mut R,
+    ) -> [Ray; 3] {
+        let rd = self.lens_radius * self.lens_point(rng);
+        let offset = self.u * rd.data.0 + self.v * rd.data.1;
+        let shift = self.aberration * (self.u * (s - 0.5) + self.v * (t - 0.5));
+        let time = self.shutter_time(rng);
+        // Where on the focus plane a channel magnified by `1 + k` sees pixel (s, t)
+        let magnified = |k: Scalar| (0.5 + (s - 0.5) / (1.0 + k), 0.5 + (t - 0.5) / (1.0 + k));
+        let (red, blue) = (magnified(self.aberration), magnified(-self.aberration));
+
+        [
+            self.ray_from_lens(red.0, red.1, ds, dt, offset - shift)
+                .with_time(time),
+            self.ray_from_lens(s, t, ds, dt, offset).with_time(time),
+            self.ray_from_lens(blue.0, blue.1, ds, dt, offset + shift)
+                .with_time(time),
+        ]
+    }
+
+    fn ray_from_lens(&self, s: Scalar, t: Scalar, ds: Scalar, dt: Scalar, offset: Vector) -> Ray {
+        Ray::new(self.origin + offset, self.direction(s, t, offset)).with_differentials(
+            self.direction(s + ds, t, offset),
+            self.direction(s, t + dt, offset),
         )
     }
+
+    fn direction(&self, s: Scalar, t: Scalar, offset: Vector) -> Vector {
+        self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset
+    }
+}
+
+impl CameraConfig {
+    // The same shot as if the camera were held by hand: `look_from` and `look_at`
+    // drift by up to about `amplitude` as smooth noise over the time `t` (in frames,
+    // or any unit where one step is a noticeable wobble). The same `seed` repeats
+    // the same shake, and at whole values of `t` the camera is back where it started.
+    pub fn handheld(&self, t: Scalar, amplitude: Scalar, seed: u64) -> Self {
+        let perlin = Perlin::new(seed);
+        // Each coordinate follows its own track through the noise, along a lattice
+        // line where the noise is zero at every whole `t`
+        let shake = |track: Scalar| {
+            let noise =
+                |axis: Scalar| perlin.noise(&Vector::new(t, track + axis, 0.0, VectorType::Point));
+            amplitude * Vector::new(noise(0.0), noise(10.0), noise(20.0), VectorType::Vector)
+        };
+
+        Self {
+            look_from: self.look_from + shake(0.0),
+            look_at: self.look_at + shake(100.0),
+            ..*self
+        }
+    }
+}
+
+// `base` shaken as by `CameraConfig::handheld`, ready to render
+pub fn handheld_camera(base: &CameraConfig, t: Scalar, amplitude: Scalar, seed: u64) -> Camera {
+    base.handheld(t, amplitude, seed).camera()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::fuzzy_equal;
+    use rand::SeedableRng;
+
+    fn camera_config() -> CameraConfig {
+        CameraConfig {
+            look_from: Vector::new(13.0, 2.0, 3.0, VectorType::Point),
+            look_at: Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            vup: Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            vfov: 20.0,
+            aspect_ratio: 1.5,
+            aperture: 0.0,
+            focus_distance: 10.0,
+        }
+    }
+
+    #[test]
+    fn still_hands_leave_the_camera_alone() {
+        let base = camera_config();
+        let centre = |camera: &Camera| -> Ray { camera.get_ray(0.5, 0.5) };
+
+        let shaken = handheld_camera(&base, 0.0, 0.0, 3);
+        let expected = centre(&base.camera());
+        let ray = centre(&shaken);
+
+        assert_eq!(ray.origin, expected.origin);
+        assert_eq!(ray.direction, expected.direction);
+    }
+
+    #[test]
+    fn shake_returns_to_rest_at_whole_times() {
+        let base = camera_config();
+        let offset = |t: Scalar| {
+            let shaken = base.handheld(t, 0.5, 3);
+            (shaken.look_from - base.look_from).len() + (shaken.look_at - base.look_at).len()
+        };
+
+        for t in [0.0, 1.0, 2.0, 7.0, 250.0] {
+            assert_eq!(offset(t), 0.0, "t = {}", t);
+        }
+        assert!(offset(0.4) > 0.0);
+        assert!(offset(6.5) > 0.0);
+    }
+
+    #[test]
+    fn more_amplitude_shakes_further() {
+        let base = camera_config();
+        let offset = |amplitude: Scalar| {
+            let shaken = base.handheld(2.37, amplitude, 3);
+            (shaken.look_from - base.look_from).len() + (shaken.look_at - base.look_at).len()
+        };
+
+        assert!(offset(0.05) > 0.0);
+        assert!(offset(0.2) > offset(0.05));
+        assert!(offset(1.0) > offset(0.2));
+    }
+
+    fn camera() -> Camera {
+        Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    #[test]
+    fn triangle_shutter_favors_the_middle_of_the_exposure() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let middle_share = |camera: &Camera, rng: &mut rand::rngs::StdRng| {
+            let samples = 20000;
+            let middle = (0..samples)
+                .map(|_| camera.shutter_time(rng))
+                .filter(|t| (0.25..0.75).contains(t))
+                .count();
+            middle as Scalar / samples as Scalar
+        };
+
+        let uniform = middle_share(&camera(), &mut rng);
+        let triangle = middle_share(&camera().with_shutter(ShutterProfile::Triangle), &mut rng);
+
+        // Half the exposure lies in the middle half for a box, three quarters for a
+        // triangle
+        assert!((uniform - 0.5).abs() < 0.02);
+        assert!((triangle - 0.75).abs() < 0.02);
+    }
+
+    #[test]
+    fn lens_points_follow_the_aperture_mask() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        // Left column open, right column a quarter open, bottom right corner shut
+        let mask = ImageTexture::linear(
+            2,
+            2,
+            vec![255, 255, 255, 64, 64, 64, 255, 255, 255, 0, 0, 0],
+        );
+        let camera = camera().with_aperture_mask(ApertureMask::new(mask));
+
+        let points: Vec<Vector> = (0..20000).map(|_| camera.lens_point(&mut rng)).collect();
+        let count = |inside: fn(Scalar, Scalar) -> bool| {
+            points.iter().filter(|p| inside(p.data.0, p.data.1)).count() as Scalar
+        };
+
+        let open = count(|x, y| x < 0.0 && y > 0.0);
+        let dim = count(|x, y| x > 0.0 && y > 0.0);
+        assert!(points
+            .iter()
+            .all(|p| p.data.0.abs() <= 1.0 && p.data.1.abs() <= 1.0));
+        assert!((open / dim - 255.0 / 64.0).abs() < 0.4, "{}", open / dim);
+        assert_eq!(count(|x, y| x > 0.0 && y < 0.0), 0.0);
+    }
+
+    #[test]
+    fn cdf_shutter_inverts_the_table() {
+        // Gathers the whole exposure in its first half
+        let front = ShutterProfile::Cdf(Arc::from(vec![0.0, 1.0, 1.0]));
+        assert!(fuzzy_equal(front.time(0.0), 0.0));
+        assert!(fuzzy_equal(front.time(0.5), 0.25));
+        assert!(fuzzy_equal(front.time(0.999), 0.4995));
+
+        // A straight line is the box shutter
+        let linear = ShutterProfile::Cdf(Arc::from(vec![0.0, 0.25, 0.5, 0.75, 1.0]));
+        for u in [0.0, 0.1, 0.5, 0.8, 0.99] {
+            assert!(fuzzy_equal(linear.time(u), ShutterProfile::Box.time(u)));
+        }
+    }
+
+    #[test]
+    fn zero_roll_keeps_basis() {
+        let original = camera();
+        let rolled = camera().with_roll(0.0);
+
+        assert_eq!(rolled.horizontal, original.horizontal);
+        assert_eq!(rolled.vertical, original.vertical);
+        assert_eq!(rolled.lower_left_corner, original.lower_left_corner);
+    }
+
+    #[test]
+    fn quarter_roll_swaps_horizontal_and_vertical() {
+        let original = camera();
+        let rolled = camera().with_roll(90.0);
+
+        let horizontal = rolled.horizontal.get_unit_vector();
+        let vertical = rolled.vertical.get_unit_vector();
+
+        assert!(fuzzy_equal(
+            horizontal.dot(&original.vertical.get_unit_vector()),
+            1.0
+        ));
+        assert!(fuzzy_equal(
+            vertical.dot(&original.horizontal.get_unit_vector()),
+            -1.0
+        ));
+
+        // The center of the view is unaffected by the roll
+        let center = rolled.get_ray(0.5, 0.5).direction.get_unit_vector();
+        assert!(fuzzy_equal(center.data.2, -1.0));
+    }
+
+    #[test]
+    fn stereo_eyes_are_ipd_apart_along_u() {
+        let original = camera();
+        let (left, right) = original.stereo_pair(0.064);
+
+        let offset = right.origin - left.origin;
+        assert!(fuzzy_equal(offset.dot(&original.u), 0.064));
+        assert!(fuzzy_equal(offset.len(), 0.064));
+        // Centered on the original eye, looking the same way
+        assert_eq!((left.origin + right.origin) / 2.0, original.origin);
+        assert_eq!(
+            left.get_ray(0.5, 0.5).direction,
+            original.get_ray(0.5, 0.5).direction
+        );
+    }
+
+    #[test]
+    fn center_pixel_ray_looks_along_minus_w() {
+        let camera = camera();
+
+        // Pixel 50 of 102 sits exactly in the middle with (x + 0.5) / (width - 1)
+        let r = camera.ray_through_pixel(50, 50, 102, 102);
+
+        assert_eq!(r.origin, camera.origin);
+        assert!(fuzzy_equal(
+            r.direction.get_unit_vector().dot(&-camera.w),
+            1.0
+        ));
+
+        // Deterministic even with a lens
+        let lens = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            90.0,
+            1.0,
+            0.5,
+            1.0,
+        );
+        let first = lens.ray_through_pixel(3, 7, 20, 10);
+        let second = lens.ray_through_pixel(3, 7, 20, 10);
+        assert_eq!(first.origin, second.origin);
+        assert_eq!(first.direction, second.direction);
+    }
+
+    #[test]
+    fn defocus_radius_matches_the_lens_geometry() {
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            90.0,
+            1.0,
+            0.2,
+            2.0,
+        );
+        let mut rng = rand::thread_rng();
+        assert!(fuzzy_equal(camera.defocus_radius(2.0), 0.0));
+
+        // Spread of the rays through the center of the frame where they cross z = -8,
+        // scaled back onto the focus plane
+        let distance = 8.0;
+        let widest = (0..500)
+            .map(|_| {
+                let r = camera.get_ray_differential(0.5, 0.5, 0.01, 0.01, &mut rng);
+                let p = r.at(distance / -r.direction.data.2);
+                (p.data.0 * p.data.0 + p.data.1 * p.data.1).sqrt() * 2.0 / distance
+            })
+            .fold(0.0 as Scalar, Scalar::max);
+
+        let expected = camera.defocus_radius(distance);
+        assert!(widest <= expected + 1e-6);
+        assert!(widest > 0.9 * expected);
+    }
+
+    #[test]
+    fn aberration_separates_channels_at_the_edge() {
+        let mut rng = rand::thread_rng();
+
+        let [red, green, blue] = camera().get_channel_rays(0.0, 0.0, 0.01, 0.01, &mut rng);
+        assert_eq!(red.origin, blue.origin);
+        assert_eq!(red.origin, green.origin);
+
+        let aberrated = camera().with_aberration(0.02);
+        let [red, green, blue] = aberrated.get_channel_rays(0.0, 0.0, 0.01, 0.01, &mut rng);
+        assert!((red.origin - blue.origin).len() > 0.01);
+        // Green is undisturbed, halfway between the two
+        assert!(((red.origin + blue.origin) / 2.0 - green.origin).len() < 1e-9);
+
+        // The center of the frame has no lateral aberration
+        let [red, _, blue] = aberrated.get_channel_rays(0.5, 0.5, 0.01, 0.01, &mut rng);
+        assert!((red.origin - blue.origin).len() < 1e-9);
+        assert!((red.direction - blue.direction).len() < 1e-9);
+    }
+
+    #[test]
+    fn aberration_fringes_an_edge_in_focus() {
+        use crate::material::{Lambertian, Material};
+        use crate::quad::Quad;
+        use crate::render::{render_albedo, RenderOptions};
+        use crate::utils::SkyGradient;
+        use crate::world::World;
+
+        let black = Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+        let white = Vector::new(1.0, 1.0, 1.0, VectorType::Color);
+        // A white wall on the focus plane over the right quarter of the view, against
+        // a black sky. With a pinhole lens everything is in focus.
+        let mut world = World::new().with_sky(SkyGradient::new(black, black));
+        world.add(Quad::new(
+            Vector::new(0.5, -2.0, -1.0, VectorType::Point),
+            Vector::new(2.0, 0.0, 0.0, VectorType::Vector),
+            Vector::new(0.0, 4.0, 0.0, VectorType::Vector),
+            Material::Lambertian(Lambertian::new(white)),
+        ));
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let fringe = |camera: &Camera| {
+            let image = render_albedo(4, 32, 4, &world, camera, &RenderOptions::default());
+            (0..32)
+                .map(|x| {
+                    let (red, _, blue) = image.get(x, 2).data;
+                    (blue - red).abs()
+                })
+                .fold(0.0, Scalar::max)
+        };
+
+        assert_eq!(fringe(&camera), 0.0);
+        // Blue, magnified less, reaches the edge of the wall a pixel before red
+        assert!(fringe(&camera.clone().with_aberration(0.1)) > 0.5);
+    }
 }
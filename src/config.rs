@@ -0,0 +1,176 @@
+use crate::camera::Camera;
+use crate::json::JsonValue;
+use crate::postprocess::{Clamp, Gamma, PostProcess, ToneMap};
+use crate::vector::{Scalar, Vector};
+
+// Where the camera is and how it is set up, as passed to `Camera::new`
+#[derive(Debug, Clone, Copy)]
+pub struct CameraConfig {
+    pub look_from: Vector,
+    pub look_at: Vector,
+    pub vup: Vector,
+    pub vfov: Scalar,
+    pub aspect_ratio: Scalar,
+    pub aperture: Scalar,
+    pub focus_distance: Scalar,
+}
+
+impl CameraConfig {
+    pub fn camera(&self) -> Camera {
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.vup,
+            self.vfov,
+            self.aspect_ratio,
+            self.aperture,
+            self.focus_distance,
+        )
+    }
+}
+
+// Everything that decided how a render came out, gathered in one place so it can be
+// recorded next to the image and the render repeated later
+#[derive(Debug, Clone, Copy)]
+pub struct RenderConfig {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u64,
+    pub seed: u64,
+    pub camera: CameraConfig,
+    pub post: PostProcess,
+    pub threads: usize,
+}
+
+impl RenderConfig {
+    pub fn to_json(&self) -> JsonValue {
+        let camera = &self.camera;
+        let tone_map = match self.post.tone_map {
+            ToneMap::None => "none",
+            ToneMap::Reinhard => "reinhard",
+        };
+        let gamma = match self.post.gamma {
+            Gamma::None => "none",
+            Gamma::Approximate => "2.0",
+            Gamma::Srgb => "srgb",
+        };
+        let clamp = match self.post.clamp {
+            Clamp::None => "none",
+            Clamp::Channels => "channels",
+            Clamp::Desaturate => "desaturate",
+        };
+
+        object(vec![
+            ("width", number(self.width as f64)),
+            ("height", number(self.height as f64)),
+            ("samples_per_pixel", number(self.samples_per_pixel as f64)),
+            ("max_depth", number(self.max_depth as f64)),
+            // Seeds can exceed what a JSON number holds exactly
+            ("seed", JsonValue::String(self.seed.to_string())),
+            (
+                "camera",
+                object(vec![
+                    ("look_from", vector(&camera.look_from)),
+                    ("look_at", vector(&camera.look_at)),
+                    ("vup", vector(&camera.vup)),
+                    ("vfov", scalar(camera.vfov)),
+                    ("aspect_ratio", scalar(camera.aspect_ratio)),
+                    ("aperture", scalar(camera.aperture)),
+                    ("focus_distance", scalar(camera.focus_distance)),
+                ]),
+            ),
+            ("tone_map", JsonValue::String(tone_map.to_string())),
+            ("gamma", JsonValue::String(gamma.to_string())),
+            ("clamp", JsonValue::String(clamp.to_string())),
+            ("threads", number(self.threads as f64)),
+        ])
+    }
+}
+
+fn object(members: Vec<(&str, JsonValue)>) -> JsonValue {
+    JsonValue::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn number(value: f64) -> JsonValue {
+    JsonValue::Number(value)
+}
+
+// The cast is only needed with the `f32` feature
+#[allow(clippy::unnecessary_cast)]
+fn scalar(value: Scalar) -> JsonValue {
+    number(value as f64)
+}
+
+fn vector(v: &Vector) -> JsonValue {
+    JsonValue::Array(vec![scalar(v.data.0), scalar(v.data.1), scalar(v.data.2)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::vector::VectorType;
+
+    #[test]
+    fn serializes_every_field() {
+        let config = RenderConfig {
+            width: 300,
+            height: 200,
+            samples_per_pixel: 64,
+            max_depth: 12,
+            seed: u64::MAX,
+            camera: CameraConfig {
+                look_from: Vector::new(13.0, 2.0, 3.0, VectorType::Point),
+                look_at: Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+                vup: Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+                vfov: 20.0,
+                aspect_ratio: 1.5,
+                aperture: 0.1,
+                focus_distance: 10.0,
+            },
+            post: PostProcess {
+                tone_map: ToneMap::Reinhard,
+                gamma: Gamma::Srgb,
+                clamp: Clamp::Desaturate,
+            },
+            threads: 6,
+        };
+
+        // Round trips through the text form
+        let parsed = json::parse(&config.to_json().to_string()).unwrap();
+        let field = |key: &str| parsed.get(key).unwrap();
+        let camera = field("camera");
+        let camera_field = |key: &str| camera.get(key).unwrap().as_f64().unwrap();
+
+        assert_eq!(field("width").as_f64(), Some(300.0));
+        assert_eq!(field("height").as_f64(), Some(200.0));
+        assert_eq!(field("samples_per_pixel").as_f64(), Some(64.0));
+        assert_eq!(field("max_depth").as_f64(), Some(12.0));
+        assert_eq!(field("seed").as_str(), Some("18446744073709551615"));
+        assert_eq!(field("tone_map").as_str(), Some("reinhard"));
+        assert_eq!(field("gamma").as_str(), Some("srgb"));
+        assert_eq!(field("clamp").as_str(), Some("desaturate"));
+        assert_eq!(field("threads").as_f64(), Some(6.0));
+
+        let look_from: Vec<f64> = camera
+            .get("look_from")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c.as_f64().unwrap())
+            .collect();
+        assert_eq!(look_from, vec![13.0, 2.0, 3.0]);
+        assert!(camera.get("look_at").is_some() && camera.get("vup").is_some());
+        assert_eq!(camera_field("vfov"), 20.0);
+        assert_eq!(camera_field("aspect_ratio"), 1.5);
+        assert!((camera_field("aperture") - 0.1).abs() < 1e-6);
+        assert_eq!(camera_field("focus_distance"), 10.0);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::vector::{consts::PI, Scalar, Vector, VectorType};
+
+// Piecewise constant distribution over [0, 1) with one bucket per entry of `func`
+struct Distribution1D {
+    func: Vec<Scalar>,
+    // cdf[i] is the probability of landing before bucket i; cdf[len] is 1
+    cdf: Vec<Scalar>,
+    integral: Scalar,
+}
+
+impl Distribution1D {
+    fn new(func: Vec<Scalar>) -> Self {
+        let n = func.len() as Scalar;
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for value in &func {
+            cdf.push(cdf[cdf.len() - 1] + value / n);
+        }
+
+        let integral = cdf[func.len()];
+        if integral > 0.0 {
+            for c in cdf.iter_mut() {
+                *c /= integral;
+            }
+        } else {
+            // Nothing to prefer, fall back to uniform
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as Scalar / n;
+            }
+        }
+
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    // Maps a uniform `u` to a point in [0, 1), returning it with its density and the
+    // bucket it fell in
+    fn sample(&self, u: Scalar) -> (Scalar, Scalar, usize) {
+        let index = (self.cdf.partition_point(|&c| c <= u) - 1).min(self.func.len() - 1);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0.0 {
+            (u - self.cdf[index]) / width
+        } else {
+            0.0
+        };
+
+        let x = (index as Scalar + offset) / self.func.len() as Scalar;
+        (x, self.pdf(index), index)
+    }
+
+    fn pdf(&self, index: usize) -> Scalar {
+        if self.integral > 0.0 {
+            self.func[index] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+// Radiance arriving from every direction, stored as an equirectangular (latitude-
+// longitude) image with row 0 straight up, e.g. one loaded with `hdr::read_hdr`.
+// Directions can be importance sampled in proportion to luminance, so bright spots
+// like the sun are found without brute force.
+pub struct EnvironmentMap {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vector>,
+    // Distribution over rows, then within each row over columns
+    marginal: Distribution1D,
+    conditional: Vec<Distribution1D>,
+}
+
+impl EnvironmentMap {
+    pub fn new(pixels: Vec<Vector>, width: u32, height: u32) -> Self {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+
+        // Rows near the poles cover less solid angle than the equator
+        let conditional: Vec<Distribution1D> = pixels
+            .chunks(width as usize)
+            .enumerate()
+            .map(|(row, texels)| {
+                let sin_theta = (PI * (row as Scalar + 0.5) / height as Scalar).sin();
+                Distribution1D::new(
+                    texels
+                        .iter()
+                        .map(|texel| texel.luminance().max(0.0) * sin_theta)
+                        .collect(),
+                )
+            })
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|row| row.integral).collect());
+
+        Self {
+            width,
+            height,
+            pixels,
+            marginal,
+            conditional,
+        }
+    }
+
+    // Radiance arriving along `-direction`, i.e. seen when looking along `direction`
+    pub fn radiance(&self, direction: &Vector) -> Vector {
+        let (u, v) = Self::to_uv(direction);
+        let x = ((u * self.width as Scalar) as u32).min(self.width - 1);
+        let y = ((v * self.height as Scalar) as u32).min(self.height - 1);
+
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    // A direction drawn in proportion to the map's luminance from two uniform numbers,
+    // with its density with respect to solid angle
+    pub fn sample(&self, u1: Scalar, u2: Scalar) -> (Vector, Scalar) {
+        let (v, row_pdf, row) = self.marginal.sample(u1);
+        let (u, column_pdf, _) = self.conditional[row].sample(u2);
+
+        let direction = Self::from_uv(u, v);
+        let sin_theta = (PI * v).sin();
+        let pdf = if sin_theta > 0.0 {
+            row_pdf * column_pdf / (2.0 * PI * PI * sin_theta)
+        } else {
+            0.0
+        };
+
+        (direction, pdf)
+    }
+
+    // Density with respect to solid angle of `sample` returning `direction`, for
+    // weighting it against other sampling strategies
+    pub fn pdf(&self, direction: &Vector) -> Scalar {
+        let (u, v) = Self::to_uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let row = ((v * self.height as Scalar) as usize).min(self.height as usize - 1);
+        let column = ((u * self.width as Scalar) as usize).min(self.width as usize - 1);
+
+        self.marginal.pdf(row) * self.conditional[row].pdf(column) / (2.0 * PI * PI * sin_theta)
+    }
+
+    // u runs once around the horizon, v from straight up (0) to straight down (1)
+    fn to_uv(direction: &Vector) -> (Scalar, Scalar) {
+        let d = direction.get_unit_vector();
+        let phi = d.data.2.atan2(d.data.0);
+        let theta = d.data.1.clamp(-1.0, 1.0).acos();
+
+        ((phi + PI) / (2.0 * PI), theta / PI)
+    }
+
+    fn from_uv(u: Scalar, v: Scalar) -> Vector {
+        let phi = 2.0 * PI * u - PI;
+        let theta = PI * v;
+
+        Vector::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+            VectorType::Vector,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::fuzzy_equal;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn map_with_sun(x: u32, y: u32) -> EnvironmentMap {
+        let (width, height) = (32, 16);
+        let mut pixels = vec![Vector::new(0.0, 0.0, 0.0, VectorType::Color); 32 * 16];
+        pixels[(y * width + x) as usize] = Vector::new(100.0, 90.0, 80.0, VectorType::Color);
+
+        EnvironmentMap::new(pixels, width, height)
+    }
+
+    #[test]
+    fn samples_land_on_the_only_bright_pixel() {
+        let map = map_with_sun(20, 5);
+        let mut rng = StdRng::seed_from_u64(3);
+
+        for _ in 0..200 {
+            let (direction, pdf) = map.sample(rng.gen(), rng.gen());
+
+            assert!(map.radiance(&direction).luminance() > 50.0);
+            assert!(pdf > 0.0);
+            assert!(fuzzy_equal(pdf, map.pdf(&direction)));
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let pixels = (0..32 * 16)
+            .map(|i| {
+                let value = (i % 32 + 1) as Scalar;
+                Vector::new(value, value, value, VectorType::Color)
+            })
+            .collect();
+        let map = EnvironmentMap::new(pixels, 32, 16);
+        let mut rng = StdRng::seed_from_u64(8);
+
+        // Monte Carlo estimate of the integral over the sphere with uniform directions
+        let n = 20000;
+        let total: Scalar = (0..n)
+            .map(|_| {
+                let direction = Vector::random_in_unit_sphere_with(&mut rng).get_unit_vector();
+                map.pdf(&direction) * 4.0 * PI
+            })
+            .sum();
+
+        assert!((total / n as Scalar - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn black_map_samples_uniformly_over_uv() {
+        let map = EnvironmentMap::new(
+            vec![Vector::new(0.0, 0.0, 0.0, VectorType::Color); 8 * 4],
+            8,
+            4,
+        );
+
+        let (direction, pdf) = map.sample(0.5, 0.25);
+
+        assert!(pdf > 0.0);
+        assert!(fuzzy_equal(direction.len(), 1.0));
+    }
+}
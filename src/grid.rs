@@ -0,0 +1,263 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::validate::Problem;
+use crate::vector::{Scalar, Vector};
+
+// A uniform grid of `resolution` cells over the bounds of the primitives, each cell
+// listing the primitives whose boxes overlap it. Rays walk the cells they cross in
+// order (3D DDA) and stop at the first cell that contains the closest hit. Suits
+// evenly spread scenes like `scene::random_scene`; for clustered geometry see `Bvh`.
+// Objects without a bounding box are tested on every ray.
+pub struct Grid {
+    objects: Vec<Box<dyn Hittable>>,
+    unbounded: Vec<usize>,
+    bounds: Option<Aabb>,
+    resolution: [usize; 3],
+    cells: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    pub fn new(objects: Vec<Box<dyn Hittable>>, resolution: [usize; 3]) -> Self {
+        let resolution = resolution.map(|n| n.max(1));
+        let boxes: Vec<Option<Aabb>> = objects.iter().map(|o| o.bounding_box()).collect();
+        let unbounded = (0..objects.len()).filter(|&i| boxes[i].is_none()).collect();
+        let bounds = boxes
+            .iter()
+            .flatten()
+            .copied()
+            .reduce(|a, b| a.surrounding(&b));
+
+        let mut grid = Self {
+            objects,
+            unbounded,
+            bounds,
+            resolution,
+            cells: vec![Vec::new(); resolution.iter().product()],
+        };
+
+        for (index, object_bounds) in boxes.iter().enumerate() {
+            if let Some(object_bounds) = object_bounds {
+                let low = grid.cell_of(&object_bounds.min);
+                let high = grid.cell_of(&object_bounds.max);
+                for z in low[2]..=high[2] {
+                    for y in low[1]..=high[1] {
+                        for x in low[0]..=high[0] {
+                            let cell = grid.cell_index([x, y, z]);
+                            grid.cells[cell].push(index);
+                        }
+                    }
+                }
+            }
+        }
+
+        grid
+    }
+
+    // Size of one cell along each axis; never zero, so flat scenes still work
+    fn cell_size(&self, bounds: &Aabb) -> [Scalar; 3] {
+        let extent = components(&(bounds.max - bounds.min));
+        let mut size = [0.0; 3];
+        for axis in 0..3 {
+            size[axis] = (extent[axis] / self.resolution[axis] as Scalar).max(Scalar::EPSILON);
+        }
+        size
+    }
+
+    // Cell containing `p`, clamped to the grid
+    fn cell_of(&self, p: &Vector) -> [usize; 3] {
+        let bounds = self.bounds.expect("only bounded objects are bucketed");
+        let size = self.cell_size(&bounds);
+        let (p, min) = (components(p), components(&bounds.min));
+
+        let mut cell = [0; 3];
+        for axis in 0..3 {
+            let i = ((p[axis] - min[axis]) / size[axis]).floor().max(0.0) as usize;
+            cell[axis] = i.min(self.resolution[axis] - 1);
+        }
+        cell
+    }
+
+    fn cell_index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (z * self.resolution[1] + y) * self.resolution[0] + x
+    }
+
+    // Closest hit among the bucketed objects, walking the cells along the ray
+    fn traverse(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        let bounds = self.bounds?;
+        let (enter, exit) = clip(&bounds, r, t_min, t_max)?;
+
+        let size = self.cell_size(&bounds);
+        let (origin, direction) = (components(&r.origin), components(&r.direction));
+        let min = components(&bounds.min);
+        let mut cell = self.cell_of(&r.at(enter));
+
+        // Per axis: which way the ray steps through cells, the ray distance between
+        // cell boundaries, and the distance to the next boundary
+        let mut step = [0isize; 3];
+        let mut t_delta = [Scalar::INFINITY; 3];
+        let mut t_next = [Scalar::INFINITY; 3];
+        for axis in 0..3 {
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                t_delta[axis] = size[axis] / direction[axis];
+                let boundary = min[axis] + (cell[axis] + 1) as Scalar * size[axis];
+                t_next[axis] = (boundary - origin[axis]) / direction[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                t_delta[axis] = -size[axis] / direction[axis];
+                let boundary = min[axis] + cell[axis] as Scalar * size[axis];
+                t_next[axis] = (boundary - origin[axis]) / direction[axis];
+            }
+        }
+
+        let mut closest: Option<HitRecord> = None;
+        loop {
+            for &index in &self.cells[self.cell_index(cell)] {
+                let t_max = closest.as_ref().map_or(exit, |hit| hit.t);
+                if let Some(hit) = self.objects[index].hit(r, t_min, t_max) {
+                    closest = Some(hit);
+                }
+            }
+
+            // Hits inside this cell cannot be beaten by anything further along
+            let axis = (0..3)
+                .min_by(|&a, &b| t_next[a].total_cmp(&t_next[b]))
+                .unwrap();
+            let cell_exit = t_next[axis];
+            if closest.as_ref().is_some_and(|hit| hit.t <= cell_exit) || cell_exit > exit {
+                break;
+            }
+
+            let next = cell[axis] as isize + step[axis];
+            if next < 0 || next >= self.resolution[axis] as isize {
+                break;
+            }
+            cell[axis] = next as usize;
+            t_next[axis] += t_delta[axis];
+        }
+
+        closest
+    }
+}
+
+fn components(v: &Vector) -> [Scalar; 3] {
+    [v.data.0, v.data.1, v.data.2]
+}
+
+// The part of [t_min, t_max] the ray spends inside `bounds`
+fn clip(bounds: &Aabb, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<(Scalar, Scalar)> {
+    let (origin, direction) = (components(&r.origin), components(&r.direction));
+    let (min, max) = (components(&bounds.min), components(&bounds.max));
+    let (mut enter, mut exit) = (t_min, t_max);
+
+    for axis in 0..3 {
+        let inverse = 1.0 / direction[axis];
+        let mut t0 = (min[axis] - origin[axis]) * inverse;
+        let mut t1 = (max[axis] - origin[axis]) * inverse;
+        if inverse < 0.0 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+
+        enter = enter.max(t0);
+        exit = exit.min(t1);
+        if exit < enter {
+            return None;
+        }
+    }
+
+    Some((enter, exit))
+}
+
+impl Hittable for Grid {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        let mut closest = self.traverse(r, t_min, t_max);
+
+        for &index in &self.unbounded {
+            let t_max = closest.as_ref().map_or(t_max, |hit| hit.t);
+            if let Some(hit) = self.objects[index].hit(r, t_min, t_max) {
+                closest = Some(hit);
+            }
+        }
+
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.unbounded.is_empty() {
+            self.bounds
+        } else {
+            None
+        }
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        self.objects.iter().flat_map(|o| o.problems()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::random_scene_with;
+    use crate::vector::VectorType;
+    use crate::world::World;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn grid(resolution: [usize; 3]) -> Grid {
+        let objects = random_scene_with(&mut StdRng::seed_from_u64(2))
+            .into_iter()
+            .map(|sphere| Box::new(sphere) as Box<dyn Hittable>)
+            .collect();
+
+        Grid::new(objects, resolution)
+    }
+
+    fn assert_matches_naive_loop(grid: &Grid) {
+        let world = World::from(random_scene_with(&mut StdRng::seed_from_u64(2)));
+        let mut rng = StdRng::seed_from_u64(4);
+
+        for _ in 0..500 {
+            let origin = Vector::new(
+                rng.gen_range(-15.0..15.0),
+                rng.gen_range(0.5..4.0),
+                rng.gen_range(-15.0..15.0),
+                VectorType::Point,
+            );
+            let target = Vector::new(
+                rng.gen_range(-11.0..11.0),
+                rng.gen_range(-0.5..1.5),
+                rng.gen_range(-11.0..11.0),
+                VectorType::Point,
+            );
+            let ray = Ray::new(origin, target - origin);
+
+            let expected = world.hit(&ray, 0.001, Scalar::INFINITY).map(|hit| hit.t);
+            let actual = grid.hit(&ray, 0.001, Scalar::INFINITY).map(|hit| hit.t);
+
+            match (expected, actual) {
+                (Some(e), Some(a)) => assert!((e - a).abs() < 1e-6, "{} != {}", e, a),
+                (None, None) => {}
+                _ => panic!("grid and naive loop disagree: {:?} {:?}", expected, actual),
+            }
+        }
+    }
+
+    #[test]
+    fn matches_naive_closest_hit_on_random_scene() {
+        assert_matches_naive_loop(&grid([1, 1, 1]));
+        assert_matches_naive_loop(&grid([24, 4, 24]));
+        assert_matches_naive_loop(&grid([7, 13, 3]));
+    }
+
+    #[test]
+    fn objects_land_in_every_cell_they_overlap() {
+        let grid = grid([24, 4, 24]);
+
+        // The ground sphere is in every cell, the small spheres in a few each
+        assert!(grid.cells.iter().all(|cell| cell.contains(&0)));
+        let total: usize = grid.cells.iter().map(Vec::len).sum();
+        assert!(total < 2 * grid.cells.len() + 8 * grid.objects.len());
+    }
+}
@@ -0,0 +1,112 @@
+use crate::png::to_io_error;
+use crate::vector::{Scalar, Vector, VectorType};
+use image::codecs::hdr::{HdrDecoder, HdrEncoder};
+use image::Rgb;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// Writes linear radiance as a Radiance .hdr (RGBE) file. No tone mapping, gamma or
+// clamping is applied, so the file can be re-exposed or composited later. See
+// `Image::to_rgb32f` for the radiance of a render.
+pub fn write_hdr<P: AsRef<Path>>(
+    path: P,
+    radiance: &[Rgb<f32>],
+    width: u32,
+    height: u32,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode_hdr(&mut writer, radiance, width, height)?;
+    writer.flush()
+}
+
+pub fn encode_hdr<W: Write>(
+    writer: &mut W,
+    radiance: &[Rgb<f32>],
+    width: u32,
+    height: u32,
+) -> io::Result<()> {
+    assert_eq!(radiance.len(), width as usize * height as usize);
+
+    HdrEncoder::new(writer)
+        .encode(radiance, width as usize, height as usize)
+        .map_err(to_io_error)
+}
+
+// The radiance of a .hdr file as colors, e.g. for an `EnvironmentMap`, with its width
+// and height
+pub fn read_hdr<P: AsRef<Path>>(path: P) -> io::Result<(Vec<Vector>, u32, u32)> {
+    decode_hdr(BufReader::new(File::open(path)?))
+}
+
+pub fn decode_hdr<R: BufRead>(reader: R) -> io::Result<(Vec<Vector>, u32, u32)> {
+    let decoder = HdrDecoder::new(reader).map_err(to_io_error)?;
+    let metadata = decoder.metadata();
+    let radiance = decoder
+        .read_image_hdr()
+        .map_err(to_io_error)?
+        .iter()
+        .map(|Rgb([r, g, b])| {
+            Vector::new(*r as Scalar, *g as Scalar, *b as Scalar, VectorType::Color)
+        })
+        .collect();
+
+    Ok((radiance, metadata.width, metadata.height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RGBE keeps 8 bits of mantissa per channel, relative to the brightest channel
+    fn close(actual: &Vector, expected: &Rgb<f32>) -> bool {
+        let Rgb([r, g, b]) = *expected;
+        let tolerance = (r.max(g).max(b) / 128.0) as Scalar;
+
+        (actual.data.0 - r as Scalar).abs() <= tolerance
+            && (actual.data.1 - g as Scalar).abs() <= tolerance
+            && (actual.data.2 - b as Scalar).abs() <= tolerance
+    }
+
+    fn round_trip(width: u32, height: u32) {
+        let radiance: Vec<Rgb<f32>> = (0..width * height)
+            .map(|i| {
+                let scale = 1.0 + i as f32;
+                Rgb([0.5 * scale, 0.75 * scale, 12.5 * scale])
+            })
+            .collect();
+
+        let mut bytes = vec![];
+        encode_hdr(&mut bytes, &radiance, width, height).unwrap();
+        let (read, read_width, read_height) = decode_hdr(bytes.as_slice()).unwrap();
+
+        assert_eq!((read_width, read_height), (width, height));
+        for (expected, actual) in radiance.iter().zip(read.iter()) {
+            assert!(close(actual, expected));
+        }
+    }
+
+    #[test]
+    fn radiance_round_trips_through_flat_scanlines() {
+        round_trip(3, 2);
+    }
+
+    #[test]
+    fn radiance_round_trips_through_rle_scanlines() {
+        round_trip(300, 2);
+    }
+
+    #[test]
+    fn rendered_radiance_is_written_unclamped() {
+        let mut image = crate::image::Image::new(2, 1);
+        image.set(1, 0, Vector::new(40.0, 0.25, 0.0, VectorType::Color));
+        let radiance = image.to_rgb32f();
+
+        let mut bytes = vec![];
+        encode_hdr(&mut bytes, &radiance, 2, 1).unwrap();
+        let (read, _, _) = decode_hdr(bytes.as_slice()).unwrap();
+
+        assert_eq!(read[0], Vector::new(0.0, 0.0, 0.0, VectorType::Color));
+        assert!(close(&read[1], &radiance[1]));
+    }
+}
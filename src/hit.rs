@@ -1,15 +1,82 @@
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::vector::Vector;
+use crate::validate::Problem;
+use crate::vector::{Scalar, Vector, VectorType};
+use rand::RngCore;
 
-pub trait Hittable {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+pub trait Hittable: Send + Sync {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>>;
+
+    // Box enclosing the whole primitive, or `None` when it is unbounded
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    // Density with respect to solid angle of `random_with` returning `direction` from
+    // `origin`. Only primitives that can be sampled as lights implement it; the rest
+    // report a density of 0 and should not be registered in `World::lights`.
+    fn pdf_value(&self, _origin: &Vector, _direction: &Vector) -> Scalar {
+        0.0
+    }
+
+    // A direction from `origin` towards the primitive, for sampling it as a light
+    fn random_with(&self, _rng: &mut dyn RngCore, _origin: &Vector) -> Vector {
+        Vector::new(1.0, 0.0, 0.0, VectorType::Vector)
+    }
+
+    // A point spread uniformly over the surface, as the hit of a ray arriving along
+    // the outward normal there, with its density with respect to area. For emitting
+    // photons from lights; primitives that cannot be sampled this way give `None`.
+    fn sample_surface(&self, _rng: &mut dyn RngCore) -> Option<(HitRecord<'_>, Scalar)> {
+        None
+    }
+
+    // Likely authoring mistakes in the primitive or its material, see `World::validate`
+    fn problems(&self) -> Vec<Problem> {
+        Vec::new()
+    }
+}
+
+// Lets boxed objects of mixed types be wrapped, e.g. in a `Transform`
+impl Hittable for Box<dyn Hittable> {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Vector, direction: &Vector) -> Scalar {
+        (**self).pdf_value(origin, direction)
+    }
+
+    fn random_with(&self, rng: &mut dyn RngCore, origin: &Vector) -> Vector {
+        (**self).random_with(rng, origin)
+    }
+
+    fn sample_surface(&self, rng: &mut dyn RngCore) -> Option<(HitRecord<'_>, Scalar)> {
+        (**self).sample_surface(rng)
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        (**self).problems()
+    }
 }
 
 pub struct HitRecord<'material> {
     pub point: Vector,
     pub normal: Vector,
-    pub t: f64,
+    pub t: Scalar,
+    pub u: Scalar,
+    pub v: Scalar,
+    // Approximate width of the pixel footprint in texture space, 0 when unknown
+    pub uv_footprint: Scalar,
     pub front_face: bool,
     pub material: &'material Material,
+    // Unit tangent frame following the direction of increasing u and v, for
+    // primitives that have a UV parameterization
+    pub tangent: Option<Vector>,
+    pub bitangent: Option<Vector>,
 }
@@ -0,0 +1,292 @@
+use crate::postprocess::{post_process, PostProcess};
+use crate::vector::{Scalar, Vector, VectorType};
+use ::image::Rgb;
+use rayon::prelude::*;
+use std::sync::atomic::Ordering;
+
+// Atomic integer wide enough to hold the bits of a `Scalar`
+#[cfg(not(feature = "f32"))]
+type AtomicScalar = std::sync::atomic::AtomicU64;
+#[cfg(feature = "f32")]
+type AtomicScalar = std::sync::atomic::AtomicU32;
+
+// The luminance histogram spans 2^-16..2^16 in log2 space
+const HISTOGRAM_BINS: usize = 256;
+const MIN_LOG_LUMINANCE: Scalar = -16.0;
+const MAX_LOG_LUMINANCE: Scalar = 16.0;
+
+// Linear radiance per pixel, averaged over all samples. Rows are stored top first,
+// matching the order they are written out in.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Vector>,
+    // NaN or infinite samples left out of the pixels while rendering this image
+    pub non_finite_samples: u64,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![
+                Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+                width as usize * height as usize
+            ],
+            non_finite_samples: 0,
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Vector {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Vector) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    // `left` and `right` next to each other in one image, e.g. the views of a stereo
+    // pair. Both must be the same height.
+    pub fn side_by_side(left: &Image, right: &Image) -> Image {
+        assert_eq!(left.height, right.height);
+
+        let mut image = Image::new(left.width + right.width, left.height);
+        let rows = image.pixels.chunks_mut(image.width as usize);
+        for ((row, left_row), right_row) in rows
+            .zip(left.pixels.chunks(left.width as usize))
+            .zip(right.pixels.chunks(right.width as usize))
+        {
+            let (left_half, right_half) = row.split_at_mut(left.width as usize);
+            left_half.copy_from_slice(left_row);
+            right_half.copy_from_slice(right_row);
+        }
+
+        image
+    }
+
+    // Gamma corrects and quantizes every pixel to 8-bit RGB triplets
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.to_rgb8_with(&PostProcess::default())
+    }
+
+    pub fn to_rgb8_with(&self, post: &PostProcess) -> Vec<u8> {
+        post_process(self, post)
+    }
+
+    // The raw radiance as single precision floats, for writing out with
+    // `hdr::write_hdr`. The cast is only needed without the `f32` feature.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn to_rgb32f(&self) -> Vec<Rgb<f32>> {
+        self.pixels
+            .iter()
+            .map(|pixel| {
+                let (r, g, b) = pixel.data;
+                Rgb([r as f32, g as f32, b as f32])
+            })
+            .collect()
+    }
+}
+
+// An image that many threads can add radiance to at once, for techniques that splat
+// contributions onto arbitrary pixels. Each channel is a Scalar stored in an atomic.
+pub struct AtomicImage {
+    pub width: u32,
+    pub height: u32,
+    channels: Vec<AtomicScalar>,
+}
+
+impl AtomicImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        let len = width as usize * height as usize * 3;
+
+        Self {
+            width,
+            height,
+            channels: (0..len)
+                .map(|_| AtomicScalar::new((0.0 as Scalar).to_bits()))
+                .collect(),
+        }
+    }
+
+    // Adds `color` to the pixel at (`x`, `y`). Panics outside the image, where the
+    // index would otherwise wrap onto a pixel of the next row.
+    pub fn splat(&self, x: u32, y: u32, color: Vector) {
+        assert!(
+            x < self.width && y < self.height,
+            "splat at ({x}, {y}) outside a {}x{} image",
+            self.width,
+            self.height
+        );
+        let index = 3 * (y * self.width + x) as usize;
+
+        for (channel, value) in [color.data.0, color.data.1, color.data.2]
+            .into_iter()
+            .enumerate()
+        {
+            let atomic = &self.channels[index + channel];
+            let mut current = atomic.load(Ordering::Relaxed);
+
+            while let Err(actual) = atomic.compare_exchange_weak(
+                current,
+                (Scalar::from_bits(current) + value).to_bits(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                current = actual;
+            }
+        }
+    }
+
+    // The accumulated sums multiplied by `scale`, e.g. one over the number of paths
+    pub fn to_image(&self, scale: Scalar) -> Image {
+        let mut image = Image::new(self.width, self.height);
+
+        for (pixel, rgb) in image.pixels.iter_mut().zip(self.channels.chunks(3)) {
+            let channel = |i: usize| scale * Scalar::from_bits(rgb[i].load(Ordering::Relaxed));
+            *pixel = Vector::new(channel(0), channel(1), channel(2), VectorType::Color);
+        }
+
+        image
+    }
+}
+
+// Exposure scale that brings the 90th-percentile luminance of the image to 0.8, leaving
+// only the brightest highlights to clip
+pub fn auto_exposure(image: &Image) -> Scalar {
+    auto_exposure_with(image, 0.9, 0.8)
+}
+
+pub fn auto_exposure_with(image: &Image, percentile: Scalar, target: Scalar) -> Scalar {
+    let histogram = luminance_histogram(image);
+    let total: u64 = histogram.iter().sum();
+
+    if total == 0 {
+        return 1.0;
+    }
+
+    let threshold = (percentile * total as Scalar).ceil() as u64;
+    let mut seen = 0;
+
+    for (bin, count) in histogram.iter().enumerate() {
+        seen += count;
+
+        if seen >= threshold.max(1) {
+            // Pure black images have nothing to expose
+            if bin == 0 && histogram[0] == total {
+                return 1.0;
+            }
+            return target / bin_luminance(bin);
+        }
+    }
+
+    1.0
+}
+
+// Counts pixels per logarithmic luminance bin, built in parallel over the pixels
+pub fn luminance_histogram(image: &Image) -> Vec<u64> {
+    image
+        .pixels
+        .par_iter()
+        .fold(
+            || vec![0u64; HISTOGRAM_BINS],
+            |mut histogram, pixel| {
+                histogram[luminance_bin(pixel.luminance())] += 1;
+                histogram
+            },
+        )
+        .reduce(
+            || vec![0u64; HISTOGRAM_BINS],
+            |mut lhs, rhs| {
+                for (l, r) in lhs.iter_mut().zip(rhs) {
+                    *l += r;
+                }
+                lhs
+            },
+        )
+}
+
+fn luminance_bin(luminance: Scalar) -> usize {
+    if luminance.is_nan() || luminance <= 0.0 {
+        return 0;
+    }
+
+    let range = MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE;
+    let position = (luminance.log2() - MIN_LOG_LUMINANCE) / range;
+
+    ((position * HISTOGRAM_BINS as Scalar) as isize).clamp(0, HISTOGRAM_BINS as isize - 1) as usize
+}
+
+// Luminance at the center of a bin
+fn bin_luminance(bin: usize) -> Scalar {
+    let range = MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE;
+    (2.0 as Scalar)
+        .powf(MIN_LOG_LUMINANCE + (bin as Scalar + 0.5) / HISTOGRAM_BINS as Scalar * range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_counts_every_pixel() {
+        let image = Image::new(8, 4);
+
+        let histogram = luminance_histogram(&image);
+
+        assert_eq!(histogram.iter().sum::<u64>(), 32);
+        assert_eq!(histogram[0], 32);
+    }
+
+    #[test]
+    fn exposure_targets_bright_half() {
+        let mut image = Image::new(10, 10);
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            let value = if i % 2 == 0 { 10.0 } else { 0.1 };
+            *pixel = Vector::new(value, value, value, VectorType::Color);
+        }
+
+        let exposure = auto_exposure(&image);
+
+        // The 90th percentile falls in the bright half, which should land near 0.8
+        let exposed = exposure * 10.0;
+        assert!(exposed > 0.7 && exposed < 0.9, "exposed to {}", exposed);
+    }
+
+    #[test]
+    fn black_image_keeps_unit_exposure() {
+        let image = Image::new(4, 4);
+
+        assert_eq!(auto_exposure(&image), 1.0);
+    }
+
+    #[test]
+    fn concurrent_splats_sum() {
+        let image = AtomicImage::new(4, 3);
+        let color = Vector::new(0.25, 0.5, 1.0, VectorType::Color);
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..1000 {
+                        image.splat(2, 1, color);
+                    }
+                });
+            }
+        });
+
+        let result = image.to_image(1.0);
+        assert_eq!(result.get(2, 1), 8000.0 * color);
+        assert_eq!(
+            result.get(0, 0),
+            Vector::new(0.0, 0.0, 0.0, VectorType::Color)
+        );
+        assert_eq!(image.to_image(0.5).get(2, 1), 4000.0 * color);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside a 4x3 image")]
+    fn splats_past_the_edge_are_caught() {
+        AtomicImage::new(4, 3).splat(4, 0, Vector::new(1.0, 1.0, 1.0, VectorType::Color));
+    }
+}
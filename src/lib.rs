@@ -0,0 +1,47 @@
+pub mod aabb;
+pub mod accumulation;
+pub mod adaptive;
+pub mod ao;
+pub mod batch;
+pub mod bvh;
+pub mod camera;
+pub mod config;
+pub mod environment;
+pub mod grid;
+pub mod hdr;
+pub mod hit;
+pub mod image;
+pub mod json;
+pub mod light;
+pub mod log;
+pub mod material;
+pub mod matrix;
+pub mod obj;
+pub mod onb;
+pub mod pdf;
+pub mod perlin;
+pub mod photon;
+pub mod pick;
+pub mod png;
+pub mod postprocess;
+pub mod quad;
+pub mod quadric;
+pub mod quaternion;
+pub mod ray;
+pub mod render;
+pub mod scene;
+pub mod scene_graph;
+pub mod sdf;
+#[cfg(feature = "spectral")]
+pub mod spectral;
+pub mod sphere;
+pub mod sphere_cluster;
+pub mod sphere_tree;
+pub mod stream;
+pub mod texture;
+pub mod transform;
+pub mod triangle;
+pub mod utils;
+pub mod validate;
+pub mod vector;
+pub mod world;
@@ -0,0 +1,118 @@
+use crate::hit::{HitRecord, Hittable};
+use crate::material::{DiffuseLight, Material};
+use crate::ray::Ray;
+use crate::validate::Problem;
+use crate::vector::{Scalar, Vector};
+use crate::world::World;
+use rand::RngCore;
+
+// An infinitely small light used for direct lighting of non-physical materials
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+    pub position: Vector,
+    pub color: Vector,
+}
+
+impl PointLight {
+    pub fn new(position: Vector, color: Vector) -> Self {
+        Self { position, color }
+    }
+}
+
+// Any shape turned into an emitter. The shape's own material is ignored.
+pub struct AreaLight<H: Hittable> {
+    shape: H,
+    material: Material,
+}
+
+impl<H: Hittable> AreaLight<H> {
+    pub fn new(shape: H, emit: Vector) -> Self {
+        Self {
+            shape,
+            material: Material::DiffuseLight(DiffuseLight::new(emit)),
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for AreaLight<H> {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        let mut hit = self.shape.hit(r, t_min, t_max)?;
+        hit.material = &self.material;
+        Some(hit)
+    }
+
+    fn pdf_value(&self, origin: &Vector, direction: &Vector) -> Scalar {
+        self.shape.pdf_value(origin, direction)
+    }
+
+    fn random_with(&self, rng: &mut dyn RngCore, origin: &Vector) -> Vector {
+        self.shape.random_with(rng, origin)
+    }
+
+    fn sample_surface(&self, rng: &mut dyn RngCore) -> Option<(HitRecord<'_>, Scalar)> {
+        let (mut hit, pdf) = self.shape.sample_surface(rng)?;
+        hit.material = &self.material;
+        Some((hit, pdf))
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        self.shape.problems()
+    }
+}
+
+// Adds `shape` to the world as a light emitting `color * intensity` and registers it
+// in the world's light list. Returns its index in `world.objects()`.
+pub fn area_light<H: Hittable + 'static>(
+    world: &mut World,
+    shape: H,
+    color: Vector,
+    intensity: Scalar,
+) -> usize {
+    let index = world.objects().len();
+    world.add(AreaLight::new(shape, intensity * color));
+    world.lights.push(index);
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::quad::Quad;
+    use crate::vector::VectorType;
+
+    #[test]
+    fn area_light_emits_and_is_registered() {
+        let mut world = World::new();
+        let panel = Quad::new(
+            Vector::new(-1.0, 2.0, -1.0, VectorType::Point),
+            Vector::new(2.0, 0.0, 0.0, VectorType::Vector),
+            Vector::new(0.0, 0.0, 2.0, VectorType::Vector),
+            Material::gray(),
+        );
+        let color = Vector::new(1.0, 0.9, 0.8, VectorType::Color);
+
+        let index = area_light(&mut world, panel, color, 4.0);
+
+        assert_eq!(world.lights, vec![index]);
+
+        let up = Ray::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+        );
+        let hit = world.objects()[index]
+            .hit(&up, 0.001, Scalar::INFINITY)
+            .unwrap();
+        assert_eq!(hit.material.emitted(&hit), 4.0 * color);
+        assert_eq!(
+            crate::utils::ray_color(
+                &up,
+                &world,
+                5,
+                Vector::new(0.0, 0.0, 0.0, VectorType::Color),
+                Vector::new(0.0, 0.0, 0.0, VectorType::Color)
+            ),
+            4.0 * color
+        );
+    }
+}
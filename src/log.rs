@@ -0,0 +1,149 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// How much diagnostic output goes to stderr. Messages are tagged with the lowest
+// verbosity that shows them: only errors are written when `Quiet`, so a quiet run
+// that succeeds leaves nothing but the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    // Errors the binary stops or skips work for
+    Quiet,
+    // Also warnings and what the binary did
+    Normal,
+    // Also details that only help when digging into a render
+    Verbose,
+}
+
+impl Verbosity {
+    // 0 is quiet and anything above 1 verbose, as in `RT_VERBOSITY`
+    pub fn from_level(level: u8) -> Self {
+        match level {
+            0 => Verbosity::Quiet,
+            1 => Verbosity::Normal,
+            _ => Verbosity::Verbose,
+        }
+    }
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+// Applies to every message written afterwards, from any thread
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    Verbosity::from_level(VERBOSITY.load(Ordering::Relaxed))
+}
+
+// Writes `message` on a line of its own to `out` if it is shown at `verbosity`
+pub fn write_at(
+    out: &mut dyn Write,
+    verbosity: Verbosity,
+    level: Verbosity,
+    message: fmt::Arguments,
+) -> io::Result<()> {
+    if level > verbosity {
+        return Ok(());
+    }
+    writeln!(out, "{}", message)
+}
+
+// A message written even when quiet, for failures
+pub fn error(message: fmt::Arguments) {
+    message_at(Verbosity::Quiet, message);
+}
+
+// A message for the normal level, e.g. `log::info(format_args!("{}", warning))`
+pub fn info(message: fmt::Arguments) {
+    message_at(Verbosity::Normal, message);
+}
+
+// A message only shown when verbose
+pub fn debug(message: fmt::Arguments) {
+    message_at(Verbosity::Verbose, message);
+}
+
+fn message_at(level: Verbosity, message: fmt::Arguments) {
+    #[cfg(test)]
+    if tests::captured(level, message) {
+        return;
+    }
+
+    // There is nowhere left to report a failure to write to stderr
+    let _ = write_at(&mut io::stderr().lock(), verbosity(), level, message);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::utils::parse_or;
+    use std::cell::RefCell;
+
+    thread_local! {
+        // Where messages from this thread go instead of stderr, and at what verbosity
+        static CAPTURE: RefCell<Option<(Verbosity, Vec<u8>)>> = const { RefCell::new(None) };
+    }
+
+    pub(super) fn captured(level: Verbosity, message: fmt::Arguments) -> bool {
+        CAPTURE.with(|capture| match capture.borrow_mut().as_mut() {
+            Some((verbosity, out)) => {
+                write_at(out, *verbosity, level, message).unwrap();
+                true
+            }
+            None => false,
+        })
+    }
+
+    // What `f` would have written to stderr at `verbosity`. Only messages from the
+    // calling thread are caught, so tests running alongside do not interfere.
+    pub(crate) fn capture(verbosity: Verbosity, f: impl FnOnce()) -> String {
+        CAPTURE.with(|capture| *capture.borrow_mut() = Some((verbosity, Vec::new())));
+        f();
+        let (_, out) = CAPTURE.with(|capture| capture.borrow_mut().take()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn written(verbosity: Verbosity, level: Verbosity) -> String {
+        let mut out = Vec::new();
+        write_at(
+            &mut out,
+            verbosity,
+            level,
+            format_args!("rendered in {} s", 2),
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn messages_show_from_their_level_up() {
+        assert_eq!(written(Verbosity::Quiet, Verbosity::Normal), "");
+        assert_eq!(written(Verbosity::Normal, Verbosity::Verbose), "");
+        assert_eq!(
+            written(Verbosity::Normal, Verbosity::Normal),
+            "rendered in 2 s\n"
+        );
+        assert_eq!(
+            written(Verbosity::Verbose, Verbosity::Verbose),
+            "rendered in 2 s\n"
+        );
+        assert_eq!(Verbosity::from_level(7), Verbosity::Verbose);
+    }
+
+    #[test]
+    fn quiet_silences_warnings_but_not_errors() {
+        let warn = || {
+            parse_or("RT_WIDTH", Some("wide"), 1200u32);
+        };
+        assert_eq!(capture(Verbosity::Quiet, warn), "");
+        assert!(capture(Verbosity::Normal, warn).contains("RT_WIDTH"));
+
+        let fail = || error(format_args!("could not write the image"));
+        assert_eq!(
+            capture(Verbosity::Quiet, fail),
+            "could not write the image\n"
+        );
+    }
+}
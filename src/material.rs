@@ -1,44 +1,196 @@
 use crate::hit::HitRecord;
+use crate::pdf::{CosinePdf, Pdf};
 use crate::ray::Ray;
-use crate::vector::Vector;
-use rand::Rng;
+use crate::texture::{SharedTexture, SolidColor};
+use crate::validate::{self, Problem};
+use crate::vector::{Scalar, Vector, VectorType};
+use crate::world::World;
+use rand::{Rng, RngCore};
+use std::sync::Arc;
 
 pub trait Scatterable {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Vector)>;
+    // Any randomness is drawn from `rng`, so a seeded generator reproduces the bounce
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Option<Ray>, Vector)>;
+
+    // Density with respect to solid angle of `scatter` sending `ray_in` off along
+    // `scattered`. Specular materials scatter into a single direction, a delta
+    // distribution with no density, and report 0; `ray_color` uses their bounce as is.
+    fn scattering_pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _scattered: &Ray) -> Scalar {
+        0.0
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum Material {
     Lambertian(Lambertian),
     Metal(Metal),
     Dielectric(Dielectric),
+    Phong(Phong),
+    DiffuseLight(DiffuseLight),
+    Cutout(Cutout),
+    Coated(Coated),
+    Unlit(Unlit),
+}
+
+impl Material {
+    // Human readable name of the material model
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Material::Lambertian(_) => "Lambertian",
+            Material::Metal(_) => "Metal",
+            Material::Dielectric(_) => "Dielectric",
+            Material::Phong(_) => "Phong",
+            Material::DiffuseLight(_) => "DiffuseLight",
+            Material::Cutout(_) => "Cutout",
+            Material::Coated(_) => "Coated",
+            Material::Unlit(_) => "Unlit",
+        }
+    }
+
+    // Parameters outside the range the material model makes sense for
+    pub fn problems(&self) -> Vec<Problem> {
+        let albedo = match self {
+            Material::Lambertian(lambertian) => lambertian.albedo.constant(),
+            Material::Metal(metal) => Some(metal.albedo),
+            Material::Dielectric(dielectric) => {
+                return if dielectric.ir >= 1.0 {
+                    Vec::new()
+                } else {
+                    vec![Problem::IndexOfRefraction(dielectric.ir)]
+                };
+            }
+            Material::Phong(_) | Material::DiffuseLight(_) | Material::Unlit(_) => None,
+            Material::Cutout(cutout) => return cutout.material.problems(),
+            Material::Coated(coated) => {
+                let mut problems = coated.base.problems();
+                if coated.coat_ior < 1.0 {
+                    problems.push(Problem::IndexOfRefraction(coated.coat_ior));
+                }
+                return problems;
+            }
+        };
+
+        match albedo {
+            Some(albedo) if !validate::in_unit_range(&albedo) => {
+                vec![Problem::AlbedoOutOfRange(albedo)]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // Light given off by the surface itself, black for everything but lights
+    pub fn emitted(&self, hit_record: &HitRecord) -> Vector {
+        match self {
+            Material::DiffuseLight(light) => {
+                light.emitted(hit_record.u, hit_record.v, &hit_record.point)
+            }
+            Material::Unlit(unlit) => unlit.color,
+            Material::Cutout(cutout) => cutout.material.emitted(hit_record),
+            Material::Coated(coated) => coated.base.emitted(hit_record),
+            _ => Vector::new(0.0, 0.0, 0.0, VectorType::Color),
+        }
+    }
+
+    // The surface's own color at the hit, unlit: what it tints the light it scatters
+    // by, or gives off for lights and unlit surfaces. Clear glass is white.
+    pub fn albedo(&self, hit_record: &HitRecord) -> Vector {
+        match self {
+            Material::Lambertian(lambertian) => lambertian.albedo.filtered_value(
+                hit_record.u,
+                hit_record.v,
+                &hit_record.point,
+                hit_record.uv_footprint,
+            ),
+            Material::Metal(metal) => metal.albedo,
+            Material::Dielectric(_) => Vector::new(1.0, 1.0, 1.0, VectorType::Color),
+            Material::Phong(phong) => phong.color,
+            Material::DiffuseLight(light) => {
+                light.emitted(hit_record.u, hit_record.v, &hit_record.point)
+            }
+            Material::Unlit(unlit) => unlit.color,
+            Material::Cutout(cutout) => cutout.material.albedo(hit_record),
+            Material::Coated(coated) => coated.base.albedo(hit_record),
+        }
+    }
+
+    // Whether the material only scatters into directions of its own choosing, like a
+    // mirror, so its bounce cannot be drawn from another distribution and reweighted
+    // with `scattering_pdf`
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Material::Lambertian(_) => false,
+            Material::Cutout(cutout) => cutout.material.is_specular(),
+            _ => true,
+        }
+    }
+
+    // Whether rays go straight through the surface at this hit as if it were not
+    // there, which only cutouts do
+    pub fn passes_through(&self, hit_record: &HitRecord) -> bool {
+        match self {
+            Material::Cutout(cutout) => cutout.passes_through(hit_record),
+            _ => false,
+        }
+    }
 }
 
 impl Scatterable for Material {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Vector)> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Option<Ray>, Vector)> {
         match self {
-            Material::Metal(m) => m.scatter(ray, hit_record),
-            Material::Lambertian(l) => l.scatter(ray, hit_record),
-            Material::Dielectric(d) => d.scatter(ray, hit_record),
+            Material::Metal(m) => m.scatter(ray, hit_record, rng),
+            Material::Lambertian(l) => l.scatter(ray, hit_record, rng),
+            Material::Dielectric(d) => d.scatter(ray, hit_record, rng),
+            Material::Phong(p) => p.scatter(ray, hit_record, rng),
+            Material::DiffuseLight(d) => d.scatter(ray, hit_record, rng),
+            Material::Cutout(c) => c.material.scatter(ray, hit_record, rng),
+            Material::Coated(c) => c.scatter(ray, hit_record, rng),
+            Material::Unlit(u) => u.scatter(ray, hit_record, rng),
+        }
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Scalar {
+        match self {
+            Material::Metal(m) => m.scattering_pdf(ray_in, hit_record, scattered),
+            Material::Lambertian(l) => l.scattering_pdf(ray_in, hit_record, scattered),
+            Material::Dielectric(d) => d.scattering_pdf(ray_in, hit_record, scattered),
+            Material::Phong(p) => p.scattering_pdf(ray_in, hit_record, scattered),
+            Material::DiffuseLight(d) => d.scattering_pdf(ray_in, hit_record, scattered),
+            Material::Cutout(c) => c.material.scattering_pdf(ray_in, hit_record, scattered),
+            Material::Coated(c) => c.scattering_pdf(ray_in, hit_record, scattered),
+            Material::Unlit(u) => u.scattering_pdf(ray_in, hit_record, scattered),
         }
     }
 }
 
 #[derive(Clone, Copy)]
 pub struct Dielectric {
-    ir: f64,
+    ir: Scalar,
 }
 
 impl Dielectric {
-    pub fn new(ir: f64) -> Self {
+    pub fn new(ir: Scalar) -> Self {
         Self { ir }
     }
 }
 
 impl Scatterable for Dielectric {
-    fn scatter(&self, r: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Vector)> {
-        let mut rng = rand::thread_rng();
-        let attenuation = Vector::new(1.0, 1.0, 1.0, crate::vector::VectorType::Color);
+    fn scatter(
+        &self,
+        r: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Option<Ray>, Vector)> {
+        let attenuation = Vector::new(1.0, 1.0, 1.0, VectorType::Color);
 
         let refraction_ratio = if hit_record.front_face {
             1.0 / self.ir
@@ -51,13 +203,13 @@ impl Scatterable for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        if cannot_refract || reflectance(cos_theta, refraction_ratio) > rng.gen::<f64>() {
-            let direction = reflect(unit_direction, hit_record.normal);
-            let scattered = Ray::new(hit_record.point, direction);
+        if cannot_refract || reflectance(cos_theta, refraction_ratio) > rng.gen::<Scalar>() {
+            let direction = unit_direction.reflect(&hit_record.normal);
+            let scattered = Ray::new(hit_record.point, direction).with_time(r.time);
             Some((Some(scattered), attenuation))
         } else {
             let direction = refract(unit_direction, hit_record.normal, refraction_ratio);
-            let scattered = Ray::new(hit_record.point, direction);
+            let scattered = Ray::new(hit_record.point, direction).with_time(r.time);
             Some((Some(scattered), attenuation))
         }
     }
@@ -66,75 +218,756 @@ impl Scatterable for Dielectric {
 #[derive(Clone, Copy)]
 pub struct Metal {
     albedo: Vector,
-    fuzz: f64,
+    // Fuzz along the surface's tangent and bitangent; equal for an isotropic metal
+    roughness_u: Scalar,
+    roughness_v: Scalar,
 }
 
 impl Metal {
-    pub fn new(albedo: Vector, fuzz: f64) -> Self {
+    pub fn new(albedo: Vector, fuzz: Scalar) -> Self {
+        Self::anisotropic(albedo, fuzz, fuzz)
+    }
+
+    // Brushed metal, blurred more along one direction of the surface than the other.
+    // Primitives without a tangent frame fall back to the mean of the two.
+    pub fn anisotropic(albedo: Vector, roughness_u: Scalar, roughness_v: Scalar) -> Self {
         Self {
             albedo,
-            fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
+            roughness_u: roughness_u.min(1.0),
+            roughness_v: roughness_v.min(1.0),
+        }
+    }
+
+    // Offset added to the mirror direction: a random point in the unit sphere,
+    // stretched into an ellipsoid along the tangent frame
+    fn fuzz(&self, hit_record: &HitRecord, rng: &mut dyn RngCore) -> Vector {
+        let p = Vector::random_in_unit_sphere_with(rng);
+        let mean = 0.5 * (self.roughness_u + self.roughness_v);
+
+        match (hit_record.tangent, hit_record.bitangent) {
+            (Some(tangent), Some(bitangent)) => {
+                let normal = hit_record.normal;
+                self.roughness_u * p.dot(&tangent) * tangent
+                    + self.roughness_v * p.dot(&bitangent) * bitangent
+                    + mean * p.dot(&normal) * normal
+            }
+            _ => mean * p,
         }
     }
 }
 
 impl Scatterable for Metal {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Vector)> {
-        let reflected = reflect(ray.direction.get_unit_vector(), hit_record.normal);
-        let scattered = Ray::new(
-            hit_record.point,
-            reflected + self.fuzz * Vector::random_in_unit_sphere(),
-        );
-        let attenuation = self.albedo;
-
-        if scattered.direction.dot(&hit_record.normal) > 0.0 {
-            Some((Some(scattered), attenuation))
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Option<Ray>, Vector)> {
+        // Near grazing angles the fuzz can keep pushing the ray into the surface, and
+        // it is absorbed
+        let direction = if self.roughness_u == self.roughness_v {
+            Vector::reflect_rough(&ray.direction, &hit_record.normal, self.roughness_u, rng)
         } else {
-            None
-        }
+            Vector::reflect_perturbed(&ray.direction, &hit_record.normal, rng, |rng| {
+                self.fuzz(hit_record, rng)
+            })
+        }?;
+
+        Some((
+            Some(Ray::new(hit_record.point, direction).with_time(ray.time)),
+            self.albedo,
+        ))
     }
 }
 
-#[derive(Clone, Copy)]
+// Whether a reflected ray leaves on the side of the surface `normal` points to, with
+// a usable direction. Transmitted rays from dielectrics are meant to fail this.
+pub fn is_valid_scatter(scattered: &Ray, normal: &Vector) -> bool {
+    let d = scattered.direction;
+
+    d.data.0.is_finite() && d.data.1.is_finite() && d.data.2.is_finite() && d.dot(normal) > 0.0
+}
+
+#[derive(Clone)]
 pub struct Lambertian {
-    pub albedo: Vector,
+    pub albedo: SharedTexture,
 }
 
 impl Lambertian {
     pub fn new(albedo: Vector) -> Self {
+        Self::with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn with_texture(albedo: SharedTexture) -> Self {
         Self { albedo }
     }
 }
 
 impl Scatterable for Lambertian {
-    fn scatter(&self, _ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Vector)> {
-        let mut scatter_direction = hit_record.normal + Vector::random_in_unit_sphere();
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Option<Ray>, Vector)> {
+        // Drawn from exactly the density `scattering_pdf` reports, so the albedo alone
+        // is the weight of the bounce
+        let scatter_direction = CosinePdf::new(&hit_record.normal).generate(rng);
+
+        let scattered = Ray::new(hit_record.point, scatter_direction).with_time(ray.time);
+        // Camera rays carry a footprint, so textures seen straight away are filtered
+        // over the pixel instead of aliasing
+        let attenuation = self.albedo.filtered_value(
+            hit_record.u,
+            hit_record.v,
+            &hit_record.point,
+            hit_record.uv_footprint,
+        );
 
-        if scatter_direction.near_zero() {
-            scatter_direction = hit_record.normal;
+        Some((Some(scattered), attenuation))
+    }
+
+    // cos θ / π above the surface, 0 below it
+    fn scattering_pdf(&self, _ray_in: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Scalar {
+        let cosine = hit_record
+            .normal
+            .dot(&scattered.direction.get_unit_vector());
+        (cosine / crate::vector::consts::PI).max(0.0)
+    }
+}
+
+// Classic, non energy conserving Phong (or Blinn-Phong) shading. It doesn't scatter
+// rays; instead `ray_color` lights it directly from the world's point lights.
+#[derive(Clone, Copy)]
+pub struct Phong {
+    color: Vector,
+    ambient: Scalar,
+    diffuse: Scalar,
+    specular: Scalar,
+    shininess: Scalar,
+    blinn: bool,
+}
+
+impl Phong {
+    pub fn new(
+        color: Vector,
+        ambient: Scalar,
+        diffuse: Scalar,
+        specular: Scalar,
+        shininess: Scalar,
+    ) -> Self {
+        Self {
+            color,
+            ambient,
+            diffuse,
+            specular,
+            shininess,
+            blinn: false,
         }
+    }
 
-        let scattered = Ray::new(hit_record.point, scatter_direction);
-        let attenuation = self.albedo;
+    // Uses the half vector instead of the mirrored light direction for the highlight
+    pub fn blinn_phong(
+        color: Vector,
+        ambient: Scalar,
+        diffuse: Scalar,
+        specular: Scalar,
+        shininess: Scalar,
+    ) -> Self {
+        Self {
+            blinn: true,
+            ..Self::new(color, ambient, diffuse, specular, shininess)
+        }
+    }
 
-        Some((Some(scattered), attenuation))
+    // Light reflected towards the viewer from a single light. All directions are unit
+    // vectors pointing away from the surface.
+    pub fn evaluate(
+        &self,
+        normal: &Vector,
+        to_light: &Vector,
+        to_viewer: &Vector,
+        light_color: Vector,
+    ) -> Vector {
+        let n_dot_l = normal.dot(to_light);
+        if n_dot_l <= 0.0 {
+            return Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+        }
+
+        let alignment = if self.blinn {
+            normal.dot(&(*to_light + *to_viewer).get_unit_vector())
+        } else {
+            (-*to_light).reflect(normal).dot(to_viewer)
+        };
+        let highlight = self.specular * alignment.max(0.0).powf(self.shininess);
+
+        light_color
+            * (self.diffuse * n_dot_l * self.color
+                + Vector::new(highlight, highlight, highlight, VectorType::Color))
+    }
+
+    pub fn shade(&self, r: &Ray, hit_record: &HitRecord, world: &World) -> Vector {
+        let mut color = self.ambient * self.color;
+        let to_viewer = -r.direction.get_unit_vector();
+
+        for light in &world.point_lights {
+            let to_light = light.position - hit_record.point;
+            let distance = to_light.len();
+            // A light on the surface itself has no direction to shine from
+            if distance == 0.0 {
+                continue;
+            }
+            let to_light = to_light / distance;
+
+            let shadow_ray = Ray::new(hit_record.point, to_light).with_time(r.time);
+            if world
+                .hit(&shadow_ray, world.t_min(&shadow_ray), distance)
+                .is_some()
+            {
+                continue;
+            }
+
+            color = color + self.evaluate(&hit_record.normal, &to_light, &to_viewer, light.color);
+        }
+
+        color
+    }
+}
+
+impl Scatterable for Phong {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hit_record: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<(Option<Ray>, Vector)> {
+        None
+    }
+}
+
+// An emitter that gives off `emit` evenly in every direction and absorbs any light
+// that reaches it. A textured `emit` varies over the surface, like a TV screen or a
+// stained glass window.
+#[derive(Clone)]
+pub struct DiffuseLight {
+    emit: SharedTexture,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Vector) -> Self {
+        Self::with_texture(Arc::new(SolidColor::new(emit)))
+    }
+
+    pub fn with_texture(emit: SharedTexture) -> Self {
+        Self { emit }
+    }
+
+    // Light given off at the texture coordinates (`u`, `v`) and the point `p`
+    pub fn emitted(&self, u: Scalar, v: Scalar, p: &Vector) -> Vector {
+        self.emit.value(u, v, p)
+    }
+}
+
+impl Scatterable for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hit_record: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<(Option<Ray>, Vector)> {
+        None
+    }
+}
+
+// A flat color that shows the same however the surface is lit, for debugging, UI
+// elements and matte objects. It returns its color like an emitter, so rays that
+// bounce into it still pick some up, but unlike `DiffuseLight` it is not meant to be
+// registered in `World::lights` and sampled as a light.
+#[derive(Clone, Copy)]
+pub struct Unlit {
+    color: Vector,
+}
+
+impl Unlit {
+    pub fn new(color: Vector) -> Self {
+        Self { color }
+    }
+}
+
+impl Scatterable for Unlit {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hit_record: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<(Option<Ray>, Vector)> {
+        None
+    }
+}
+
+// A clear varnish over another material, like car paint or lacquered wood. Each
+// scatter either reflects off the coat, with the Fresnel odds of a dielectric of
+// index `coat_ior`, or goes through to `base`. The coat is glossy rather than a
+// perfect mirror when `coat_roughness` is above 0.
+#[derive(Clone)]
+pub struct Coated {
+    base: Box<Material>,
+    coat_ior: Scalar,
+    coat_roughness: Scalar,
+}
+
+impl Coated {
+    pub fn new(base: Material, coat_ior: Scalar, coat_roughness: Scalar) -> Self {
+        Self {
+            base: Box::new(base),
+            coat_ior,
+            coat_roughness: coat_roughness.min(1.0),
+        }
+    }
+}
+
+impl Scatterable for Coated {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Option<Ray>, Vector)> {
+        let unit_direction = ray.direction.get_unit_vector();
+        let cos_theta = (-unit_direction).dot(&hit_record.normal).clamp(0.0, 1.0);
+
+        if reflectance(cos_theta, self.coat_ior) <= rng.gen::<Scalar>() {
+            return self.base.scatter(ray, hit_record, rng);
+        }
+
+        // The coat itself is clear, so its reflection is not tinted
+        let white = Vector::new(1.0, 1.0, 1.0, VectorType::Color);
+        let direction = Vector::reflect_rough(
+            &unit_direction,
+            &hit_record.normal,
+            self.coat_roughness,
+            rng,
+        )?;
+
+        Some((
+            Some(Ray::new(hit_record.point, direction).with_time(ray.time)),
+            white,
+        ))
     }
 }
 
-fn reflect(v: Vector, n: Vector) -> Vector {
-    return v - n * (2.0 * v.dot(&n));
+// Alpha tested transparency for foliage, fences and the like on simple geometry.
+// Where the luminance of `alpha` at the hit is below `threshold` the surface is a hole
+// and the ray carries on; elsewhere `material` shades it as usual. Shadow rays for
+// Phong lights still treat the whole surface as solid.
+#[derive(Clone)]
+pub struct Cutout {
+    material: Box<Material>,
+    alpha: SharedTexture,
+    threshold: Scalar,
 }
 
-fn refract(uv: Vector, n: Vector, etai_over_eatt: f64) -> Vector {
-    let cos_theta: f64 = ((-uv).dot(&n)).min(1.0);
+impl Cutout {
+    pub fn new(material: Material, alpha: SharedTexture, threshold: Scalar) -> Self {
+        Self {
+            material: Box::new(material),
+            alpha,
+            threshold,
+        }
+    }
+
+    fn passes_through(&self, hit_record: &HitRecord) -> bool {
+        let alpha = self.alpha.filtered_value(
+            hit_record.u,
+            hit_record.v,
+            &hit_record.point,
+            hit_record.uv_footprint,
+        );
+
+        alpha.luminance() < self.threshold
+    }
+}
+
+fn refract(uv: Vector, n: Vector, etai_over_eatt: Scalar) -> Vector {
+    let cos_theta: Scalar = ((-uv).dot(&n)).min(1.0);
     let r_out_perp = (uv + n * cos_theta) * etai_over_eatt;
-    let r_out_parallel = n * (-1.0 * (1.0 - r_out_perp.length_squared()).abs().sqrt());
+    let r_out_parallel = n * -(1.0 - r_out_perp.length_squared()).abs().sqrt();
 
     r_out_parallel + r_out_perp
 }
 
-fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
+fn reflectance(cosine: Scalar, ref_idx: Scalar) -> Scalar {
     let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     r0 = r0 * r0;
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
+
+// The mid gray diffuse surface most test scenes are built from
+#[cfg(test)]
+impl Material {
+    pub(crate) fn gray() -> Self {
+        Material::Lambertian(Lambertian::new(Vector::new(
+            0.5,
+            0.5,
+            0.5,
+            VectorType::Color,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::ImageTexture;
+    use crate::vector::fuzzy_equal;
+
+    fn hit_at<'material>(
+        u: Scalar,
+        v: Scalar,
+        material: &'material Material,
+    ) -> HitRecord<'material> {
+        HitRecord {
+            point: Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            normal: Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            t: 1.0,
+            u,
+            v,
+            uv_footprint: 0.0,
+            front_face: true,
+            material,
+            tangent: None,
+            bitangent: None,
+        }
+    }
+
+    // Alternates between two colors in squares of a quarter of texture space
+    struct Checker(Vector, Vector);
+
+    impl crate::texture::Texture for Checker {
+        fn value(&self, u: Scalar, v: Scalar, _point: &Vector) -> Vector {
+            if ((4.0 * u).floor() + (4.0 * v).floor()) as i64 % 2 == 0 {
+                self.0
+            } else {
+                self.1
+            }
+        }
+    }
+
+    #[test]
+    fn textured_light_emits_its_texture() {
+        let warm = Vector::new(4.0, 3.0, 2.0, VectorType::Color);
+        let cool = Vector::new(1.0, 2.0, 5.0, VectorType::Color);
+        let light =
+            Material::DiffuseLight(DiffuseLight::with_texture(Arc::new(Checker(warm, cool))));
+
+        assert_eq!(light.emitted(&hit_at(0.1, 0.1, &light)), warm);
+        assert_eq!(light.emitted(&hit_at(0.35, 0.1, &light)), cool);
+        assert_eq!(light.emitted(&hit_at(0.35, 0.35, &light)), warm);
+        assert_eq!(light.emitted(&hit_at(0.1, 0.3, &light)), cool);
+
+        // A plain color is the same everywhere
+        let plain = Material::DiffuseLight(DiffuseLight::new(warm));
+        assert_eq!(plain.emitted(&hit_at(0.1, 0.1, &plain)), warm);
+        assert_eq!(plain.emitted(&hit_at(0.9, 0.35, &plain)), warm);
+    }
+
+    #[test]
+    fn materials_share_one_image_texture() {
+        // 2x1 image: red on the left, green on the right
+        let texture = Arc::new(ImageTexture::new(2, 1, vec![255, 0, 0, 0, 255, 0]));
+
+        let first = Material::Lambertian(Lambertian::with_texture(texture.clone()));
+        let second = Material::Lambertian(Lambertian::with_texture(texture.clone()));
+
+        assert_eq!(Arc::strong_count(&texture), 3);
+
+        let ray = Ray::new(
+            Vector::new(0.0, 1.0, 0.0, VectorType::Point),
+            Vector::new(0.0, -1.0, 0.0, VectorType::Vector),
+        );
+        let red = Vector::new(1.0, 0.0, 0.0, VectorType::Color);
+        let green = Vector::new(0.0, 1.0, 0.0, VectorType::Color);
+        let mut rng = rand::thread_rng();
+
+        let (_, first_left) = first
+            .scatter(&ray, &hit_at(0.25, 0.5, &first), &mut rng)
+            .unwrap();
+        let (_, second_left) = second
+            .scatter(&ray, &hit_at(0.25, 0.5, &second), &mut rng)
+            .unwrap();
+        let (_, second_right) = second
+            .scatter(&ray, &hit_at(0.75, 0.5, &second), &mut rng)
+            .unwrap();
+
+        assert_eq!(first_left, red);
+        assert_eq!(second_left, red);
+        assert_eq!(second_right, green);
+    }
+
+    #[test]
+    fn phong_highlight_peaks_along_reflected_light() {
+        let white = Vector::new(1.0, 1.0, 1.0, VectorType::Color);
+        let normal = Vector::new(0.0, 1.0, 0.0, VectorType::Vector);
+        let to_light = Vector::new(1.0, 1.0, 0.0, VectorType::Vector).get_unit_vector();
+        let mirrored = Vector::new(-1.0, 1.0, 0.0, VectorType::Vector).get_unit_vector();
+
+        for phong in [
+            Phong::new(white, 0.0, 0.0, 0.8, 32.0),
+            Phong::blinn_phong(white, 0.0, 0.0, 0.8, 32.0),
+        ] {
+            let peak = phong.evaluate(&normal, &to_light, &mirrored, white);
+            assert!(fuzzy_equal(peak.data.0, 0.8));
+
+            for view in [
+                Vector::new(-1.0, 1.5, 0.0, VectorType::Vector),
+                Vector::new(-1.5, 1.0, 0.0, VectorType::Vector),
+                Vector::new(-1.0, 1.0, 0.3, VectorType::Vector),
+                Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            ] {
+                let off_peak = phong.evaluate(&normal, &to_light, &view.get_unit_vector(), white);
+                assert!(off_peak.data.0 < peak.data.0);
+            }
+        }
+    }
+
+    #[test]
+    fn phong_is_dark_when_lit_from_behind() {
+        let white = Vector::new(1.0, 1.0, 1.0, VectorType::Color);
+        let phong = Phong::new(white, 0.1, 0.9, 0.5, 8.0);
+        let normal = Vector::new(0.0, 1.0, 0.0, VectorType::Vector);
+        let below = Vector::new(0.0, -1.0, 0.0, VectorType::Vector);
+
+        let color = phong.evaluate(&normal, &below, &normal, white);
+        assert_eq!(color, Vector::new(0.0, 0.0, 0.0, VectorType::Color));
+    }
+
+    #[test]
+    fn phong_skips_lights_on_the_surface() {
+        let white = Vector::new(1.0, 1.0, 1.0, VectorType::Color);
+        let phong = Phong::new(white, 0.1, 0.9, 0.5, 8.0);
+        let material = Material::gray();
+        let hit = hit_at(0.5, 0.5, &material);
+        let ray = Ray::new(
+            Vector::new(0.0, 1.0, 0.0, VectorType::Point),
+            Vector::new(0.0, -1.0, 0.0, VectorType::Vector),
+        );
+        let mut world = World::new();
+        world.add_point_light(crate::light::PointLight::new(hit.point, white));
+
+        assert_eq!(phong.shade(&ray, &hit, &world), 0.1 * white);
+    }
+
+    #[test]
+    fn lambertian_pdf_is_the_cosine_term() {
+        let gray = Material::gray();
+        let hit = hit_at(0.5, 0.5, &gray);
+        let ray = Ray::new(
+            Vector::new(0.0, 1.0, 1.0, VectorType::Point),
+            Vector::new(0.0, -1.0, -1.0, VectorType::Vector),
+        );
+        let towards =
+            |x: Scalar, y: Scalar| Ray::new(hit.point, Vector::new(x, y, 0.0, VectorType::Vector));
+        let pi = crate::vector::consts::PI;
+
+        assert!(fuzzy_equal(
+            gray.scattering_pdf(&ray, &hit, &towards(0.0, 2.0)),
+            1.0 / pi
+        ));
+        assert!(fuzzy_equal(
+            gray.scattering_pdf(&ray, &hit, &towards(1.0, 1.0)),
+            (pi / 4.0).cos() / pi
+        ));
+        assert_eq!(gray.scattering_pdf(&ray, &hit, &towards(1.0, -1.0)), 0.0);
+        assert!(!gray.is_specular());
+
+        let mirror = Material::Metal(Metal::new(
+            Vector::new(1.0, 1.0, 1.0, VectorType::Color),
+            0.0,
+        ));
+        assert_eq!(mirror.scattering_pdf(&ray, &hit, &towards(0.0, 1.0)), 0.0);
+        assert!(mirror.is_specular());
+    }
+
+    #[test]
+    fn lambertian_scatters_with_mean_cosine_two_thirds() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let gray = Material::gray();
+        let hit = hit_at(0.5, 0.5, &gray);
+        let ray = Ray::new(
+            Vector::new(0.0, 1.0, 0.0, VectorType::Point),
+            Vector::new(0.0, -1.0, 0.0, VectorType::Vector),
+        );
+        let mut rng = StdRng::seed_from_u64(4);
+
+        // E[cos θ] under the density cos θ / π over the hemisphere
+        let n = 20000;
+        let total: Scalar = (0..n)
+            .map(|_| {
+                let scattered = gray.scatter(&ray, &hit, &mut rng).unwrap().0.unwrap();
+                scattered.direction.get_unit_vector().data.1
+            })
+            .sum();
+
+        assert!((total / n as Scalar - 2.0 / 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn seeded_dielectric_scatter_is_reproducible() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let glass = Material::Dielectric(Dielectric::new(1.5));
+        let hit = hit_at(0.5, 0.5, &glass);
+        // Grazing incidence, where reflection and refraction are about equally likely
+        let ray = Ray::new(
+            Vector::new(-1.0, 0.2, 0.0, VectorType::Point),
+            Vector::new(1.0, -0.2, 0.0, VectorType::Vector),
+        );
+
+        let scatter_all = |seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..64)
+                .map(|_| {
+                    glass
+                        .scatter(&ray, &hit, &mut rng)
+                        .unwrap()
+                        .0
+                        .unwrap()
+                        .direction
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let first = scatter_all(3);
+        let second = scatter_all(3);
+
+        assert!(first.iter().zip(&second).all(|(a, b)| a.data == b.data));
+        // Both outcomes occur, so the seed actually decides between them
+        assert!(first.iter().any(|d| d.data.1 > 0.0));
+        assert!(first.iter().any(|d| d.data.1 < 0.0));
+    }
+
+    #[test]
+    fn fuzzy_metal_never_scatters_into_the_surface() {
+        let material = Material::Metal(Metal::new(
+            Vector::new(0.8, 0.8, 0.8, VectorType::Color),
+            1.0,
+        ));
+        let hit = hit_at(0.5, 0.5, &material);
+        let mut rng = rand::thread_rng();
+        let mut scattered = 0;
+
+        // Steep and grazing incidence onto the upward facing surface
+        for direction in [
+            Vector::new(0.0, -1.0, 0.0, VectorType::Vector),
+            Vector::new(1.0, -0.05, 0.0, VectorType::Vector),
+        ] {
+            let ray = Ray::new(Vector::new(0.0, 1.0, 0.0, VectorType::Point), direction);
+            for _ in 0..1000 {
+                if let Some((Some(r), _)) = material.scatter(&ray, &hit, &mut rng) {
+                    assert!(is_valid_scatter(&r, &hit.normal));
+                    scattered += 1;
+                }
+            }
+        }
+
+        // Resampling the fuzz keeps most grazing rays alive
+        assert!(scattered > 1800);
+    }
+
+    #[test]
+    fn equal_roughness_matches_isotropic_fuzz() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let albedo = Vector::new(0.8, 0.8, 0.8, VectorType::Color);
+        let isotropic = Material::Metal(Metal::new(albedo, 0.4));
+        let anisotropic = Material::Metal(Metal::anisotropic(albedo, 0.4, 0.4));
+        let ray = Ray::new(
+            Vector::new(-1.0, 1.0, 0.0, VectorType::Point),
+            Vector::new(1.0, -1.0, 0.2, VectorType::Vector),
+        );
+        // The plain hit has no tangent frame, this one has a frame turned about the
+        // normal
+        let plain = hit_at(0.5, 0.5, &isotropic);
+        let framed = HitRecord {
+            tangent: Some(Vector::new(0.6, 0.0, 0.8, VectorType::Vector)),
+            bitangent: Some(Vector::new(0.8, 0.0, -0.6, VectorType::Vector)),
+            ..hit_at(0.5, 0.5, &anisotropic)
+        };
+
+        for seed in 0..50 {
+            let scatter = |material: &Material, hit: &HitRecord| {
+                let (scattered, _) = material
+                    .scatter(&ray, hit, &mut StdRng::seed_from_u64(seed))
+                    .unwrap();
+                scattered.unwrap().direction
+            };
+
+            assert_eq!(scatter(&isotropic, &plain), scatter(&anisotropic, &framed));
+        }
+    }
+
+    #[test]
+    fn brushed_metal_spreads_along_u() {
+        let metal = Material::Metal(Metal::anisotropic(
+            Vector::new(0.8, 0.8, 0.8, VectorType::Color),
+            0.8,
+            0.05,
+        ));
+        let hit = HitRecord {
+            tangent: Some(Vector::new(1.0, 0.0, 0.0, VectorType::Vector)),
+            bitangent: Some(Vector::new(0.0, 0.0, -1.0, VectorType::Vector)),
+            ..hit_at(0.5, 0.5, &metal)
+        };
+        let ray = Ray::new(
+            Vector::new(0.0, 1.0, 0.0, VectorType::Point),
+            Vector::new(0.0, -1.0, 0.0, VectorType::Vector),
+        );
+        let mut rng = rand::thread_rng();
+
+        let (mut along, mut across): (Scalar, Scalar) = (0.0, 0.0);
+        for _ in 0..500 {
+            if let Some((Some(r), _)) = metal.scatter(&ray, &hit, &mut rng) {
+                along = along.max(r.direction.data.0.abs());
+                across = across.max(r.direction.data.2.abs());
+            }
+        }
+
+        assert!(along > 0.5);
+        assert!(across <= 0.05);
+    }
+
+    #[test]
+    fn coat_reflects_more_at_grazing_angles() {
+        let base = Vector::new(0.2, 0.05, 0.05, VectorType::Color);
+        let paint = Material::Coated(Coated::new(
+            Material::Lambertian(Lambertian::new(base)),
+            1.5,
+            0.0,
+        ));
+        let hit = hit_at(0.5, 0.5, &paint);
+        let mut rng = rand::thread_rng();
+
+        // Fraction of scatters off the clear coat rather than the red base
+        let mut coat_fraction = |direction: Vector| {
+            let ray = Ray::new(Vector::new(0.0, 1.0, 0.0, VectorType::Point), direction);
+            let coat = (0..2000)
+                .filter(|_| match paint.scatter(&ray, &hit, &mut rng) {
+                    Some((_, attenuation)) => attenuation != base,
+                    None => false,
+                })
+                .count();
+            coat as Scalar / 2000.0
+        };
+
+        let normal = coat_fraction(Vector::new(0.0, -1.0, 0.0, VectorType::Vector));
+        let grazing = coat_fraction(Vector::new(1.0, -0.05, 0.0, VectorType::Vector));
+
+        // Schlick gives 4% head on and about 78% at this angle
+        assert!(normal < 0.1);
+        assert!(grazing > 0.6);
+    }
+}
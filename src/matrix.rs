@@ -0,0 +1,409 @@
+use crate::quaternion::Quaternion;
+use crate::utils;
+use crate::vector::{Scalar, Vector, VectorType};
+use std::ops::Mul;
+
+// A 4x4 matrix acting on column vectors, stored row by row. Only affine transforms
+// are expected, so the bottom row is normally 0 0 0 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[Scalar; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn new(m: [[Scalar; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        Self::scaling(1.0, 1.0, 1.0)
+    }
+
+    pub fn translation(x: Scalar, y: Scalar, z: Scalar) -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, x],
+            [0.0, 1.0, 0.0, y],
+            [0.0, 0.0, 1.0, z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(x: Scalar, y: Scalar, z: Scalar) -> Self {
+        Self::new([
+            [x, 0.0, 0.0, 0.0],
+            [0.0, y, 0.0, 0.0],
+            [0.0, 0.0, z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Rotations by `degrees` counter-clockwise when looking down the axis towards the
+    // origin
+    pub fn rotation_x(degrees: Scalar) -> Self {
+        let (sin, cos) = utils::degrees_to_radians(degrees).sin_cos();
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, cos, -sin, 0.0],
+            [0.0, sin, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotation_y(degrees: Scalar) -> Self {
+        let (sin, cos) = utils::degrees_to_radians(degrees).sin_cos();
+        Self::new([
+            [cos, 0.0, sin, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-sin, 0.0, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotation_z(degrees: Scalar) -> Self {
+        let (sin, cos) = utils::degrees_to_radians(degrees).sin_cos();
+        Self::new([
+            [cos, -sin, 0.0, 0.0],
+            [sin, cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Self::new(m)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting; `None` for singular matrices
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inverse = Self::identity().m;
+        // Pivots this small are rounding noise in a singular matrix
+        let largest = self
+            .m
+            .iter()
+            .flatten()
+            .fold(0.0 as Scalar, |max, v| max.max(v.abs()));
+        let tolerance = 16.0 * Scalar::EPSILON * largest;
+
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap();
+            if a[pivot][column].abs() <= tolerance {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = a[row][column];
+                for j in 0..4 {
+                    a[row][j] -= factor * a[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+
+        Some(Self::new(inverse))
+    }
+
+    // Entry by entry blend from `self` at `f` = 0 to `other` at 1. Every point it
+    // maps lies on the line between where the two matrices take it, which suits
+    // scales and translations; see `interpolate` for matrices that turn.
+    pub fn lerp(&self, other: &Self, f: Scalar) -> Self {
+        let mut m = self.m;
+        for (row, other_row) in m.iter_mut().zip(other.m.iter()) {
+            for (value, other_value) in row.iter_mut().zip(other_row.iter()) {
+                *value += f * (other_value - *value);
+            }
+        }
+        Self::new(m)
+    }
+
+    // The transform part of the way from `self` at `f` = 0 to `other` at 1, moving
+    // rigidly: the translations and the scales (with any shear) are blended entry by
+    // entry, while the rotation turns at a constant rate about a fixed axis. A half
+    // turn is made one way or the other rather than squashing the object flat.
+    pub fn interpolate(&self, other: &Self, f: Scalar) -> Self {
+        let (t0, r0, s0) = self.decompose();
+        let (t1, r1, s1) = other.decompose();
+        let t = t0 + f * (t1 - t0);
+
+        Self::translation(t.data.0, t.data.1, t.data.2)
+            * r0.slerp(&r1, f).to_matrix()
+            * s0.lerp(&s1, f)
+    }
+
+    // `self` as translation * rotation * stretch, where the stretch holds the scale
+    // and any shear. The rotation is the one nearest the linear part (its polar
+    // decomposition), found by averaging a matrix with its inverse transpose until
+    // that settles. Mirror images keep the flip in the stretch; singular matrices
+    // keep everything there.
+    pub(crate) fn decompose(&self) -> (Vector, Quaternion, Self) {
+        let translation = Vector::new(self.m[0][3], self.m[1][3], self.m[2][3], VectorType::Vector);
+        let mut linear = *self;
+        for row in linear.m.iter_mut().take(3) {
+            row[3] = 0.0;
+        }
+        linear.m[3] = [0.0, 0.0, 0.0, 1.0];
+        if linear.inverse().is_none() {
+            return (translation, Quaternion::identity(), linear);
+        }
+
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let Some(inverse_transpose) = rotation.transpose().inverse() else {
+                break;
+            };
+            let next = rotation.lerp(&inverse_transpose, 0.5);
+            let change = next
+                .m
+                .iter()
+                .flatten()
+                .zip(rotation.m.iter().flatten())
+                .fold(0.0 as Scalar, |max, (a, b)| max.max((a - b).abs()));
+            rotation = next;
+            if change < 16.0 * Scalar::EPSILON {
+                break;
+            }
+        }
+        if rotation.determinant3() < 0.0 {
+            rotation = rotation * Self::scaling(-1.0, -1.0, -1.0);
+        }
+
+        let stretch = rotation.transpose() * linear;
+        (translation, Quaternion::from_matrix(&rotation), stretch)
+    }
+
+    // Determinant of the upper 3x3, the linear part of an affine transform
+    fn determinant3(&self) -> Scalar {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // Transforms a position, including the translation
+    pub fn mul_point(&self, p: &Vector) -> Vector {
+        let (x, y, z) = p.data;
+        let row = |r: [Scalar; 4]| r[0] * x + r[1] * y + r[2] * z + r[3];
+
+        Vector::new(
+            row(self.m[0]),
+            row(self.m[1]),
+            row(self.m[2]),
+            VectorType::Point,
+        )
+    }
+
+    // Transforms a direction, ignoring the translation
+    pub fn mul_vector(&self, v: &Vector) -> Vector {
+        let (x, y, z) = v.data;
+        let row = |r: [Scalar; 4]| r[0] * x + r[1] * y + r[2] * z;
+
+        Vector::new(row(self.m[0]), row(self.m[1]), row(self.m[2]), v.data_type)
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Self;
+
+    // `self * other` applies `other` first
+    fn mul(self, other: Self) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Self::new(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::fuzzy_equal;
+
+    fn assert_matrix_eq(actual: &Matrix4, expected: &Matrix4) {
+        for (actual_row, expected_row) in actual.m.iter().zip(expected.m.iter()) {
+            for (a, e) in actual_row.iter().zip(expected_row.iter()) {
+                assert!(fuzzy_equal(*a, *e), "{:?} != {:?}", actual, expected);
+            }
+        }
+    }
+
+    fn sample_matrix() -> Matrix4 {
+        Matrix4::new([
+            [1.0, 2.0, 3.0, 4.0],
+            [5.0, 6.0, 7.0, 8.0],
+            [9.0, 8.0, 7.0, 6.0],
+            [5.0, 4.0, 3.0, 2.0],
+        ])
+    }
+
+    #[test]
+    fn identity_times_matrix() {
+        let m = sample_matrix();
+
+        assert_eq!(Matrix4::identity() * m, m);
+        assert_eq!(m * Matrix4::identity(), m);
+    }
+
+    #[test]
+    fn multiply_two_matrices() {
+        let other = Matrix4::new([
+            [-2.0, 1.0, 2.0, 3.0],
+            [3.0, 2.0, 1.0, -1.0],
+            [4.0, 3.0, 6.0, 5.0],
+            [1.0, 2.0, 7.0, 8.0],
+        ]);
+        let expected = Matrix4::new([
+            [20.0, 22.0, 50.0, 48.0],
+            [44.0, 54.0, 114.0, 108.0],
+            [40.0, 58.0, 110.0, 102.0],
+            [16.0, 26.0, 46.0, 42.0],
+        ]);
+
+        assert_eq!(sample_matrix() * other, expected);
+    }
+
+    #[test]
+    fn transpose_matrix() {
+        let expected = Matrix4::new([
+            [1.0, 5.0, 9.0, 5.0],
+            [2.0, 6.0, 8.0, 4.0],
+            [3.0, 7.0, 7.0, 3.0],
+            [4.0, 8.0, 6.0, 2.0],
+        ]);
+
+        assert_eq!(sample_matrix().transpose(), expected);
+        assert_eq!(Matrix4::identity().transpose(), Matrix4::identity());
+    }
+
+    #[test]
+    fn translation_moves_points() {
+        let matrix = Matrix4::translation(5.0, -3.0, 2.0);
+        let point = Vector::new(-3.0, 4.0, 5.0, VectorType::Point);
+
+        assert_eq!(
+            matrix.mul_point(&point),
+            Vector::new(2.0, 1.0, 7.0, VectorType::Point)
+        );
+    }
+
+    #[test]
+    fn translation_does_not_move_vectors() {
+        let matrix = Matrix4::translation(5.0, -3.0, 2.0);
+        let vector = Vector::new(-3.0, 4.0, 5.0, VectorType::Vector);
+
+        assert_eq!(matrix.mul_vector(&vector), vector);
+    }
+
+    #[test]
+    fn scaling_points_and_vectors() {
+        let matrix = Matrix4::scaling(2.0, 3.0, 4.0);
+
+        assert_eq!(
+            matrix.mul_point(&Vector::new(-4.0, 6.0, 8.0, VectorType::Point)),
+            Vector::new(-8.0, 18.0, 32.0, VectorType::Point)
+        );
+        assert_eq!(
+            matrix.mul_vector(&Vector::new(-4.0, 6.0, 8.0, VectorType::Vector)),
+            Vector::new(-8.0, 18.0, 32.0, VectorType::Vector)
+        );
+    }
+
+    #[test]
+    fn quarter_turns_cycle_the_axes() {
+        let x = Vector::new(1.0, 0.0, 0.0, VectorType::Vector);
+        let y = Vector::new(0.0, 1.0, 0.0, VectorType::Vector);
+        let z = Vector::new(0.0, 0.0, 1.0, VectorType::Vector);
+
+        assert_eq!(Matrix4::rotation_x(90.0).mul_vector(&y), z);
+        assert_eq!(Matrix4::rotation_y(90.0).mul_vector(&z), x);
+        assert_eq!(Matrix4::rotation_z(90.0).mul_vector(&x), y);
+    }
+
+    #[test]
+    fn inverse_times_matrix_is_identity() {
+        let matrix = Matrix4::translation(1.0, -2.0, 3.0)
+            * Matrix4::rotation_y(30.0)
+            * Matrix4::scaling(2.0, 0.5, 1.0);
+        let inverse = matrix.inverse().unwrap();
+
+        assert_matrix_eq(&(inverse * matrix), &Matrix4::identity());
+        assert_matrix_eq(&(matrix * inverse), &Matrix4::identity());
+    }
+
+    #[test]
+    fn inverse_of_a_general_matrix() {
+        let matrix = Matrix4::new([
+            [-5.0, 2.0, 6.0, -8.0],
+            [1.0, -5.0, 1.0, 8.0],
+            [7.0, 7.0, -6.0, -7.0],
+            [1.0, -3.0, 7.0, 4.0],
+        ]);
+
+        let inverse = matrix.inverse().unwrap();
+
+        assert!(fuzzy_equal(inverse.m[0][0], 116.0 / 532.0));
+        assert!(fuzzy_equal(inverse.m[3][2], -160.0 / 532.0));
+        assert_matrix_eq(&(inverse * matrix), &Matrix4::identity());
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let matrix = Matrix4::rotation_x(20.0) * Matrix4::translation(0.0, 4.0, 1.0);
+        let point = Vector::new(0.3, 1.2, -4.0, VectorType::Point);
+
+        let inverse = matrix.inverse().unwrap();
+
+        assert_eq!(inverse.mul_point(&matrix.mul_point(&point)), point);
+    }
+
+    #[test]
+    fn singular_matrix_has_no_inverse() {
+        assert!(Matrix4::scaling(1.0, 0.0, 1.0).inverse().is_none());
+        assert!(sample_matrix().inverse().is_none());
+    }
+
+    #[test]
+    fn interpolation_keeps_the_ends_and_stays_rigid() {
+        let start = Matrix4::translation(1.0, 2.0, 3.0) * Matrix4::scaling(2.0, 1.0, 1.0);
+        let end = Matrix4::translation(-1.0, 0.0, 3.0)
+            * Matrix4::rotation_x(180.0)
+            * Matrix4::scaling(2.0, 1.0, 1.0);
+
+        assert_matrix_eq(&start.interpolate(&end, 0.0), &start);
+        assert_matrix_eq(&end.interpolate(&start, 0.0), &end);
+        assert_matrix_eq(&start.interpolate(&end, 1.0), &end);
+
+        // Halfway the scale is kept and the turn is a quarter one about x
+        let halfway = start.interpolate(&end, 0.5);
+        let (translation, rotation, stretch) = halfway.decompose();
+        assert_eq!(translation, Vector::new(0.0, 1.0, 3.0, VectorType::Vector));
+        assert_matrix_eq(&stretch, &Matrix4::scaling(2.0, 1.0, 1.0));
+        let y = rotation
+            .to_matrix()
+            .mul_vector(&Vector::new(0.0, 1.0, 0.0, VectorType::Vector));
+        assert!(fuzzy_equal(y.data.1, 0.0));
+        assert!(fuzzy_equal(y.data.2.abs(), 1.0));
+        assert!(halfway.inverse().is_some());
+    }
+}
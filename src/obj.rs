@@ -0,0 +1,270 @@
+use crate::material::Material;
+use crate::triangle::Triangle;
+use crate::vector::{Scalar, Vector, VectorType};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+// Which axis points up in the file. The renderer is Y-up.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    // As exported by Blender, 3ds Max and most CAD tools
+    Z,
+}
+
+// How vertices are brought from the file's conventions into the renderer's
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImportOptions {
+    pub up_axis: UpAxis,
+    // Uniform scale applied last, e.g. 0.01 for a file modelled in centimeters
+    pub scale: Scalar,
+    // Mirrors the mesh along Z, for files written with left-handed coordinates.
+    // Faces are wound the other way round too, so they keep facing out.
+    pub swap_handedness: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            up_axis: UpAxis::Y,
+            scale: 1.0,
+            swap_handedness: false,
+        }
+    }
+}
+
+impl ImportOptions {
+    // Where the vertex at `(x, y, z)` in the file ends up
+    pub fn transform(&self, x: Scalar, y: Scalar, z: Scalar) -> Vector {
+        // Z-up turns into Y-up by a quarter turn about X, which keeps the handedness
+        let (x, y, z) = match self.up_axis {
+            UpAxis::Y => (x, y, z),
+            UpAxis::Z => (x, z, -y),
+        };
+        let z = if self.swap_handedness { -z } else { z };
+
+        Vector::new(
+            self.scale * x,
+            self.scale * y,
+            self.scale * z,
+            VectorType::Point,
+        )
+    }
+}
+
+// The geometry of an OBJ file: its vertices and the triangles between them, as
+// indices into `vertices`. Polygons are split into fans of triangles. Texture
+// coordinates, normals, groups and materials are skipped.
+#[derive(Debug, Default)]
+pub struct ObjMesh {
+    pub vertices: Vec<Vector>,
+    pub faces: Vec<[usize; 3]>,
+}
+
+impl ObjMesh {
+    // A `Triangle` per face, all in `material`, ready to add to a `World`
+    pub fn triangles(&self, material: Material) -> Vec<Triangle> {
+        self.faces
+            .iter()
+            .map(|&[a, b, c]| {
+                Triangle::new(
+                    self.vertices[a],
+                    self.vertices[b],
+                    self.vertices[c],
+                    material.clone(),
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    // The line number, from 1, and what is wrong with it
+    Invalid(usize, String),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(error) => write!(f, "could not read OBJ file: {}", error),
+            ObjError::Invalid(line, message) => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<io::Error> for ObjError {
+    fn from(error: io::Error) -> Self {
+        ObjError::Io(error)
+    }
+}
+
+pub fn load_obj<P: AsRef<Path>>(path: P, options: &ImportOptions) -> Result<ObjMesh, ObjError> {
+    parse_obj(&std::fs::read_to_string(path)?, options)
+}
+
+pub fn parse_obj(text: &str, options: &ImportOptions) -> Result<ObjMesh, ObjError> {
+    let mut mesh = ObjMesh::default();
+
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let invalid = |message: &str| ObjError::Invalid(number, message.to_string());
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("v") => {
+                let mut coordinate = || -> Result<Scalar, ObjError> {
+                    tokens
+                        .next()
+                        .and_then(|token| token.parse().ok())
+                        .ok_or_else(|| invalid("a vertex needs three numbers"))
+                };
+                let (x, y, z) = (coordinate()?, coordinate()?, coordinate()?);
+                mesh.vertices.push(options.transform(x, y, z));
+            }
+            Some("f") => {
+                let corners = tokens
+                    .map(|token| vertex_index(token, mesh.vertices.len()))
+                    .collect::<Option<Vec<usize>>>()
+                    .ok_or_else(|| invalid("face refers to a missing vertex"))?;
+                if corners.len() < 3 {
+                    return Err(invalid("a face needs at least three corners"));
+                }
+
+                for i in 1..corners.len() - 1 {
+                    mesh.faces.push(if options.swap_handedness {
+                        [corners[0], corners[i + 1], corners[i]]
+                    } else {
+                        [corners[0], corners[i], corners[i + 1]]
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(mesh)
+}
+
+// The index into the vertices read so far of a face corner like `3`, `3/1` or
+// `-1//2`. Positive indices count from 1, negative ones back from the last vertex.
+fn vertex_index(token: &str, vertex_count: usize) -> Option<usize> {
+    let index: i64 = token.split('/').next()?.parse().ok()?;
+    let index = if index < 0 {
+        vertex_count as i64 + index
+    } else {
+        index - 1
+    };
+
+    (0..vertex_count as i64)
+        .contains(&index)
+        .then_some(index as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: &str = "\
+# A triangle standing up from the ground, in Z-up coordinates
+v 0 0 0
+v 1 0 0
+v 0 0 2
+f 1 2 3
+";
+
+    fn point(x: Scalar, y: Scalar, z: Scalar) -> Vector {
+        Vector::new(x, y, z, VectorType::Point)
+    }
+
+    #[test]
+    fn z_up_import_stands_the_triangle_up() {
+        let options = ImportOptions {
+            up_axis: UpAxis::Z,
+            ..ImportOptions::default()
+        };
+        let mesh = parse_obj(TRIANGLE, &options).unwrap();
+
+        assert_eq!(
+            mesh.vertices,
+            vec![
+                point(0.0, 0.0, 0.0),
+                point(1.0, 0.0, 0.0),
+                point(0.0, 2.0, 0.0)
+            ]
+        );
+        assert_eq!(mesh.faces, vec![[0, 1, 2]]);
+
+        // Y up in the file is into the screen once Z is up
+        let mesh = parse_obj("v 0 3 0\n", &options).unwrap();
+        assert_eq!(mesh.vertices, vec![point(0.0, 0.0, -3.0)]);
+    }
+
+    #[test]
+    fn imported_mesh_renders_where_the_options_put_it() {
+        use crate::camera::Camera;
+        use crate::material::Lambertian;
+        use crate::render::{render_albedo, RenderOptions};
+        use crate::world::World;
+
+        // A square wall two units along the file's Y axis, which Z-up import turns
+        // into the renderer's -Z, straight in front of the camera
+        let wall = "v -1 2 -1\nv 1 2 -1\nv 1 2 1\nv -1 2 1\nf 1 2 3 4\n";
+        let options = ImportOptions {
+            up_axis: UpAxis::Z,
+            ..ImportOptions::default()
+        };
+        let color = Vector::new(0.2, 0.4, 0.6, VectorType::Color);
+        let mut world = World::new();
+        let mesh = parse_obj(wall, &options).unwrap();
+        for triangle in mesh.triangles(Material::Lambertian(Lambertian::new(color))) {
+            world.add(triangle);
+        }
+        let camera = Camera::new(
+            point(0.0, 0.0, 0.0),
+            point(0.0, 0.0, -1.0),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+
+        // The wall covers the middle half of a 90 degree view
+        let image = render_albedo(8, 8, 4, &world, &camera, &RenderOptions::default());
+        for (x, y) in [(3, 3), (4, 4), (3, 4), (4, 3), (2, 5)] {
+            assert_eq!(image.get(x, y), color, "({}, {})", x, y);
+        }
+        assert_ne!(image.get(0, 0), color);
+        assert_ne!(image.get(7, 7), color);
+    }
+
+    #[test]
+    fn scale_and_handedness() {
+        let options = ImportOptions {
+            scale: 0.5,
+            swap_handedness: true,
+            ..ImportOptions::default()
+        };
+        let mesh = parse_obj("v 2 4 6\nv 0 0 0\nv 1 1 1\nf 1 -2 3/1/1\n", &options).unwrap();
+
+        assert_eq!(mesh.vertices[0], point(1.0, 2.0, -3.0));
+        // Mirroring turns the winding around
+        assert_eq!(mesh.faces, vec![[0, 2, 1]]);
+    }
+
+    #[test]
+    fn polygons_become_fans_and_bad_faces_are_reported() {
+        let square = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n";
+        let mesh = parse_obj(square, &ImportOptions::default()).unwrap();
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [0, 2, 3]]);
+
+        let result = parse_obj("v 0 0 0\nf 1 2 3\n", &ImportOptions::default());
+        assert!(matches!(result, Err(ObjError::Invalid(2, _))));
+    }
+}
@@ -0,0 +1,80 @@
+use crate::vector::{Scalar, Vector, VectorType};
+
+// A right-handed orthonormal frame (u, v, w), for working in coordinates local to a
+// surface or a direction, with w along it
+#[derive(Debug, Clone, Copy)]
+pub struct Onb {
+    pub u: Vector,
+    pub v: Vector,
+    pub w: Vector,
+}
+
+impl Onb {
+    // The frame around `n`, which need not be unit length. Uses the branchless
+    // construction of Duff et al. (2017), which stays accurate for every direction,
+    // straight down the z axis included.
+    pub fn from_w(n: &Vector) -> Self {
+        let w = n.get_unit_vector();
+        let (x, y, z) = w.data;
+        let sign = (1.0 as Scalar).copysign(z);
+        let a = -1.0 / (sign + z);
+        let b = x * y * a;
+
+        Self {
+            u: Vector::new(
+                1.0 + sign * x * x * a,
+                sign * b,
+                -sign * x,
+                VectorType::Vector,
+            ),
+            v: Vector::new(b, sign + y * y * a, -y, VectorType::Vector),
+            w,
+        }
+    }
+
+    // The direction with coordinates (`a`, `b`, `c`) along (u, v, w)
+    pub fn local(&self, a: Scalar, b: Scalar, c: Scalar) -> Vector {
+        a * self.u + b * self.v + c * self.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::fuzzy_equal;
+
+    #[test]
+    fn axes_are_orthonormal_and_right_handed() {
+        let normals = [
+            (0.0, 0.0, 1.0),
+            (0.0, 0.0, -1.0),
+            (1.0, 0.0, 0.0),
+            (0.0, -1.0, 0.0),
+            (0.3, -0.4, 0.5),
+            (1e-9, 0.0, -1.0),
+            (-2.0, 7.0, 0.1),
+        ];
+
+        for (x, y, z) in normals {
+            let n = Vector::new(x, y, z, VectorType::Vector);
+            let onb = Onb::from_w(&n);
+
+            for axis in [onb.u, onb.v, onb.w] {
+                assert!(fuzzy_equal(axis.len(), 1.0));
+            }
+            assert!(fuzzy_equal(onb.u.dot(&onb.v), 0.0));
+            assert!(fuzzy_equal(onb.v.dot(&onb.w), 0.0));
+            assert!(fuzzy_equal(onb.w.dot(&onb.u), 0.0));
+            assert_eq!(onb.u.cross(&onb.v), onb.w);
+            assert_eq!(onb.w, n.get_unit_vector());
+        }
+    }
+
+    #[test]
+    fn local_coordinates_map_onto_the_axes() {
+        let onb = Onb::from_w(&Vector::new(0.0, 1.0, 1.0, VectorType::Vector));
+
+        assert_eq!(onb.local(0.0, 0.0, 2.0), 2.0 * onb.w);
+        assert_eq!(onb.local(1.0, -1.0, 0.0), onb.u - onb.v);
+    }
+}
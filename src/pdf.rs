@@ -0,0 +1,211 @@
+use crate::hit::Hittable;
+use crate::onb::Onb;
+use crate::vector::{consts::PI, Scalar, Vector};
+use crate::world::World;
+use rand::{Rng, RngCore};
+
+// A distribution of directions to scatter in. `value` is the density with respect to
+// solid angle of `generate` returning `direction`.
+pub trait Pdf {
+    fn value(&self, direction: &Vector) -> Scalar;
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vector;
+}
+
+// Directions above the surface in proportion to the cosine with its normal, which is
+// exactly what a Lambertian surface scatters
+pub struct CosinePdf {
+    frame: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: &Vector) -> Self {
+        Self {
+            frame: Onb::from_w(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vector) -> Scalar {
+        let cosine = direction.get_unit_vector().dot(&self.frame.w);
+        (cosine / PI).max(0.0)
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vector {
+        let (r1, r2): (Scalar, Scalar) = (rng.gen(), rng.gen());
+        let phi = 2.0 * PI * r1;
+        let radius = r2.sqrt();
+
+        self.frame.local(
+            radius * phi.cos(),
+            radius * phi.sin(),
+            (1.0 - r2).max(0.0).sqrt(),
+        )
+    }
+}
+
+// Directions from `origin` towards the objects of `world` at `lights`, picking each
+// light equally often
+pub struct HittablePdf<'a> {
+    world: &'a World,
+    lights: &'a [usize],
+    origin: Vector,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(world: &'a World, lights: &'a [usize], origin: Vector) -> Self {
+        assert!(!lights.is_empty(), "need at least one light to sample");
+        Self {
+            world,
+            lights,
+            origin,
+        }
+    }
+
+    fn light(&self, index: usize) -> &dyn Hittable {
+        self.world.objects()[self.lights[index]].as_ref()
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vector) -> Scalar {
+        let total: Scalar = (0..self.lights.len())
+            .map(|index| self.light(index).pdf_value(&self.origin, direction))
+            .sum();
+
+        total / self.lights.len() as Scalar
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vector {
+        let index = rng.gen_range(0..self.lights.len());
+        self.light(index).random_with(rng, &self.origin)
+    }
+}
+
+// Draws from either of two distributions with equal probability. Its density is the
+// average of theirs, so every direction either one can produce is weighted correctly.
+pub struct MixturePdf<'a> {
+    pdfs: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(first: &'a dyn Pdf, second: &'a dyn Pdf) -> Self {
+        Self {
+            pdfs: [first, second],
+        }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vector) -> Scalar {
+        0.5 * self.pdfs[0].value(direction) + 0.5 * self.pdfs[1].value(direction)
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vector {
+        if rng.gen::<bool>() {
+            self.pdfs[0].generate(rng)
+        } else {
+            self.pdfs[1].generate(rng)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{DiffuseLight, Material};
+    use crate::sphere::Sphere;
+    use crate::vector::{fuzzy_equal, VectorType};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn up() -> Vector {
+        Vector::new(0.0, 1.0, 0.0, VectorType::Vector)
+    }
+
+    fn light() -> Sphere {
+        Sphere::new(
+            Vector::new(0.0, 4.0, -1.0, VectorType::Point),
+            1.0,
+            Material::DiffuseLight(DiffuseLight::new(Vector::new(
+                1.0,
+                1.0,
+                1.0,
+                VectorType::Color,
+            ))),
+        )
+    }
+
+    #[test]
+    fn mixture_value_is_the_average() {
+        let mut world = World::new();
+        world.add(light());
+        let cosine = CosinePdf::new(&up());
+        let toward_light =
+            HittablePdf::new(&world, &[0], Vector::new(0.0, 0.0, 0.0, VectorType::Point));
+        let mixture = MixturePdf::new(&cosine, &toward_light);
+
+        let directions = [
+            Vector::new(0.0, 4.0, -1.0, VectorType::Vector),
+            Vector::new(1.0, 1.0, 0.0, VectorType::Vector),
+            Vector::new(0.2, -1.0, 0.3, VectorType::Vector),
+        ];
+        for direction in directions {
+            let expected = (cosine.value(&direction) + toward_light.value(&direction)) / 2.0;
+            assert!(fuzzy_equal(mixture.value(&direction), expected));
+        }
+    }
+
+    #[test]
+    fn cosine_samples_stay_above_the_surface() {
+        let pdf = CosinePdf::new(&up());
+        let mut rng = StdRng::seed_from_u64(2);
+
+        for _ in 0..500 {
+            let direction = pdf.generate(&mut rng);
+
+            assert!(fuzzy_equal(direction.len(), 1.0));
+            assert!(direction.data.1 >= 0.0);
+            assert!(pdf.value(&direction) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn mixture_samples_have_positive_density() {
+        let mut world = World::new();
+        world.add(light());
+        let cosine = CosinePdf::new(&up());
+        let toward_light =
+            HittablePdf::new(&world, &[0], Vector::new(0.0, 0.0, 0.0, VectorType::Point));
+        let mixture = MixturePdf::new(&cosine, &toward_light);
+        let mut rng = StdRng::seed_from_u64(5);
+
+        for _ in 0..500 {
+            let direction = mixture.generate(&mut rng);
+            assert!(mixture.value(&direction) > 0.0);
+        }
+    }
+
+    #[test]
+    fn hittable_pdf_samples_only_the_listed_lights() {
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, -4.0, -1.0, VectorType::Point),
+            1.0,
+            Material::gray(),
+        ));
+        world.add(light());
+        let toward_light =
+            HittablePdf::new(&world, &[1], Vector::new(0.0, 0.0, 0.0, VectorType::Point));
+        let mut rng = StdRng::seed_from_u64(3);
+
+        assert_eq!(
+            toward_light.value(&Vector::new(0.0, -4.0, -1.0, VectorType::Vector)),
+            0.0
+        );
+        for _ in 0..100 {
+            assert!(toward_light.generate(&mut rng).data.1 > 0.0);
+        }
+    }
+}
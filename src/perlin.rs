@@ -0,0 +1,121 @@
+use crate::vector::{Scalar, Vector};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+const POINT_COUNT: usize = 256;
+
+// Gradient (Perlin) noise: smooth pseudo-random values in about [-1, 1] that vary
+// over a scale of one unit and are 0 at every integer lattice point. The same seed
+// always gives the same noise.
+pub struct Perlin {
+    gradients: Vec<Vector>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| Vector::random_with(&mut rng, -1.0, 1.0).get_unit_vector())
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+        let (perm_x, perm_y, perm_z) = (permutation(), permutation(), permutation());
+
+        Self {
+            gradients,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    pub fn noise(&self, p: &Vector) -> Scalar {
+        let (x, y, z) = p.data;
+        let (i, j, k) = (x.floor(), y.floor(), z.floor());
+        let (u, v, w) = (x - i, y - j, z - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+
+        // Hermite smoothing hides the lattice
+        let (uu, vv, ww) = (
+            u * u * (3.0 - 2.0 * u),
+            v * v * (3.0 - 2.0 * v),
+            w * w * (3.0 - 2.0 * w),
+        );
+
+        let mut total = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.perm_x[wrap(i + di)]
+                        ^ self.perm_y[wrap(j + dj)]
+                        ^ self.perm_z[wrap(k + dk)]];
+                    let (fi, fj, fk) = (di as Scalar, dj as Scalar, dk as Scalar);
+                    let offset = Vector::new(u - fi, v - fj, w - fk, p.data_type);
+
+                    total += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * gradient.dot(&offset);
+                }
+            }
+        }
+
+        total
+    }
+}
+
+fn wrap(i: i64) -> usize {
+    i.rem_euclid(POINT_COUNT as i64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::VectorType;
+
+    fn point(x: Scalar, y: Scalar, z: Scalar) -> Vector {
+        Vector::new(x, y, z, VectorType::Point)
+    }
+
+    #[test]
+    fn zero_on_the_lattice() {
+        let perlin = Perlin::new(1);
+
+        for p in [
+            point(0.0, 0.0, 0.0),
+            point(3.0, -2.0, 7.0),
+            point(-5.0, 1.0, 0.0),
+        ] {
+            assert_eq!(perlin.noise(&p), 0.0);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_noise() {
+        let p = point(0.3, 1.7, -2.2);
+
+        assert_eq!(Perlin::new(4).noise(&p), Perlin::new(4).noise(&p));
+        assert_ne!(Perlin::new(4).noise(&p), Perlin::new(5).noise(&p));
+    }
+
+    #[test]
+    fn noise_is_smooth_and_bounded() {
+        let perlin = Perlin::new(2);
+
+        let mut previous = perlin.noise(&point(0.0, 0.5, 0.5));
+        for step in 1..1000 {
+            let value = perlin.noise(&point(step as Scalar * 0.01, 0.5, 0.5));
+
+            assert!(value.abs() <= 1.5);
+            assert!((value - previous).abs() < 0.05);
+            previous = value;
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use crate::camera::Camera;
+use crate::hit::HitRecord;
+use crate::image::Image;
+use crate::material::Scatterable;
+use crate::pdf::{CosinePdf, Pdf};
+use crate::ray::Ray;
+use crate::render::{render_path_traced, RenderOptions};
+use crate::utils::{seed_for, Caustics};
+use crate::vector::{consts::PI, Scalar, Vector, VectorType};
+use crate::world::World;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+// Bounces a photon may take through glass and mirrors before it is given up on
+const MAX_PHOTON_BOUNCES: usize = 16;
+
+const BLACK: Vector = Vector {
+    data: (0.0, 0.0, 0.0),
+    data_type: VectorType::Color,
+};
+
+// Light that reached a diffuse surface through at least one specular bounce
+#[derive(Debug, Clone, Copy)]
+struct Photon {
+    position: Vector,
+    power: Vector,
+    // Unit direction of travel as the photon arrived
+    incoming: Vector,
+}
+
+// A caustic photon map: photons traced from the lights through specular surfaces
+// and stored where they first land on a diffuse one, in a kd-tree for looking up
+// the photons around a point. Light focused through glass or off mirrors takes path
+// tracing very many samples to find, but collects here in a single pass.
+//
+// Photons are only emitted from the world's lights that can sample their surface
+// (spheres and quads); light from the sky that is focused onto diffuse surfaces is
+// not captured.
+pub struct PhotonMap {
+    // Laid out as an implicit kd-tree: the middle photon of every range splits it
+    // along `axes` at the same index
+    photons: Vec<Photon>,
+    axes: Vec<usize>,
+    // Photons within this distance of a point contribute to its estimate
+    radius: Scalar,
+}
+
+impl PhotonMap {
+    // Traces `photons` photons, split evenly between the lights. A smaller `radius`
+    // gives sharper caustics that need more photons to be smooth.
+    pub fn build(world: &World, photons: u32, radius: Scalar, seed: u64) -> Self {
+        let mut stored: Vec<Photon> = (0..photons)
+            .into_par_iter()
+            .filter_map(|i| {
+                let mut rng = StdRng::seed_from_u64(seed_for(seed, i, 0, 0));
+                trace_photon(world, photons, &mut rng)
+            })
+            .collect();
+
+        let mut axes = vec![0; stored.len()];
+        build_tree(&mut stored, &mut axes);
+
+        Self {
+            photons: stored,
+            axes,
+            radius,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    // Caustic light arriving at `point` on a surface facing `normal`, per unit area:
+    // the power of the photons within `radius` that came in from the front, spread
+    // over the disc they were gathered from
+    pub fn irradiance(&self, point: &Vector, normal: &Vector) -> Vector {
+        let mut power = BLACK;
+        gather(
+            &self.photons,
+            &self.axes,
+            point,
+            self.radius * self.radius,
+            &mut |photon| {
+                if photon.incoming.dot(normal) < 0.0 {
+                    power = power + photon.power;
+                }
+            },
+        );
+
+        power / (PI * self.radius * self.radius)
+    }
+}
+
+// Emits one photon from a random light and follows it through specular bounces.
+// Its power is the light's total output shared between all `count` photons.
+fn trace_photon(world: &World, count: u32, rng: &mut StdRng) -> Option<Photon> {
+    if world.lights.is_empty() {
+        return None;
+    }
+    let light = &world.objects()[world.lights[rng.gen_range(0..world.lights.len())]];
+    let (surface, pdf) = light.sample_surface(rng)?;
+    if pdf <= 0.0 {
+        return None;
+    }
+
+    // Lambertian emitters give off π times their radiance per unit area from each
+    // side, and quads light both ways, so one side is picked at random
+    let normal = if rng.gen::<bool>() {
+        surface.normal
+    } else {
+        -surface.normal
+    };
+    let scale = 2.0 * PI * world.lights.len() as Scalar / (pdf * count as Scalar);
+    let mut power = scale * surface.material.emitted(&surface);
+    let mut ray = Ray::new(surface.point, CosinePdf::new(&normal).generate(rng));
+    let mut specular = false;
+
+    for _ in 0..MAX_PHOTON_BOUNCES {
+        let hit = world.hit(&ray, world.t_min(&ray), Scalar::INFINITY)?;
+        if hit.material.passes_through(&hit) {
+            ray = ray.continued_from(hit.point);
+            continue;
+        }
+
+        if !hit.material.is_specular() {
+            return specular.then(|| Photon {
+                position: hit.point,
+                power,
+                incoming: ray.direction.get_unit_vector(),
+            });
+        }
+
+        match hit.material.scatter(&ray, &hit, rng) {
+            Some((Some(scattered), attenuation)) => {
+                power = power * attenuation;
+                ray = scattered;
+                specular = true;
+            }
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+fn axis(v: &Vector, axis: usize) -> Scalar {
+    match axis {
+        0 => v.data.0,
+        1 => v.data.1,
+        _ => v.data.2,
+    }
+}
+
+// Orders `photons` into an implicit kd-tree, splitting every range at its median
+// along the axis it is widest in
+fn build_tree(photons: &mut [Photon], axes: &mut [usize]) {
+    if photons.len() <= 1 {
+        return;
+    }
+
+    let extent = |a: usize| {
+        let (min, max) = photons.iter().fold(
+            (Scalar::INFINITY, Scalar::NEG_INFINITY),
+            |(min, max), photon| {
+                let x = axis(&photon.position, a);
+                (min.min(x), max.max(x))
+            },
+        );
+        max - min
+    };
+    let split = (0..3)
+        .max_by(|&a, &b| extent(a).total_cmp(&extent(b)))
+        .unwrap_or(0);
+
+    let middle = photons.len() / 2;
+    photons.select_nth_unstable_by(middle, |a, b| {
+        axis(&a.position, split).total_cmp(&axis(&b.position, split))
+    });
+    axes[middle] = split;
+
+    let (left, rest) = photons.split_at_mut(middle);
+    let (left_axes, rest_axes) = axes.split_at_mut(middle);
+    build_tree(left, left_axes);
+    build_tree(&mut rest[1..], &mut rest_axes[1..]);
+}
+
+// Calls `found` with every photon within the square root of `radius_squared` of
+// `point`, skipping the halves of the tree that are too far to hold any
+fn gather(
+    photons: &[Photon],
+    axes: &[usize],
+    point: &Vector,
+    radius_squared: Scalar,
+    found: &mut impl FnMut(&Photon),
+) {
+    if photons.is_empty() {
+        return;
+    }
+
+    let middle = photons.len() / 2;
+    let photon = &photons[middle];
+    if (photon.position - *point).length_squared() <= radius_squared {
+        found(photon);
+    }
+
+    let offset = axis(point, axes[middle]) - axis(&photon.position, axes[middle]);
+    let (near, far) = if offset < 0.0 {
+        ((0, middle), (middle + 1, photons.len()))
+    } else {
+        ((middle + 1, photons.len()), (0, middle))
+    };
+
+    gather(
+        &photons[near.0..near.1],
+        &axes[near.0..near.1],
+        point,
+        radius_squared,
+        found,
+    );
+    if offset * offset <= radius_squared {
+        gather(
+            &photons[far.0..far.1],
+            &axes[far.0..far.1],
+            point,
+            radius_squared,
+            found,
+        );
+    }
+}
+
+// `render_with_options` with the caustics taken from `map` instead of traced, see
+// `utils::Caustics`
+#[allow(clippy::too_many_arguments)]
+pub fn render_with_caustics(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+    map: &PhotonMap,
+) -> Image {
+    let light =
+        |hit: &HitRecord| hit.material.albedo(hit) * map.irradiance(&hit.point, &hit.normal) / PI;
+
+    render_path_traced(
+        height,
+        width,
+        samples_per_pixel,
+        world,
+        camera,
+        max_depth,
+        options,
+        Some(Caustics::new(&light)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::area_light;
+    use crate::material::{Dielectric, Lambertian, Material};
+    use crate::quad::Quad;
+    use crate::render::{render_with_options, Integrator};
+    use crate::sphere::Sphere;
+    use crate::utils::SkyGradient;
+
+    fn point(x: Scalar, y: Scalar, z: Scalar) -> Vector {
+        Vector::new(x, y, z, VectorType::Point)
+    }
+
+    fn vector(x: Scalar, y: Scalar, z: Scalar) -> Vector {
+        Vector::new(x, y, z, VectorType::Vector)
+    }
+
+    // A glass ball focusing a tiny light overhead onto the floor under it, in the dark
+    fn glass_over_floor() -> World {
+        let white = Vector::new(0.8, 0.8, 0.8, VectorType::Color);
+        let mut world = World::new();
+        world.add(Quad::new(
+            point(-5.0, 0.0, 5.0),
+            vector(10.0, 0.0, 0.0),
+            vector(0.0, 0.0, -10.0),
+            Material::Lambertian(Lambertian::new(white)),
+        ));
+        world.add(Sphere::new(
+            point(0.0, 0.8, 0.0),
+            0.5,
+            Material::Dielectric(Dielectric::new(1.5)),
+        ));
+        area_light(
+            &mut world,
+            Quad::new(
+                point(-0.025, 4.0, -0.025),
+                vector(0.05, 0.0, 0.0),
+                vector(0.0, 0.0, 0.05),
+                Material::Lambertian(Lambertian::new(white)),
+            ),
+            Vector::new(1.0, 1.0, 1.0, VectorType::Color),
+            6400.0,
+        );
+
+        world.with_sky(SkyGradient::new(BLACK, BLACK))
+    }
+
+    #[test]
+    fn kd_tree_finds_the_same_photons_as_a_scan() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut photons: Vec<Photon> = (0..500)
+            .map(|_| Photon {
+                position: Vector::random_with(&mut rng, -1.0, 1.0),
+                power: Vector::new(1.0, 1.0, 1.0, VectorType::Color),
+                incoming: vector(0.0, -1.0, 0.0),
+            })
+            .collect();
+        let scan = photons.clone();
+        let mut axes = vec![0; photons.len()];
+        build_tree(&mut photons, &mut axes);
+
+        for _ in 0..50 {
+            let p = Vector::random_with(&mut rng, -1.0, 1.0);
+            let mut found = 0;
+            gather(&photons, &axes, &p, 0.09, &mut |_| found += 1);
+            let expected = scan
+                .iter()
+                .filter(|photon| (photon.position - p).length_squared() <= 0.09)
+                .count();
+
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn photon_map_brightens_the_caustic() {
+        let world = glass_over_floor();
+        let map = PhotonMap::build(&world, 200_000, 0.05, 1);
+        assert!(!map.is_empty());
+
+        // Looking down at the floor right under the ball
+        let camera = Camera::new(
+            point(0.0, 1.5, 3.0),
+            point(0.0, 0.0, 0.0),
+            vector(0.0, 1.0, 0.0),
+            2.0,
+            1.0,
+            0.0,
+            3.4,
+        );
+        let options = RenderOptions::default();
+        let spot = |image: &Image| {
+            image.pixels.iter().map(Vector::luminance).sum::<Scalar>()
+                / image.pixels.len() as Scalar
+        };
+
+        let traced = render_with_options(8, 8, 4, &world, camera.clone(), 10, &options);
+        let mapped = render_with_caustics(8, 8, 4, &world, camera, 10, &options, &map);
+
+        assert!(
+            spot(&mapped) > 2.0 * spot(&traced),
+            "{} vs {}",
+            spot(&mapped),
+            spot(&traced)
+        );
+    }
+
+    #[test]
+    fn caustic_renders_follow_the_options() {
+        let world = glass_over_floor();
+        let map = PhotonMap::build(&world, 1000, 0.05, 1);
+        let camera = Camera::new(
+            point(0.0, 1.5, 3.0),
+            point(0.0, 0.0, 0.0),
+            vector(0.0, 1.0, 0.0),
+            2.0,
+            1.0,
+            0.0,
+            3.4,
+        );
+
+        // Integrators without a path to take caustics into see what they always do
+        let options = RenderOptions {
+            integrator: Integrator::Albedo,
+            ..RenderOptions::default()
+        };
+        let traced = render_with_options(4, 4, 1, &world, camera.clone(), 10, &options);
+        let mapped = render_with_caustics(4, 4, 1, &world, camera, 10, &options, &map);
+
+        for (a, b) in traced.pixels.iter().zip(&mapped.pixels) {
+            assert_eq!(a.data, b.data);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use crate::ray::Ray;
+use crate::vector::{Scalar, Vector};
+use crate::world::World;
+
+// What lies under a pixel, for inspecting a scene interactively
+#[derive(Debug, Clone)]
+pub struct PickInfo {
+    pub point: Vector,
+    pub distance: Scalar,
+    pub normal: Vector,
+    // Index of the primitive in `World::objects`
+    pub primitive: usize,
+    pub material: &'static str,
+}
+
+// First surface along `ray`. The distance is measured along the ray, so it is only
+// a world space distance when the direction is normalized.
+pub fn pick(world: &World, ray: &Ray) -> Option<PickInfo> {
+    world
+        .hit_indexed(ray, world.t_min(ray), Scalar::INFINITY)
+        .map(|(primitive, hit)| PickInfo {
+            point: hit.point,
+            distance: hit.t,
+            normal: hit.normal,
+            primitive,
+            material: hit.material.kind(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Material, Metal};
+    use crate::sphere::Sphere;
+    use crate::vector::VectorType;
+
+    #[test]
+    fn picks_closest_metal_sphere() {
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -10.0, VectorType::Point),
+            1.0,
+            Material::gray(),
+        ));
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -3.0, VectorType::Point),
+            0.5,
+            Material::Metal(Metal::new(
+                Vector::new(0.8, 0.8, 0.8, VectorType::Color),
+                0.0,
+            )),
+        ));
+        let ray = Ray::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+        );
+
+        let info = pick(&world, &ray).unwrap();
+
+        assert_eq!(info.material, "Metal");
+        assert_eq!(info.primitive, 1);
+        assert!((info.distance - 2.5).abs() < 1e-9);
+        assert!((info.normal.data.2 - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn miss_picks_nothing() {
+        let ray = Ray::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+        );
+
+        assert!(pick(&World::new(), &ray).is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use crate::image::Image;
+use crate::postprocess::PostProcess;
+use crate::utils;
+use crate::vector::Scalar;
+use image::codecs::png::PngEncoder;
+use image::{ImageBuffer, ImageEncoder, ImageError, Rgb, RgbImage};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Gamma 2.0 like the 8-bit path, but spread over the full 16-bit range
+pub fn to_u16(value: Scalar) -> u16 {
+    (65535.0 * utils::clamp(value.sqrt(), 0.0, 1.0)).round() as u16
+}
+
+// The image quantized to 16 bits per channel after gamma, without the banding of
+// the 8-bit buffer in smooth gradients and dark scenes
+pub fn to_rgb16(image: &Image) -> ImageBuffer<Rgb<u16>, Vec<u16>> {
+    ImageBuffer::from_fn(image.width, image.height, |x, y| {
+        let (r, g, b) = image.get(x, y).data;
+        Rgb([to_u16(r), to_u16(g), to_u16(b)])
+    })
+}
+
+// The 8-bit image with the given post processing
+pub fn to_rgb8(image: &Image, post: &PostProcess) -> RgbImage {
+    RgbImage::from_raw(image.width, image.height, image.to_rgb8_with(post))
+        .expect("post processing gives three bytes per pixel")
+}
+
+pub fn write_png<P: AsRef<Path>>(path: P, image: &Image, post: &PostProcess) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let rgb = to_rgb8(image, post);
+    PngEncoder::new(&mut writer)
+        .write_image(&rgb, rgb.width(), rgb.height(), image::ColorType::Rgb8)
+        .map_err(to_io_error)?;
+    writer.flush()
+}
+
+pub fn write_png16<P: AsRef<Path>>(path: P, image: &Image) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode_png16(&mut writer, image)?;
+    writer.flush()
+}
+
+pub fn encode_png16<W: Write>(writer: &mut W, image: &Image) -> io::Result<()> {
+    let rgb = to_rgb16(image);
+    // PNG stores samples big-endian; the encoder takes native-endian bytes
+    let bytes: Vec<u8> = rgb.iter().flat_map(|value| value.to_ne_bytes()).collect();
+
+    PngEncoder::new(writer)
+        .write_image(&bytes, rgb.width(), rgb.height(), image::ColorType::Rgb16)
+        .map_err(to_io_error)
+}
+
+// Errors from the image crate as plain I/O errors, which is what callers handle
+pub(crate) fn to_io_error(error: ImageError) -> io::Error {
+    match error {
+        ImageError::IoError(error) => error,
+        other => io::Error::other(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::{Vector, VectorType};
+
+    #[test]
+    fn mid_gray_maps_to_half_range() {
+        // Linear 0.25 is 0.5 after gamma 2.0
+        assert_eq!(to_u16(0.25), 32768);
+        assert_eq!(to_u16(0.0), 0);
+        assert_eq!(to_u16(1.0), 65535);
+        assert_eq!(to_u16(4.0), 65535);
+    }
+
+    #[test]
+    fn png16_round_trips_through_the_decoder() {
+        let mut image = Image::new(3, 2);
+        image.set(1, 0, Vector::new(0.25, 0.0, 1.0, VectorType::Color));
+        let mut bytes = vec![];
+        encode_png16(&mut bytes, &image).unwrap();
+
+        let decoded = image::load_from_memory(&bytes).unwrap().into_rgb16();
+        assert_eq!(decoded.dimensions(), (3, 2));
+        assert_eq!(decoded.get_pixel(1, 0), &Rgb([32768, 0, 65535]));
+        assert_eq!(decoded.get_pixel(0, 1), &Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn packs_8_bit_pixels_with_post_processing() {
+        let mut image = Image::new(1, 1);
+        image.set(0, 0, Vector::new(0.25, 0.0, 1.0, VectorType::Color));
+
+        assert_eq!(
+            to_rgb8(&image, &PostProcess::default()).get_pixel(0, 0),
+            &Rgb([128, 0, 255])
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use crate::image::Image;
+use crate::utils;
+use crate::vector::{Scalar, Vector};
+use rayon::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    None,
+    // c / (1 + c) per channel
+    Reinhard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gamma {
+    // Leave the values linear
+    None,
+    // Gamma 2.0, a square root
+    Approximate,
+    // The piecewise sRGB transfer function
+    Srgb,
+}
+
+// What happens to values outside what a display can show
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clamp {
+    // Keep them; the float to byte cast still saturates, but NaN turns into 0
+    None,
+    // Clip every channel to [0, 1] on its own, which shifts the hue of bright
+    // saturated colors as their strongest channel clips first
+    Channels,
+    // Pull colors brighter than white towards white, keeping their luminance, until
+    // no channel is above 1, so bright orange washes out to white instead of turning
+    // yellow. What is left is clipped per channel.
+    Desaturate,
+}
+
+// The display transform applied to linear radiance when an image is quantized.
+// The default reproduces the renderer's original output: gamma 2.0 and clamping.
+#[derive(Debug, Clone, Copy)]
+pub struct PostProcess {
+    pub tone_map: ToneMap,
+    pub gamma: Gamma,
+    pub clamp: Clamp,
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self {
+            tone_map: ToneMap::None,
+            gamma: Gamma::Approximate,
+            clamp: Clamp::Channels,
+        }
+    }
+}
+
+impl PostProcess {
+    // Scales radiance straight to bytes, to inspect exactly what the integrator produced
+    pub fn raw() -> Self {
+        Self {
+            tone_map: ToneMap::None,
+            gamma: Gamma::None,
+            clamp: Clamp::None,
+        }
+    }
+
+    pub fn apply(&self, color: Vector) -> Vector {
+        let color = match self.tone_map {
+            ToneMap::None => color,
+            ToneMap::Reinhard => Vector::new(
+                color.data.0 / (1.0 + color.data.0),
+                color.data.1 / (1.0 + color.data.1),
+                color.data.2 / (1.0 + color.data.2),
+                color.data_type,
+            ),
+        };
+        let color = match self.clamp {
+            Clamp::Desaturate => desaturate_highlights(color),
+            Clamp::None | Clamp::Channels => color,
+        };
+
+        match self.gamma {
+            Gamma::None => color,
+            Gamma::Approximate => Vector::new(
+                color.data.0.sqrt(),
+                color.data.1.sqrt(),
+                color.data.2.sqrt(),
+                color.data_type,
+            ),
+            Gamma::Srgb => color.to_srgb(),
+        }
+    }
+
+    pub fn to_rgb8(&self, color: Vector) -> [u8; 3] {
+        let color = self.apply(color);
+
+        [
+            self.quantize(color.data.0),
+            self.quantize(color.data.1),
+            self.quantize(color.data.2),
+        ]
+    }
+
+    // Clamped just below 1 so that 1.0 maps to 255 rather than overflowing
+    fn quantize(&self, value: Scalar) -> u8 {
+        match self.clamp {
+            Clamp::Channels | Clamp::Desaturate => (256.0 * utils::clamp(value, 0.0, 0.9999)) as u8,
+            Clamp::None => (256.0 * value) as u8,
+        }
+    }
+}
+
+// The display transform of every pixel of `radiance`, quantized to 8-bit RGB triplets
+// top row first. Pixels are independent, so they are spread over the thread pool;
+// at high resolutions this is no longer negligible next to writing the file.
+pub fn post_process(radiance: &Image, post: &PostProcess) -> Vec<u8> {
+    radiance
+        .pixels
+        .par_iter()
+        .flat_map_iter(|pixel| post.to_rgb8(*pixel))
+        .collect()
+}
+
+// Mixes `color` with the gray of the same luminance just enough that its largest
+// channel comes down to 1, or all the way to white when even that gray is too bright
+fn desaturate_highlights(color: Vector) -> Vector {
+    let (r, g, b) = color.data;
+    let peak = r.max(g).max(b);
+    if peak <= 1.0 {
+        return color;
+    }
+
+    let luminance = color.luminance();
+    if luminance >= 1.0 {
+        return Vector::new(1.0, 1.0, 1.0, color.data_type);
+    }
+
+    let saturation = (1.0 - luminance) / (peak - luminance);
+    let mix = |channel: Scalar| luminance + saturation * (channel - luminance);
+    Vector::new(mix(r), mix(g), mix(b), color.data_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::{fuzzy_equal, VectorType};
+
+    #[test]
+    fn default_matches_gamma_and_clamp() {
+        let post = PostProcess::default();
+
+        let gray = Vector::new(0.25, 1.0, 4.0, VectorType::Color);
+
+        assert_eq!(post.to_rgb8(gray), [128, 255, 255]);
+    }
+
+    #[test]
+    fn raw_output_skips_gamma_and_clamp() {
+        let post = PostProcess::raw();
+
+        let gray = Vector::new(0.5, 0.5, 0.5, VectorType::Color);
+
+        assert_eq!(post.to_rgb8(gray), [(0.5 * 256.0) as u8; 3]);
+    }
+
+    #[test]
+    fn srgb_output_uses_piecewise_curve() {
+        let post = PostProcess {
+            gamma: Gamma::Srgb,
+            ..PostProcess::default()
+        };
+
+        let gray = Vector::new(0.5, 0.5, 0.5, VectorType::Color);
+
+        // 0.7354 after encoding, versus 0.7071 with gamma 2.0
+        assert_eq!(post.to_rgb8(gray), [188; 3]);
+    }
+
+    #[test]
+    fn reinhard_compresses_highlights() {
+        let post = PostProcess {
+            tone_map: ToneMap::Reinhard,
+            gamma: Gamma::None,
+            clamp: Clamp::Channels,
+        };
+
+        let color = post.apply(Vector::new(1.0, 3.0, 0.0, VectorType::Color));
+
+        assert_eq!(color, Vector::new(0.5, 0.75, 0.0, VectorType::Color));
+    }
+
+    #[test]
+    fn desaturating_clamp_washes_highlights_out_to_white() {
+        let post = PostProcess {
+            tone_map: ToneMap::None,
+            gamma: Gamma::None,
+            clamp: Clamp::Desaturate,
+        };
+        let orange = Vector::new(2.0, 0.5, 0.0, VectorType::Color);
+
+        let color = post.apply(orange);
+
+        // Not clipped to (1, 0.5, 0) but lifted towards white at the same luminance
+        assert!(fuzzy_equal(color.data.0, 1.0));
+        assert!(color.data.1 > 0.7 && color.data.2 > 0.6);
+        assert!(color.data.1 > color.data.2);
+        assert!(fuzzy_equal(color.luminance(), orange.luminance()));
+
+        // In range colors are left alone, too bright ones become white
+        let dim = Vector::new(0.8, 0.5, 0.1, VectorType::Color);
+        assert_eq!(post.apply(dim), dim);
+        let bright = Vector::new(3.0, 2.0, 1.5, VectorType::Color);
+        assert_eq!(post.to_rgb8(bright), [255; 3]);
+    }
+
+    #[test]
+    fn parallel_post_process_matches_pixel_by_pixel() {
+        let mut image = Image::new(7, 5);
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            let i = i as Scalar;
+            *pixel = Vector::new(0.05 * i, 1.5 - 0.04 * i, (0.3 * i).sin(), VectorType::Color);
+        }
+
+        for post in [
+            PostProcess::default(),
+            PostProcess::raw(),
+            PostProcess {
+                tone_map: ToneMap::Reinhard,
+                gamma: Gamma::Srgb,
+                clamp: Clamp::Desaturate,
+            },
+        ] {
+            let expected: Vec<u8> = image
+                .pixels
+                .iter()
+                .flat_map(|pixel| post.to_rgb8(*pixel))
+                .collect();
+
+            assert_eq!(post_process(&image, &post), expected);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::validate::{self, Problem};
+use crate::vector::{Scalar, Vector};
+use rand::{Rng, RngCore};
+
+// A parallelogram spanned by the edges `u` and `v` from the corner `q`. Its front is
+// the side `u × v` points to.
+pub struct Quad {
+    q: Vector,
+    u: Vector,
+    v: Vector,
+    material: Material,
+    normal: Vector,
+    d: Scalar,
+    w: Vector,
+    double_sided: bool,
+}
+
+impl Quad {
+    pub fn new(q: Vector, u: Vector, v: Vector, material: Material) -> Self {
+        let n = u.cross(&v);
+        let normal = n.get_unit_vector();
+        let d = normal.dot(&q);
+        // A degenerate shape keeps a zero `w`; its zero normal already makes `hit` miss
+        let area_squared = n.dot(&n);
+        let w = if area_squared == 0.0 {
+            n
+        } else {
+            n / area_squared
+        };
+
+        Self {
+            q,
+            u,
+            v,
+            material,
+            normal,
+            d,
+            w,
+            double_sided: true,
+        }
+    }
+
+    // Single sided quads are invisible from behind: rays reaching their back go
+    // straight through, for one way windows and culling the backs of closed meshes
+    pub fn with_double_sided(mut self, double_sided: bool) -> Self {
+        self.double_sided = double_sided;
+        self
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        let denominator = self.normal.dot(&r.direction);
+
+        // The ray is parallel to the plane
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        // Also rejects the NaN of a degenerate ray
+        let t = (self.d - self.normal.dot(&r.origin)) / denominator;
+        if !(t_min <= t && t <= t_max) {
+            return None;
+        }
+
+        // Express the hit point in the plane's (u, v) coordinates
+        let point = r.at(t);
+        let planar = point - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let front_face = r.direction.dot(&self.normal) < 0.0;
+        if !front_face && !self.double_sided {
+            return None;
+        }
+        let normal = if front_face {
+            self.normal
+        } else {
+            -self.normal
+        };
+        let tangent = self.u.get_unit_vector();
+        let footprint = r.footprint_at(t, &normal);
+
+        Some(HitRecord {
+            point,
+            normal,
+            t,
+            u: alpha,
+            v: beta,
+            uv_footprint: (footprint / self.u.len()).max(footprint / self.v.len()),
+            front_face,
+            material: &self.material,
+            tangent: Some(tangent),
+            bitangent: Some(normal.cross(&tangent)),
+        })
+    }
+
+    // Uniform over the quad's area, converted to solid angle as seen from `origin`
+    fn pdf_value(&self, origin: &Vector, direction: &Vector) -> Scalar {
+        match self.hit(&Ray::new(*origin, *direction), 0.001, Scalar::INFINITY) {
+            Some(hit) => {
+                let distance_squared = hit.t * hit.t * direction.length_squared();
+                let cosine = direction.dot(&self.normal).abs() / direction.len();
+                let area = self.u.cross(&self.v).len();
+
+                distance_squared / (cosine * area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random_with(&self, rng: &mut dyn RngCore, origin: &Vector) -> Vector {
+        let point = self.q + rng.gen::<Scalar>() * self.u + rng.gen::<Scalar>() * self.v;
+        point - *origin
+    }
+
+    fn sample_surface(&self, rng: &mut dyn RngCore) -> Option<(HitRecord<'_>, Scalar)> {
+        let point = self.q + rng.gen::<Scalar>() * self.u + rng.gen::<Scalar>() * self.v;
+        let hit = self.hit(&Ray::new(point + self.normal, -self.normal), 0.5, 1.5)?;
+
+        Some((hit, 1.0 / self.u.cross(&self.v).len()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let diagonal = Aabb::new(self.q, self.q + self.u + self.v);
+        let other = Aabb::new(self.q + self.u, self.q + self.v);
+
+        Some(diagonal.surrounding(&other).pad(1e-4))
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        let mut problems = self.material.problems();
+
+        if ![self.q, self.u, self.v].iter().all(validate::is_finite) {
+            problems.push(Problem::NonFinite);
+        } else if self.u.cross(&self.v).near_zero() {
+            problems.push(Problem::ZeroArea);
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Scatterable;
+    use crate::vector::{fuzzy_equal, VectorType};
+
+    fn quad() -> Quad {
+        Quad::new(
+            Vector::new(-1.0, -1.0, -2.0, VectorType::Point),
+            Vector::new(2.0, 0.0, 0.0, VectorType::Vector),
+            Vector::new(0.0, 2.0, 0.0, VectorType::Vector),
+            Material::gray(),
+        )
+    }
+
+    #[test]
+    fn hit_reports_uv_and_tangent_frame() {
+        let ray = Ray::new(
+            Vector::new(0.5, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+        );
+
+        let quad = quad();
+        let hit = quad.hit(&ray, 0.001, Scalar::INFINITY).unwrap();
+        let tangent = hit.tangent.unwrap();
+        let bitangent = hit.bitangent.unwrap();
+
+        assert!(fuzzy_equal(hit.t, 2.0));
+        assert!(fuzzy_equal(hit.u, 0.75));
+        assert!(fuzzy_equal(hit.v, 0.5));
+        assert!(fuzzy_equal(tangent.dot(&hit.normal), 0.0));
+        assert!(fuzzy_equal(bitangent.dot(&hit.normal), 0.0));
+        assert!(fuzzy_equal(tangent.data.0, 1.0));
+    }
+
+    #[test]
+    fn ray_outside_edges_misses() {
+        let ray = Ray::new(
+            Vector::new(1.5, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+        );
+
+        assert!(quad().hit(&ray, 0.001, Scalar::INFINITY).is_none());
+    }
+
+    #[test]
+    fn single_sided_quad_is_invisible_from_behind() {
+        let one_sided = quad().with_double_sided(false);
+        // The quad faces +z
+        let front = Ray::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+        );
+        let back = Ray::new(
+            Vector::new(0.0, 0.0, -4.0, VectorType::Point),
+            Vector::new(0.0, 0.0, 1.0, VectorType::Vector),
+        );
+
+        assert!(one_sided.hit(&back, 0.001, Scalar::INFINITY).is_none());
+        assert!(quad().hit(&back, 0.001, Scalar::INFINITY).is_some());
+
+        let hit = one_sided.hit(&front, 0.001, Scalar::INFINITY).unwrap();
+        assert!(hit.front_face);
+        let scattered = hit
+            .material
+            .scatter(&front, &hit, &mut rand::thread_rng())
+            .and_then(|(ray, _)| ray)
+            .unwrap();
+        assert!(scattered.direction.dot(&hit.normal) > 0.0);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::validate::Problem;
+use crate::vector::{Scalar, Vector, VectorType};
+
+// The implicit surface Ax² + By² + Cz² + Dxy + Exz + Fyz + Gx + Hy + Iz + J = 0.
+// Covers ellipsoids, paraboloids, hyperboloids, cylinders and cones; all but the
+// ellipsoid are unbounded.
+pub struct Quadric {
+    // A through J, in that order
+    coefficients: [Scalar; 10],
+    material: Material,
+    // Only known for the shapes built by the named constructors
+    bounds: Option<Aabb>,
+}
+
+impl Quadric {
+    pub fn new(coefficients: [Scalar; 10], material: Material) -> Self {
+        Self {
+            coefficients,
+            material,
+            bounds: None,
+        }
+    }
+
+    // ((x - cx) / rx)² + ((y - cy) / ry)² + ((z - cz) / rz)² = 1
+    pub fn ellipsoid(center: Vector, radii: Vector, material: Material) -> Self {
+        let (cx, cy, cz) = center.data;
+        let (a, b, c) = (
+            1.0 / (radii.data.0 * radii.data.0),
+            1.0 / (radii.data.1 * radii.data.1),
+            1.0 / (radii.data.2 * radii.data.2),
+        );
+
+        let quadric = Self::new(
+            [
+                a,
+                b,
+                c,
+                0.0,
+                0.0,
+                0.0,
+                -2.0 * a * cx,
+                -2.0 * b * cy,
+                -2.0 * c * cz,
+                a * cx * cx + b * cy * cy + c * cz * cz - 1.0,
+            ],
+            material,
+        );
+
+        Self {
+            bounds: Some(Aabb::new(center - radii, center + radii)),
+            ..quadric
+        }
+    }
+
+    pub fn value(&self, p: &Vector) -> Scalar {
+        let [a, b, c, d, e, f, g, h, i, j] = self.coefficients;
+        let (x, y, z) = p.data;
+
+        a * x * x
+            + b * y * y
+            + c * z * z
+            + d * x * y
+            + e * x * z
+            + f * y * z
+            + g * x
+            + h * y
+            + i * z
+            + j
+    }
+
+    // Points towards increasing values, i.e. out of an ellipsoid
+    pub fn gradient(&self, p: &Vector) -> Vector {
+        let [a, b, c, d, e, f, g, h, i, _] = self.coefficients;
+        let (x, y, z) = p.data;
+
+        Vector::new(
+            2.0 * a * x + d * y + e * z + g,
+            2.0 * b * y + d * x + f * z + h,
+            2.0 * c * z + e * x + f * y + i,
+            VectorType::Vector,
+        )
+    }
+}
+
+impl Hittable for Quadric {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        let [a, b, c, d, e, f, g, h, i, _] = self.coefficients;
+        let (ox, oy, oz) = r.origin.data;
+        let (dx, dy, dz) = r.direction.data;
+
+        // Substituting o + t·d gives qa·t² + qb·t + qc = 0
+        let qa = a * dx * dx + b * dy * dy + c * dz * dz + d * dx * dy + e * dx * dz + f * dy * dz;
+        let qb = 2.0 * (a * ox * dx + b * oy * dy + c * oz * dz)
+            + d * (ox * dy + oy * dx)
+            + e * (ox * dz + oz * dx)
+            + f * (oy * dz + oz * dy)
+            + g * dx
+            + h * dy
+            + i * dz;
+        let qc = self.value(&r.origin);
+
+        let in_range = |t: Scalar| t_min <= t && t <= t_max;
+
+        let root = if qa.abs() < 1e-12 {
+            // Degenerates to a linear equation along this direction
+            if qb.abs() < 1e-12 {
+                return None;
+            }
+            Some(-qc / qb).filter(|&t| in_range(t))?
+        } else {
+            let discriminant = qb * qb - 4.0 * qa * qc;
+            if discriminant < 0.0 {
+                return None;
+            }
+
+            let discriminant_sqrt = discriminant.sqrt();
+            let first = (-qb - discriminant_sqrt) / (2.0 * qa);
+            let second = (-qb + discriminant_sqrt) / (2.0 * qa);
+            let (near, far) = if first < second {
+                (first, second)
+            } else {
+                (second, first)
+            };
+
+            [near, far].into_iter().find(|&t| in_range(t))?
+        };
+
+        let point = r.at(root);
+        let outward = self.gradient(&point).get_unit_vector();
+        let front_face = r.direction.dot(&outward) < 0.0;
+        let normal = if front_face { outward } else { -outward };
+
+        Some(HitRecord {
+            point,
+            normal,
+            t: root,
+            u: 0.0,
+            v: 0.0,
+            uv_footprint: 0.0,
+            front_face,
+            material: &self.material,
+            tangent: None,
+            bitangent: None,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        let mut problems = self.material.problems();
+
+        if !self.coefficients.iter().all(|c| c.is_finite()) {
+            problems.push(Problem::NonFinite);
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sphere::Sphere;
+    use crate::vector::fuzzy_equal;
+
+    #[test]
+    fn unit_sphere_quadric_matches_sphere() {
+        let center = Vector::new(0.5, -0.25, -3.0, VectorType::Point);
+        let sphere = Sphere::new(center, 1.0, Material::gray());
+        let quadric = Quadric::ellipsoid(
+            center,
+            Vector::new(1.0, 1.0, 1.0, VectorType::Vector),
+            Material::gray(),
+        );
+
+        for (x, y) in [(0.0, 0.0), (0.3, -0.2), (0.9, 0.1), (2.0, 0.0)] {
+            let ray = Ray::new(
+                Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+                Vector::new(x, y, -1.0, VectorType::Vector),
+            );
+
+            match (
+                sphere.hit(&ray, 0.001, Scalar::INFINITY),
+                quadric.hit(&ray, 0.001, Scalar::INFINITY),
+            ) {
+                (Some(expected), Some(actual)) => {
+                    assert!(fuzzy_equal(expected.t, actual.t));
+                    assert!(fuzzy_equal(expected.normal.dot(&actual.normal), 1.0));
+                    assert_eq!(expected.front_face, actual.front_face);
+                }
+                (None, None) => {}
+                _ => panic!("sphere and quadric disagree for ({}, {})", x, y),
+            }
+        }
+    }
+
+    #[test]
+    fn ellipsoid_stretches_along_one_axis() {
+        let origin = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+        let ellipsoid = Quadric::ellipsoid(
+            origin,
+            Vector::new(3.0, 1.0, 1.0, VectorType::Vector),
+            Material::gray(),
+        );
+
+        let along_x = Ray::new(
+            Vector::new(10.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(-1.0, 0.0, 0.0, VectorType::Vector),
+        );
+        let along_y = Ray::new(
+            Vector::new(0.0, 10.0, 0.0, VectorType::Point),
+            Vector::new(0.0, -1.0, 0.0, VectorType::Vector),
+        );
+
+        let x_hit = ellipsoid.hit(&along_x, 0.001, Scalar::INFINITY).unwrap();
+        let y_hit = ellipsoid.hit(&along_y, 0.001, Scalar::INFINITY).unwrap();
+
+        assert!(fuzzy_equal(x_hit.point.data.0, 3.0));
+        assert!(fuzzy_equal(y_hit.point.data.1, 1.0));
+        assert!(fuzzy_equal(x_hit.normal.data.0, 1.0));
+
+        // Beyond the unit sphere along x, but still inside the stretched extent
+        let offset = Ray::new(
+            Vector::new(2.0, 10.0, 0.0, VectorType::Point),
+            Vector::new(0.0, -1.0, 0.0, VectorType::Vector),
+        );
+        let offset_hit = ellipsoid.hit(&offset, 0.001, Scalar::INFINITY).unwrap();
+        assert!(fuzzy_equal(
+            offset_hit.point.data.1,
+            (5.0 as Scalar / 9.0).sqrt()
+        ));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::matrix::Matrix4;
+use crate::vector::Scalar;
+
+// A unit quaternion standing for a rotation, for turning smoothly between two
+// orientations. `w` is the real part.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: Scalar,
+    pub x: Scalar,
+    pub y: Scalar,
+    pub z: Scalar,
+}
+
+impl Quaternion {
+    pub fn new(w: Scalar, x: Scalar, y: Scalar, z: Scalar) -> Self {
+        Self { w, x, y, z }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    // The rotation in the upper 3x3 of `matrix`, which should be a proper rotation
+    // (orthonormal, determinant 1). Works from the largest of the four components so
+    // none is found by dividing by something near zero.
+    pub fn from_matrix(matrix: &Matrix4) -> Self {
+        let m = &matrix.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Self::new(
+                s / 4.0,
+                (m[2][1] - m[1][2]) / s,
+                (m[0][2] - m[2][0]) / s,
+                (m[1][0] - m[0][1]) / s,
+            )
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Self::new(
+                (m[2][1] - m[1][2]) / s,
+                s / 4.0,
+                (m[0][1] + m[1][0]) / s,
+                (m[0][2] + m[2][0]) / s,
+            )
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Self::new(
+                (m[0][2] - m[2][0]) / s,
+                (m[0][1] + m[1][0]) / s,
+                s / 4.0,
+                (m[1][2] + m[2][1]) / s,
+            )
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Self::new(
+                (m[1][0] - m[0][1]) / s,
+                (m[0][2] + m[2][0]) / s,
+                (m[1][2] + m[2][1]) / s,
+                s / 4.0,
+            )
+        };
+
+        q.normalized()
+    }
+
+    pub fn to_matrix(&self) -> Matrix4 {
+        let Self { w, x, y, z } = *self;
+
+        Matrix4::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn dot(&self, other: &Self) -> Scalar {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    // Turns at a constant rate from `self` at `f` = 0 to `other` at 1, the short way
+    // round. A half turn has no short way; it goes whichever way the signs fall.
+    pub fn slerp(&self, other: &Self, f: Scalar) -> Self {
+        // `q` and `-q` are the same rotation; pick the one nearer `self`
+        let (other, cos) = match self.dot(other) {
+            cos if cos < 0.0 => (other.scaled(-1.0), -cos),
+            cos => (*other, cos),
+        };
+
+        // Nearly the same orientation: the arc is practically straight
+        if cos > 0.9995 {
+            return self.scaled(1.0 - f).plus(&other.scaled(f)).normalized();
+        }
+
+        let theta = cos.acos();
+        let sin = theta.sin();
+        self.scaled(((1.0 - f) * theta).sin() / sin)
+            .plus(&other.scaled((f * theta).sin() / sin))
+    }
+
+    fn scaled(&self, s: Scalar) -> Self {
+        Self::new(self.w * s, self.x * s, self.y * s, self.z * s)
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        Self::new(
+            self.w + other.w,
+            self.x + other.x,
+            self.y + other.y,
+            self.z + other.z,
+        )
+    }
+
+    fn normalized(&self) -> Self {
+        self.scaled(1.0 / self.dot(self).sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::{fuzzy_equal, Vector, VectorType};
+
+    #[test]
+    fn round_trips_rotation_matrices() {
+        for matrix in [
+            Matrix4::identity(),
+            Matrix4::rotation_x(30.0),
+            Matrix4::rotation_y(180.0),
+            Matrix4::rotation_z(-120.0) * Matrix4::rotation_x(75.0),
+        ] {
+            let round_trip = Quaternion::from_matrix(&matrix).to_matrix();
+
+            for (row, expected) in round_trip.m.iter().zip(matrix.m.iter()) {
+                for (a, e) in row.iter().zip(expected.iter()) {
+                    assert!(fuzzy_equal(*a, *e), "{:?} != {:?}", round_trip, matrix);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn slerp_turns_at_a_constant_rate() {
+        let start = Quaternion::identity();
+        let end = Quaternion::from_matrix(&Matrix4::rotation_z(120.0));
+        let x = Vector::new(1.0, 0.0, 0.0, VectorType::Vector);
+
+        for (f, degrees) in [(0.0, 0.0), (0.25, 30.0), (0.5, 60.0), (1.0, 120.0)] {
+            let turned = start.slerp(&end, f).to_matrix().mul_vector(&x);
+            let expected = Matrix4::rotation_z(degrees).mul_vector(&x);
+
+            assert!(fuzzy_equal(turned.dot(&expected), 1.0), "{}", f);
+        }
+    }
+}
@@ -1,17 +1,74 @@
-use crate::vector::Vector;
+use crate::vector::{Scalar, Vector};
 
 #[derive(Debug)]
 pub struct Ray {
     pub origin: Vector,
     pub direction: Vector,
+    pub differentials: Option<RayDifferentials>,
+    // The moment during the exposure the ray is traced at, from 0 as the shutter
+    // opens to 1 as it closes, for objects that move
+    pub time: Scalar,
+}
+
+// Directions of the rays through the neighboring pixels in x and y. They share the
+// main ray's origin and are used to estimate how much of a surface a pixel covers.
+#[derive(Debug, Clone, Copy)]
+pub struct RayDifferentials {
+    pub dx: Vector,
+    pub dy: Vector,
 }
 
 impl Ray {
     pub fn new(origin: Vector, direction: Vector) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            differentials: None,
+            time: 0.0,
+        }
+    }
+
+    pub fn with_differentials(mut self, dx: Vector, dy: Vector) -> Self {
+        self.differentials = Some(RayDifferentials { dx, dy });
+        self
     }
 
-    pub fn at(&self, t: f64) -> Vector {
+    pub fn with_time(mut self, time: Scalar) -> Self {
+        self.time = time;
+        self
+    }
+
+    // The same ray carrying on from `origin`, a point along it such as a hit it
+    // passes through
+    pub fn continued_from(&self, origin: Vector) -> Self {
+        Self {
+            origin,
+            direction: self.direction,
+            differentials: self.differentials,
+            time: self.time,
+        }
+    }
+
+    pub fn at(&self, t: Scalar) -> Vector {
         self.origin + t * self.direction
     }
+
+    // World space width of the pixel footprint where the ray meets a surface with the
+    // given normal at `t`, or 0 when the ray carries no differentials
+    pub fn footprint_at(&self, t: Scalar, normal: &Vector) -> Scalar {
+        let differentials = match &self.differentials {
+            Some(differentials) => differentials,
+            None => return 0.0,
+        };
+
+        let direction = self.direction.get_unit_vector();
+        let spread = (differentials.dx.get_unit_vector() - direction)
+            .len()
+            .max((differentials.dy.get_unit_vector() - direction).len());
+
+        // Glancing hits stretch the footprint along the surface
+        let cos_theta = direction.dot(normal).abs().max(0.01);
+
+        t * self.direction.len() * spread / cos_theta
+    }
 }
@@ -0,0 +1,1399 @@
+use crate::aabb::Aabb;
+use crate::ao::ambient_occlusion_with;
+use crate::camera::Camera;
+use crate::hit::HitRecord;
+use crate::image::Image;
+use crate::ray::Ray;
+use crate::utils::{
+    clamp_luminance, seed_for, sky_color, Caustics, DepthExhaustion, SKY_BOTTOM, SKY_TOP,
+};
+use crate::vector::{Scalar, Vector, VectorType};
+use crate::world::World;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub fn render(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+) -> Image {
+    render_with_options(
+        height,
+        width,
+        samples_per_pixel,
+        world,
+        camera,
+        max_depth,
+        &RenderOptions::default(),
+    )
+}
+
+// Settings for the less commonly changed parts of a render
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions {
+    // Sky gradient from the horizon to straight up, unless the world has its own
+    pub sky_bottom: Vector,
+    pub sky_top: Vector,
+    // Mixed into every pixel sample's seed; different seeds give independent noise
+    pub seed: u64,
+    // Index of the first sample drawn for each pixel. Renders of the same frame that
+    // start at different samples can be merged, see `accumulation::merge`.
+    pub first_sample: u32,
+    pub depth_exhaustion: DepthExhaustion,
+    // Caps the luminance of light arriving along scattered rays, see
+    // `utils::ray_color_with`. The spectral renderer ignores it.
+    pub max_indirect_luminance: Option<Scalar>,
+    // Square tiles this many pixels across, or whole scanlines when `None`
+    pub tile_size: Option<u32>,
+    // Shown instead of the sky where a camera ray misses everything, see `BackdropFn`.
+    // Scattered rays still see the sky.
+    pub backdrop: Option<BackdropFn>,
+    // Jitters the samples of each pixel within an n×n grid of strata, sample `s` in
+    // stratum `s % (n * n)`, rather than anywhere in the pixel. Every pixel shifts its
+    // grid by its own random offset so the strata never line up into patterns.
+    pub strata: Option<u32>,
+    // What the built in shader computes along each camera ray
+    pub integrator: Integrator,
+    // Limits how bright a single sample can be before it is averaged into its pixel
+    pub firefly_clamp: FireflyClamp,
+    // Clip distances from the lens along camera rays, for cutaway views: surfaces
+    // nearer than `near` or beyond `far` are not seen directly, though they still
+    // cast shadows and show up in reflections
+    pub near: Scalar,
+    pub far: Scalar,
+}
+
+// Caps on the radiance of each sample, trading a little energy for fewer fireflies:
+// single bright samples from unlikely paths, like caustics through glass, that
+// would otherwise take very many samples to average out. Samples that are NaN or
+// infinite are left to be discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FireflyClamp {
+    #[default]
+    None,
+    // Scales samples down to at most this luminance, keeping their hue
+    Luminance(Scalar),
+    // Clips each channel to at most this on its own, which only touches the channels
+    // that are too bright, at the cost of shifting the hue of the samples it clips
+    PerChannel(Scalar),
+}
+
+impl FireflyClamp {
+    pub fn apply(&self, color: Vector) -> Vector {
+        if !color.is_finite() {
+            return color;
+        }
+
+        match *self {
+            FireflyClamp::None => color,
+            FireflyClamp::Luminance(max) => clamp_luminance(color, max),
+            FireflyClamp::PerChannel(max) => Vector::new(
+                color.data.0.min(max),
+                color.data.1.min(max),
+                color.data.2.min(max),
+                color.data_type,
+            ),
+        }
+    }
+}
+
+// The quantities the built in shader can compute along a camera ray: the full render,
+// or one of the simpler passes used for debugging, compositing and comparison. All of
+// them look through cutout holes like the path tracer does.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Integrator {
+    // Full path tracing, see `utils::ray_color_with`
+    #[default]
+    Path,
+    // Path tracing cut off after a single bounce: emitters and the sky seen directly
+    // or in one reflection, without any of the light bounced around the scene
+    DirectOnly,
+    // The normal at the first hit mapped from [-1, 1] to [0, 1] per channel, black
+    // where the ray misses
+    Normals,
+    // How open the geometry is at the first hit, see `ao::ambient_occlusion`, in gray.
+    // White where the ray misses.
+    AmbientOcclusion {
+        samples: u32,
+        radius: Scalar,
+    },
+    // The first hit's color without any lighting, see `Material::albedo`, and the
+    // background where the ray misses
+    Albedo,
+}
+
+// Backdrop color at a point on the screen, from (0, 0) in the bottom left corner to
+// (1, 1) in the top right, whatever the direction of the ray through it. For studio
+// style product shots with a flat or graded backdrop.
+pub type BackdropFn = fn(Scalar, Scalar) -> Vector;
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            sky_bottom: SKY_BOTTOM,
+            sky_top: SKY_TOP,
+            seed: 0,
+            first_sample: 0,
+            depth_exhaustion: DepthExhaustion::Black,
+            max_indirect_luminance: None,
+            tile_size: Some(32),
+            backdrop: None,
+            strata: None,
+            integrator: Integrator::Path,
+            firefly_clamp: FireflyClamp::None,
+            near: 0.0,
+            far: Scalar::INFINITY,
+        }
+    }
+}
+
+// `render` with the sky gradient running from `sky.0` at the horizon to `sky.1`
+// straight up
+pub fn render_with_sky(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+    sky: (Vector, Vector),
+) -> Image {
+    render_with_options(
+        height,
+        width,
+        samples_per_pixel,
+        world,
+        camera,
+        max_depth,
+        &RenderOptions {
+            sky_bottom: sky.0,
+            sky_top: sky.1,
+            ..RenderOptions::default()
+        },
+    )
+}
+
+pub fn render_with_options(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+) -> Image {
+    render_path_traced(
+        height,
+        width,
+        samples_per_pixel,
+        world,
+        camera,
+        max_depth,
+        options,
+        None,
+    )
+}
+
+// `render_with_options` with the caustic light from `caustics` if set
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn render_path_traced(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+    caustics: Option<Caustics>,
+) -> Image {
+    let shade = path_tracer_with(world, max_depth, options, caustics);
+
+    let sampler = PixelSampler {
+        world: Some(world),
+        camera: &camera,
+        width,
+        height,
+        samples_per_pixel,
+        options,
+        shade: &shade,
+        discarded: AtomicU64::new(0),
+    };
+
+    render_sampler(&sampler)
+}
+
+// Renders with `shade` computing the radiance along each camera ray in place of the
+// path tracer, e.g. to try out a different integrator. Tiling, seeding and averaging
+// the samples work as in `render_with_options`; the sky, depth, luminance and backdrop
+// settings in `options` are left to `shade`.
+pub fn render_with(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    camera: Camera,
+    options: &RenderOptions,
+    shade: impl Fn(&Ray, &mut StdRng) -> Vector + Sync,
+) -> Image {
+    let sampler = PixelSampler {
+        world: None,
+        camera: &camera,
+        width,
+        height,
+        samples_per_pixel,
+        options,
+        shade: &shade,
+        discarded: AtomicU64::new(0),
+    };
+
+    render_sampler(&sampler)
+}
+
+fn render_sampler(sampler: &PixelSampler) -> Image {
+    let mut image = match sampler.options.tile_size {
+        Some(tile_size) => render_tiles(sampler, tile_size.max(1)),
+        None => render_scanlines(sampler),
+    };
+    image.non_finite_samples = sampler.discarded.load(Ordering::Relaxed);
+
+    image
+}
+
+// The built in shader: the integrator chosen in `options`, with the path tracing ones
+// running `utils::ray_color_with`, or the spectral renderer's equivalent, with the
+// settings from `options`
+pub(crate) fn path_tracer<'a>(
+    world: &'a World,
+    max_depth: u64,
+    options: &'a RenderOptions,
+) -> impl Fn(&Ray, &mut StdRng) -> Vector + Sync + 'a {
+    path_tracer_with(world, max_depth, options, None)
+}
+
+// `path_tracer` with the caustic light from `caustics` if set
+fn path_tracer_with<'a>(
+    world: &'a World,
+    max_depth: u64,
+    options: &'a RenderOptions,
+    caustics: Option<Caustics<'a>>,
+) -> impl Fn(&Ray, &mut StdRng) -> Vector + Sync + 'a {
+    move |camera_ray, rng| {
+        let (r, far) = clipped(camera_ray, options);
+        let r = &r;
+
+        let depth = match options.integrator {
+            Integrator::Path => max_depth,
+            // The camera ray and the one bounce after it
+            Integrator::DirectOnly => max_depth.min(2),
+            Integrator::Normals => {
+                return first_hit(world, r, far).map_or(BLACK, |hit| {
+                    let n = hit.normal.get_unit_vector();
+                    0.5 * Vector::new(
+                        n.data.0 + 1.0,
+                        n.data.1 + 1.0,
+                        n.data.2 + 1.0,
+                        VectorType::Color,
+                    )
+                });
+            }
+            Integrator::AmbientOcclusion { samples, radius } => {
+                let open = first_hit(world, r, far).map_or(1.0, |hit| {
+                    ambient_occlusion_with(
+                        world,
+                        &hit.point,
+                        &hit.normal,
+                        samples,
+                        radius,
+                        r.time,
+                        rng,
+                    )
+                });
+                return Vector::new(open, open, open, VectorType::Color);
+            }
+            Integrator::Albedo => {
+                return match first_hit(world, r, far) {
+                    Some(hit) => hit.material.albedo(&hit),
+                    None => background(world, r, options),
+                };
+            }
+        };
+
+        // Everything along the ray is past the far plane
+        if far < Scalar::INFINITY && first_hit(world, r, far).is_none() {
+            return background(world, r, options);
+        }
+
+        #[cfg(not(feature = "spectral"))]
+        let color = crate::utils::ray_color_with(
+            r,
+            world,
+            depth,
+            options.sky_bottom,
+            options.sky_top,
+            options.depth_exhaustion,
+            options.max_indirect_luminance,
+            caustics,
+            rng,
+        );
+        #[cfg(feature = "spectral")]
+        let color = crate::spectral::ray_color_sampled(
+            r,
+            world,
+            depth,
+            options.sky_bottom,
+            options.sky_top,
+            options.depth_exhaustion,
+            caustics,
+            rng,
+        );
+
+        color
+    }
+}
+
+const BLACK: Vector = Vector {
+    data: (0.0, 0.0, 0.0),
+    data_type: VectorType::Color,
+};
+
+// The first surface along `r` up to `t_max`, looking through the holes in cutouts
+fn first_hit<'a>(world: &'a World, r: &Ray, t_max: Scalar) -> Option<HitRecord<'a>> {
+    let mut hit = world.hit(r, world.t_min(r), t_max)?;
+    let mut travelled = 0.0;
+    while hit.material.passes_through(&hit) {
+        travelled += hit.t;
+        let r = r.continued_from(hit.point);
+        hit = world.hit(&r, world.t_min(&r), t_max - travelled)?;
+    }
+
+    Some(hit)
+}
+
+// The camera ray `r` starting at the near clip distance instead, and how far along
+// it the far clip distance is
+fn clipped(r: &Ray, options: &RenderOptions) -> (Ray, Scalar) {
+    let speed = r.direction.len();
+    let near = options.near.max(0.0) / speed;
+
+    (r.continued_from(r.at(near)), options.far / speed - near)
+}
+
+// What a camera ray that misses everything sees
+fn background(world: &World, r: &Ray, options: &RenderOptions) -> Vector {
+    let (bottom, top) = world.sky_or(options.sky_bottom, options.sky_top);
+    sky_color(&r.direction, bottom, top, world.sky_mapping)
+}
+
+// Opacity and colors of the boxes drawn by `render_with_bvh_overlay`, cycling
+// through the palette by primitive index
+const OVERLAY_ALPHA: Scalar = 0.3;
+const OVERLAY_TINTS: [(Scalar, Scalar, Scalar); 6] = [
+    (1.0, 0.2, 0.2),
+    (0.2, 1.0, 0.2),
+    (0.2, 0.4, 1.0),
+    (1.0, 1.0, 0.2),
+    (1.0, 0.2, 1.0),
+    (0.2, 1.0, 1.0),
+];
+
+// `render_with_options` with each primitive's bounding box drawn over the result as a
+// translucent colored box, for checking the extents the acceleration structures work
+// with. Boxes are not occluded by the geometry, and unbounded primitives get none.
+pub fn render_with_bvh_overlay(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+) -> Image {
+    let mut image = render_with_options(
+        height,
+        width,
+        samples_per_pixel,
+        world,
+        camera.clone(),
+        max_depth,
+        options,
+    );
+    let boxes: Vec<(Aabb, Vector)> = world
+        .objects()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, object)| {
+            let (r, g, b) = OVERLAY_TINTS[index % OVERLAY_TINTS.len()];
+            Some((
+                object.bounding_box()?,
+                Vector::new(r, g, b, VectorType::Color),
+            ))
+        })
+        .collect();
+
+    let bands: Vec<(usize, &mut [Vector])> = image
+        .pixels
+        .chunks_mut(width as usize)
+        .rev()
+        .enumerate()
+        .collect();
+
+    bands.into_par_iter().for_each(|(y, band)| {
+        for (x, pixel) in band.iter_mut().enumerate() {
+            let r = camera.ray_through_pixel(x as u32, y as u32, width, height);
+
+            for (bounds, tint) in &boxes {
+                if bounds.hit(&r, 0.0001, Scalar::INFINITY) {
+                    *pixel = (1.0 - OVERLAY_ALPHA) * *pixel + OVERLAY_ALPHA * *tint;
+                }
+            }
+        }
+    });
+
+    image
+}
+
+// Renders the albedo buffer denoisers take as a guide next to the image: the unlit
+// color of the first surface each sample sees, averaged over the pixel like the
+// render, so edges are antialiased the same way. Misses show the background. The
+// integrator in `options` is ignored.
+pub fn render_albedo(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: &Camera,
+    options: &RenderOptions,
+) -> Image {
+    render_with_options(
+        height,
+        width,
+        samples_per_pixel,
+        world,
+        camera.clone(),
+        1,
+        &RenderOptions {
+            integrator: Integrator::Albedo,
+            ..*options
+        },
+    )
+}
+
+// Renders the left and right eye views of a stereo pair, `ipd` apart. See
+// `Camera::stereo_pair` and `Image::side_by_side`.
+#[allow(clippy::too_many_arguments)]
+pub fn render_stereo(
+    ipd: Scalar,
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: &Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+) -> (Image, Image) {
+    let (left, right) = camera.stereo_pair(ipd);
+    let render_eye = |eye: Camera| {
+        render_with_options(
+            height,
+            width,
+            samples_per_pixel,
+            world,
+            eye,
+            max_depth,
+            options,
+        )
+    };
+
+    (render_eye(left), render_eye(right))
+}
+
+// Renders the frame `levels` times for quick previews, from coarse to fine: each level
+// has twice the width and height of the one before and ends at full size. Every level
+// is an independent render whose samples per pixel shrink with its pixel count, so
+// each takes about a quarter of the time of the next. `on_level` gets every level as
+// soon as it is done, to show or save it; the last one, which is also returned,
+// matches `render_with_options` at full size.
+#[allow(clippy::too_many_arguments)]
+pub fn render_cascade(
+    levels: u32,
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: &Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+    mut on_level: impl FnMut(&Image),
+) -> Image {
+    let render_level = |shrink: u32| {
+        render_with_options(
+            (height / shrink).max(1),
+            (width / shrink).max(1),
+            (samples_per_pixel / shrink.saturating_mul(shrink)).max(1),
+            world,
+            camera.clone(),
+            max_depth,
+            options,
+        )
+    };
+
+    for level in (1..levels).rev() {
+        on_level(&render_level(1 << level.min(31)));
+    }
+    let image = render_level(1);
+    on_level(&image);
+
+    image
+}
+
+// Renders in square tiles of `tile_size` pixels with every sample seeded from `seed`.
+// The result is the same for any tile size or thread count.
+#[allow(clippy::too_many_arguments)]
+pub fn render_tiled(
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+    tile_size: u32,
+    seed: u64,
+) -> Image {
+    render_with_options(
+        height,
+        width,
+        samples_per_pixel,
+        world,
+        camera,
+        max_depth,
+        &RenderOptions {
+            tile_size: Some(tile_size),
+            seed,
+            ..RenderOptions::default()
+        },
+    )
+}
+
+// One row per task
+fn render_scanlines(sampler: &PixelSampler) -> Image {
+    let mut image = Image::new(sampler.width, sampler.height);
+    let bands: Vec<(usize, &mut [Vector])> = image
+        .pixels
+        .chunks_mut(sampler.width as usize)
+        .rev()
+        .enumerate()
+        .collect();
+
+    bands.into_par_iter().for_each(|(y, band)| {
+        for (x, pixel) in band.iter_mut().enumerate() {
+            *pixel = sampler.pixel(x as u32, y as u32);
+        }
+    });
+
+    image
+}
+
+// One tile per task, which balances better than rows when parts of the frame are
+// much more expensive than others. Rayon steals tiles from busy threads.
+fn render_tiles(sampler: &PixelSampler, tile_size: u32) -> Image {
+    let (width, height) = (sampler.width, sampler.height);
+
+    let rendered: Vec<((u32, u32), Vec<Vector>)> = tile_origins(width, height, tile_size)
+        .into_par_iter()
+        .map(|(x0, y0)| ((x0, y0), render_tile(sampler, x0, y0, tile_size)))
+        .collect();
+
+    let mut image = Image::new(width, height);
+    for ((x0, y0), pixels) in rendered {
+        let tile_width = (x0 + tile_size).min(width) - x0;
+        for (i, color) in pixels.into_iter().enumerate() {
+            let x = x0 + i as u32 % tile_width;
+            let y = y0 + i as u32 / tile_width;
+            // `y` counts up from the bottom of the frame, the image stores rows top first
+            image.set(x, height - 1 - y, color);
+        }
+    }
+
+    image
+}
+
+// Lower left corners of the tiles covering the frame, in sampler coordinates
+pub(crate) fn tile_origins(width: u32, height: u32, tile_size: u32) -> Vec<(u32, u32)> {
+    (0..height)
+        .step_by(tile_size as usize)
+        .flat_map(|y| (0..width).step_by(tile_size as usize).map(move |x| (x, y)))
+        .collect()
+}
+
+// The pixels of the tile at (x0, y0), clipped to the frame, bottom row first
+pub(crate) fn render_tile(sampler: &PixelSampler, x0: u32, y0: u32, tile_size: u32) -> Vec<Vector> {
+    let x1 = (x0 + tile_size).min(sampler.width);
+    let y1 = (y0 + tile_size).min(sampler.height);
+
+    (y0..y1)
+        .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+        .map(|(x, y)| sampler.pixel(x, y))
+        .collect()
+}
+
+// Cranley-Patterson rotation of the strata of pixel (`x`, `y`): a random offset that
+// shifts its whole grid, wrapping around the pixel. Keyed like a sample whose index
+// no render reaches.
+fn strata_rotation(seed: u64, x: u32, y: u32) -> (Scalar, Scalar) {
+    let mut rng = StdRng::seed_from_u64(seed_for(seed, x, y, u32::MAX));
+    (rng.gen(), rng.gen())
+}
+
+// Position within the pixel of sample `s` jittered by `jitter` inside its stratum of
+// a `strata`×`strata` grid, after shifting the grid by `rotation`
+fn stratified(
+    s: u32,
+    strata: u32,
+    jitter: (Scalar, Scalar),
+    rotation: (Scalar, Scalar),
+) -> (Scalar, Scalar) {
+    let cell = s % (strata * strata);
+    let (column, row) = ((cell % strata) as Scalar, (cell / strata) as Scalar);
+    let n = strata as Scalar;
+
+    (
+        ((column + jitter.0) / n + rotation.0).fract(),
+        ((row + jitter.1) / n + rotation.1).fract(),
+    )
+}
+
+// Everything needed to compute the color of a single pixel
+pub(crate) struct PixelSampler<'a> {
+    // Tested for camera ray misses when `options.backdrop` is set
+    pub(crate) world: Option<&'a World>,
+    pub(crate) camera: &'a Camera,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) samples_per_pixel: u32,
+    pub(crate) options: &'a RenderOptions,
+    pub(crate) shade: &'a (dyn Fn(&Ray, &mut StdRng) -> Vector + Sync),
+    // Samples left out of pixel averages so far for not being finite
+    pub(crate) discarded: AtomicU64,
+}
+
+impl PixelSampler<'_> {
+    // Average radiance through the pixel `x` across and `y` up from the bottom left.
+    // NaN or infinite samples, from the odd degenerate bounce, are left out of the
+    // average rather than turning the whole pixel into NaN, and counted in
+    // `discarded`.
+    fn pixel(&self, x: u32, y: u32) -> Vector {
+        self.pixel_with(x, y, self.samples_per_pixel)
+    }
+
+    // `pixel` with `samples` samples instead of `samples_per_pixel`
+    pub(crate) fn pixel_with(&self, x: u32, y: u32, samples: u32) -> Vector {
+        let mut pixel_color = Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+        let mut finite = 0;
+
+        let first = self.options.first_sample;
+        for s in first..first + samples {
+            let color = self.sample(x, y, s);
+            if color.is_finite() {
+                pixel_color = pixel_color + color;
+                finite += 1;
+            }
+        }
+
+        let discarded = samples - finite;
+        if discarded > 0 {
+            self.discard(discarded);
+        }
+        if finite == 0 {
+            return pixel_color;
+        }
+
+        let scale: Scalar = 1.0 / finite as Scalar;
+
+        scale * pixel_color
+    }
+
+    // Counts `samples` more samples left out of pixel averages
+    pub(crate) fn discard(&self, samples: u32) {
+        self.discarded.fetch_add(samples as u64, Ordering::Relaxed);
+    }
+
+    // Radiance of the `s`th sample of a pixel, which only depends on its arguments
+    // and the render's seed
+    pub(crate) fn sample(&self, x: u32, y: u32, s: u32) -> Vector {
+        let (width, height) = (self.width, self.height);
+        let (ds, dt) = (
+            1.0 / (width as Scalar - 1.0),
+            1.0 / (height as Scalar - 1.0),
+        );
+
+        let mut rng = StdRng::seed_from_u64(seed_for(self.options.seed, x, y, s));
+        let jitter = (rng.gen::<Scalar>(), rng.gen::<Scalar>());
+        let (dx, dy) = match self.options.strata {
+            Some(strata) if strata > 1 => {
+                let rotation = strata_rotation(self.options.seed, x, y);
+                stratified(s, strata, jitter, rotation)
+            }
+            _ => jitter,
+        };
+        let u = (x as Scalar + dx) / (width as Scalar - 1.0);
+        let v = (y as Scalar + dy) / (height as Scalar - 1.0);
+
+        let color = if self.camera.aberration() == 0.0 {
+            let r = self.camera.get_ray_differential(u, v, ds, dt, &mut rng);
+            self.shade_camera_ray(&r, u, v, &mut rng)
+        } else {
+            // Each channel comes from its own ray
+            let [red, green, blue] = self.camera.get_channel_rays(u, v, ds, dt, &mut rng);
+            Vector::new(
+                self.shade_camera_ray(&red, u, v, &mut rng).data.0,
+                self.shade_camera_ray(&green, u, v, &mut rng).data.1,
+                self.shade_camera_ray(&blue, u, v, &mut rng).data.2,
+                VectorType::Color,
+            )
+        };
+
+        let color = if self.camera.bloom() == 0.0 {
+            color
+        } else {
+            // Part of the light above white is gathered through the wider lens instead
+            let r = self
+                .camera
+                .get_bloom_ray_differential(u, v, ds, dt, &mut rng);
+            let wide = self.shade_camera_ray(&r, u, v, &mut rng);
+
+            color + BLOOM_SHARE * (highlight(&wide) - highlight(&color))
+        };
+
+        self.options.firefly_clamp.apply(color)
+    }
+
+    // Radiance along a ray leaving the camera through the screen point (`u`, `v`)
+    fn shade_camera_ray(&self, r: &Ray, u: Scalar, v: Scalar, rng: &mut StdRng) -> Vector {
+        if let (Some(backdrop), Some(world)) = (self.options.backdrop, self.world) {
+            let (r, far) = clipped(r, self.options);
+            if world.hit(&r, world.t_min(&r), far).is_none() {
+                return backdrop(u, v);
+            }
+        }
+
+        (self.shade)(r, rng)
+    }
+}
+
+// Fraction of a highlight that `Camera::with_bloom` moves to the wider lens
+const BLOOM_SHARE: Scalar = 0.5;
+
+// The part of `color` brighter than white, keeping its hue
+fn highlight(color: &Vector) -> Vector {
+    let luminance = color.luminance();
+    if luminance <= 1.0 {
+        return Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+    }
+
+    (1.0 - 1.0 / luminance) * *color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Material, Metal};
+    use crate::sphere::Sphere;
+    use crate::utils::SkyGradient;
+
+    fn mirror_scene() -> World {
+        let mut world = World::new();
+        for i in 0..3 {
+            world.add(Sphere::new(
+                Vector::new(i as Scalar - 1.0, 0.0, -2.0, VectorType::Point),
+                0.45,
+                Material::Metal(Metal::new(
+                    Vector::new(0.9, 0.6, 0.3, VectorType::Color),
+                    0.0,
+                )),
+            ));
+        }
+        world
+    }
+
+    // `render` of the mirror scene on a pool of `threads` threads
+    fn render_with_threads(threads: usize, render: fn(&World, Camera) -> Image) -> Image {
+        let camera = Camera::new(
+            Vector::new(0.0, 0.5, 1.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            60.0,
+            1.5,
+            0.1,
+            3.0,
+        );
+        let world = mirror_scene();
+
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(|| render(&world, camera))
+    }
+
+    fn single_sphere(color: Vector) -> World {
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            0.5,
+            Material::Lambertian(Lambertian::new(color)),
+        ));
+        world
+    }
+
+    fn integrate(world: &World, integrator: Integrator, r: &Ray, seed: u64) -> Vector {
+        let options = RenderOptions {
+            integrator,
+            ..RenderOptions::default()
+        };
+        let shade = path_tracer(world, 8, &options);
+        shade(r, &mut StdRng::seed_from_u64(seed))
+    }
+
+    fn toward(x: Scalar, y: Scalar, z: Scalar) -> Ray {
+        Ray::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(x, y, z, VectorType::Vector),
+        )
+    }
+
+    #[test]
+    fn flat_integrators_show_the_first_hit() {
+        let world = single_sphere(Vector::new(0.8, 0.3, 0.2, VectorType::Color));
+        let (front, miss) = (toward(0.0, 0.0, -1.0), toward(0.0, 1.0, 0.0));
+        let ao = Integrator::AmbientOcclusion {
+            samples: 64,
+            radius: 10.0,
+        };
+
+        assert_eq!(
+            integrate(&world, Integrator::Normals, &front, 1),
+            Vector::new(0.5, 0.5, 1.0, VectorType::Color)
+        );
+        assert_eq!(integrate(&world, Integrator::Normals, &miss, 1), BLACK);
+
+        assert_eq!(
+            integrate(&world, Integrator::Albedo, &front, 1),
+            Vector::new(0.8, 0.3, 0.2, VectorType::Color)
+        );
+        assert_eq!(integrate(&world, Integrator::Albedo, &miss, 1), SKY_TOP);
+
+        // Nothing else in the scene, so the front of the sphere is fully open
+        let white = Vector::new(1.0, 1.0, 1.0, VectorType::Color);
+        assert_eq!(integrate(&world, ao, &front, 1), white);
+        assert_eq!(integrate(&world, ao, &miss, 1), white);
+    }
+
+    #[test]
+    fn albedo_buffer_ignores_the_lighting() {
+        let red = Vector::new(0.8, 0.1, 0.1, VectorType::Color);
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            30.0,
+            1.0,
+            0.0,
+            2.0,
+        );
+        let night = SkyGradient::new(BLACK, Vector::new(0.01, 0.01, 0.02, VectorType::Color));
+        let albedo =
+            |world: &World| render_albedo(9, 9, 4, world, &camera, &RenderOptions::default());
+
+        let day = albedo(&single_sphere(red));
+        let dark = albedo(&single_sphere(red).with_sky(night));
+
+        let center = 4 * 9 + 4;
+        assert_eq!(day.pixels[center], red);
+        assert_eq!(dark.pixels[center], red);
+        // Only the background changes
+        assert_ne!(day.pixels[0], dark.pixels[0]);
+    }
+
+    #[test]
+    fn clip_distances_hide_near_and_far_surfaces() {
+        let (red, blue) = (
+            Vector::new(0.8, 0.1, 0.1, VectorType::Color),
+            Vector::new(0.1, 0.1, 0.8, VectorType::Color),
+        );
+        // The red sphere's front is 1.5 away, the blue one's behind it 4.5
+        let mut world = single_sphere(red);
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -5.0, VectorType::Point),
+            0.5,
+            Material::Lambertian(Lambertian::new(blue)),
+        ));
+        let seen = |integrator: Integrator, near: Scalar, far: Scalar| {
+            let options = RenderOptions {
+                integrator,
+                near,
+                far,
+                ..RenderOptions::default()
+            };
+            let shade = path_tracer(&world, 8, &options);
+            // Not a unit direction, which must not change the distances
+            shade(&toward(0.0, 0.0, -4.0), &mut StdRng::seed_from_u64(1))
+        };
+
+        assert_eq!(seen(Integrator::Albedo, 0.0, Scalar::INFINITY), red);
+        assert_eq!(seen(Integrator::Albedo, 3.0, Scalar::INFINITY), blue);
+        assert_eq!(seen(Integrator::Albedo, 0.0, 4.0), red);
+        assert_eq!(seen(Integrator::Albedo, 0.0, 1.0), SKY_BOTTOM);
+        assert_eq!(seen(Integrator::Albedo, 3.0, 4.0), SKY_BOTTOM);
+
+        // Past the far plane the path tracer sees the sky as well
+        assert_eq!(seen(Integrator::Path, 0.0, 1.0), SKY_BOTTOM);
+        assert_ne!(seen(Integrator::Path, 0.0, 4.0), SKY_BOTTOM);
+    }
+
+    #[test]
+    fn path_integrator_converges_to_the_lit_sphere() {
+        let world = single_sphere(Vector::new(0.5, 0.5, 0.5, VectorType::Color));
+        let front = toward(0.0, 0.0, -1.0);
+
+        let samples = 4000;
+        let mut total = Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+        for seed in 0..samples {
+            total = total + integrate(&world, Integrator::Path, &front, seed);
+        }
+        let mean = total / samples as Scalar;
+
+        // The front faces sideways, so cosine weighted bounces see the sky rise on
+        // average 2 / (3π) of the way from the horizon color to the zenith color
+        let rise = 2.0 / (3.0 * crate::vector::consts::PI);
+        let expected = 0.5 * ((1.0 - rise) * SKY_BOTTOM + rise * SKY_TOP);
+        for (actual, expected) in [
+            (mean.data.0, expected.data.0),
+            (mean.data.1, expected.data.1),
+            (mean.data.2, expected.data.2),
+        ] {
+            assert!(
+                (actual - expected).abs() < 0.03,
+                "{} vs {}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn direct_only_stops_after_one_bounce() {
+        // A lone convex sphere never sees itself, so one bounce is the whole path
+        let world = single_sphere(Vector::new(0.5, 0.5, 0.5, VectorType::Color));
+        for seed in 0..20 {
+            let front = toward(0.1, 0.2, -1.0);
+            assert_eq!(
+                integrate(&world, Integrator::DirectOnly, &front, seed),
+                integrate(&world, Integrator::Path, &front, seed)
+            );
+        }
+
+        // Light that reaches the camera off two mirrors is left out
+        let mut world = World::new();
+        let mirror = || {
+            Material::Metal(Metal::new(
+                Vector::new(0.5, 0.5, 0.5, VectorType::Color),
+                0.0,
+            ))
+        };
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            0.5,
+            mirror(),
+        ));
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.3, 2.0, VectorType::Point),
+            0.5,
+            mirror(),
+        ));
+        let front = toward(0.0, 0.0, -1.0);
+
+        assert_eq!(integrate(&world, Integrator::DirectOnly, &front, 0), BLACK);
+        assert!(integrate(&world, Integrator::Path, &front, 0).luminance() > 0.1);
+    }
+
+    #[test]
+    fn cascade_doubles_up_to_the_full_render() {
+        let camera = Camera::new(
+            Vector::new(0.0, 0.5, 1.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            60.0,
+            1.5,
+            0.1,
+            3.0,
+        );
+        let world = mirror_scene();
+        let options = RenderOptions {
+            seed: 3,
+            ..RenderOptions::default()
+        };
+
+        let mut sizes = vec![];
+        let last = render_cascade(3, 20, 30, 8, &world, &camera, 6, &options, |level| {
+            sizes.push((level.width, level.height))
+        });
+        let direct = render_with_options(20, 30, 8, &world, camera, 6, &options);
+
+        assert_eq!(sizes, vec![(7, 5), (15, 10), (30, 20)]);
+        for (a, b) in last.pixels.iter().zip(direct.pixels.iter()) {
+            assert_eq!(a.data, b.data);
+        }
+    }
+
+    #[test]
+    fn per_channel_clamp_only_touches_bright_channels() {
+        let caustic = Vector::new(0.4, 30.0, 2.0, VectorType::Color);
+
+        let clipped = FireflyClamp::PerChannel(5.0).apply(caustic);
+        assert_eq!(clipped.data, (0.4, 5.0, 2.0));
+
+        // The luminance clamp dims every channel to keep the hue
+        let scaled = FireflyClamp::Luminance(5.0).apply(caustic);
+        assert!(scaled.data.0 < 0.4 && scaled.data.2 < 2.0);
+        assert!((scaled.luminance() - 5.0).abs() < 1e-4);
+
+        let dim = Vector::new(0.4, 0.9, 2.0, VectorType::Color);
+        assert_eq!(FireflyClamp::PerChannel(5.0).apply(dim), dim);
+        assert_eq!(FireflyClamp::None.apply(caustic), caustic);
+    }
+
+    #[test]
+    fn firefly_clamp_applies_to_every_sample() {
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let options = RenderOptions {
+            firefly_clamp: FireflyClamp::PerChannel(1.0),
+            ..RenderOptions::default()
+        };
+
+        let image = render_with(4, 4, 3, camera, &options, |_, _| {
+            Vector::new(0.5, 8.0, 1.0, VectorType::Color)
+        });
+
+        for pixel in &image.pixels {
+            assert_eq!(pixel.data, (0.5, 1.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn output_is_independent_of_thread_count() {
+        let renders: [fn(&World, Camera) -> Image; 2] = [
+            |world, camera| render(12, 18, 4, world, camera, 8),
+            |world, camera| crate::ao::render_ao(12, 18, 4, 8, 0.5, world, &camera),
+        ];
+
+        for render in renders {
+            let single = render_with_threads(1, render);
+            let parallel = render_with_threads(8, render);
+
+            for (a, b) in single.pixels.iter().zip(parallel.pixels.iter()) {
+                assert_eq!(a.data, b.data);
+            }
+        }
+    }
+
+    #[test]
+    fn neighbors_shift_their_strata_differently() {
+        let left = strata_rotation(7, 10, 20);
+        let right = strata_rotation(7, 11, 20);
+        let above = strata_rotation(7, 10, 21);
+
+        assert_ne!(left, right);
+        assert_ne!(left, above);
+    }
+
+    #[test]
+    fn shifted_strata_still_cover_the_pixel_evenly() {
+        let mut rng = StdRng::seed_from_u64(3);
+
+        for (x, y) in [(0, 0), (1, 0), (5, 9)] {
+            let rotation = strata_rotation(0, x, y);
+            let mut hits = [[0; 4]; 4];
+
+            for s in 0..16 {
+                let (dx, dy) = stratified(s, 4, (rng.gen(), rng.gen()), rotation);
+                assert!((0.0..1.0).contains(&dx) && (0.0..1.0).contains(&dy));
+
+                // Undoing the shift puts exactly one sample in each stratum
+                let column = ((dx - rotation.0).rem_euclid(1.0) * 4.0) as usize;
+                let row = ((dy - rotation.1).rem_euclid(1.0) * 4.0) as usize;
+                hits[row.min(3)][column.min(3)] += 1;
+            }
+
+            assert_eq!(hits, [[1; 4]; 4]);
+        }
+    }
+
+    #[test]
+    fn tile_size_does_not_change_the_image() {
+        let camera = || {
+            Camera::new(
+                Vector::new(0.0, 0.5, 1.0, VectorType::Point),
+                Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+                Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+                60.0,
+                1.5,
+                0.1,
+                3.0,
+            )
+        };
+        let mut world = mirror_scene();
+        world.add(Sphere::new(
+            Vector::new(0.0, -100.5, -2.0, VectorType::Point),
+            100.0,
+            Material::gray(),
+        ));
+
+        let small = render_tiled(45, 70, 2, &world, camera(), 8, 8, 11);
+        let large = render_tiled(45, 70, 2, &world, camera(), 8, 64, 11);
+        let scanlines = render_with_options(
+            45,
+            70,
+            2,
+            &world,
+            camera(),
+            8,
+            &RenderOptions {
+                tile_size: None,
+                seed: 11,
+                ..RenderOptions::default()
+            },
+        );
+
+        assert_eq!(small.to_rgb8(), large.to_rgb8());
+        assert_eq!(small.to_rgb8(), scanlines.to_rgb8());
+        for (a, b) in small.pixels.iter().zip(large.pixels.iter()) {
+            assert_eq!(a.data, b.data);
+        }
+    }
+
+    #[test]
+    fn overlay_shades_the_corners_of_the_bounding_box() {
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -3.0, VectorType::Point),
+            1.0,
+            Material::gray(),
+        ));
+        // The front face of the box projects to the middle half of the frame
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let black = Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+        let options = RenderOptions {
+            sky_bottom: black,
+            sky_top: black,
+            ..RenderOptions::default()
+        };
+
+        let image = render_with_bvh_overlay(101, 101, 1, &world, camera, 4, &options);
+
+        // Just inside each corner of the box, where the sphere itself does not reach
+        for (x, y) in [(26, 26), (26, 73), (73, 26), (73, 73)] {
+            assert!(image.get(x, y).luminance() > 0.0, "corner ({}, {})", x, y);
+        }
+        assert_eq!(image.get(0, 0).data, black.data);
+        assert_eq!(image.get(10, 50).data, black.data);
+    }
+
+    #[test]
+    fn stereo_pair_has_two_full_size_views() {
+        let camera = Camera::new(
+            Vector::new(0.0, 0.5, 1.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            60.0,
+            1.5,
+            0.0,
+            3.0,
+        );
+        let world = mirror_scene();
+
+        let (left, right) = render_stereo(
+            0.2,
+            12,
+            18,
+            2,
+            &world,
+            &camera,
+            8,
+            &RenderOptions::default(),
+        );
+
+        for eye in [&left, &right] {
+            assert_eq!((eye.width, eye.height), (18, 12));
+            assert_eq!(eye.pixels.len(), 18 * 12);
+        }
+        assert_ne!(left.to_rgb8(), right.to_rgb8());
+
+        let both = Image::side_by_side(&left, &right);
+        assert_eq!((both.width, both.height), (36, 12));
+        assert_eq!(both.get(0, 5).data, left.get(0, 5).data);
+        assert_eq!(both.get(18 + 3, 5).data, right.get(3, 5).data);
+    }
+
+    #[test]
+    fn bloom_spreads_highlights_far_from_focus() {
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            20.0,
+            1.0,
+            0.2,
+            2.0,
+        );
+        // Pixels lit by a small light `distance` straight ahead, `brightness` times
+        // white, against black
+        let lit_pixels = |camera: &Camera, distance: Scalar, brightness: Scalar| {
+            let light = Vector::new(0.0, 0.0, -distance, VectorType::Point);
+            let image = render_with(
+                24,
+                24,
+                64,
+                camera.clone(),
+                &RenderOptions::default(),
+                |r, _| {
+                    let to_light = light - r.origin;
+                    let along = to_light.dot(&r.direction) / r.direction.len();
+                    let off_axis = (to_light.length_squared() - along * along).sqrt();
+                    if along > 0.0 && off_axis < 0.01 * distance {
+                        Vector::new(brightness, brightness, brightness, VectorType::Color)
+                    } else {
+                        BLACK
+                    }
+                },
+            );
+            image
+                .pixels
+                .iter()
+                .filter(|pixel| pixel.luminance() > 0.01)
+                .count()
+        };
+        let bloom = camera.clone().with_bloom(2.0);
+
+        // Highlights far from focus spill into many more neighbouring pixels
+        assert!(lit_pixels(&bloom, 20.0, 20.0) > 2 * lit_pixels(&camera, 20.0, 20.0));
+        assert!(lit_pixels(&bloom, 20.0, 20.0) > 4 * lit_pixels(&bloom, 2.0, 20.0));
+        // Nothing brighter than white blooms
+        assert_eq!(
+            lit_pixels(&bloom, 20.0, 0.9),
+            lit_pixels(&camera, 20.0, 0.9)
+        );
+    }
+
+    #[test]
+    fn non_finite_samples_are_left_out_of_the_average() {
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            60.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let color = Vector::new(0.25, 0.5, 0.75, VectorType::Color);
+        let nan = Vector::new(Scalar::NAN, 0.0, 0.0, VectorType::Color);
+
+        // Roughly one sample in five is poisoned
+        let image = render_with(
+            6,
+            6,
+            10,
+            camera.clone(),
+            &RenderOptions::default(),
+            |_, rng| {
+                if rng.gen::<Scalar>() < 0.2 {
+                    nan
+                } else {
+                    color
+                }
+            },
+        );
+
+        for pixel in &image.pixels {
+            assert_eq!(*pixel, color);
+        }
+        // Counted per render, so renders running alongside do not add to it
+        assert!(image.non_finite_samples > 0);
+        assert!(image.non_finite_samples < 6 * 6 * 10 / 2);
+        let clean = render_with(6, 6, 10, camera, &RenderOptions::default(), |_, _| color);
+        assert_eq!(clean.non_finite_samples, 0);
+    }
+
+    #[test]
+    fn constant_shader_gives_a_uniform_image() {
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            60.0,
+            1.5,
+            0.1,
+            1.0,
+        );
+        let color = Vector::new(0.25, 0.5, 0.75, VectorType::Color);
+
+        let image = render_with(9, 14, 3, camera, &RenderOptions::default(), |_, _| color);
+
+        assert_eq!((image.width, image.height), (14, 9));
+        for pixel in &image.pixels {
+            assert_eq!(*pixel, color);
+        }
+    }
+
+    #[test]
+    fn backdrop_depends_on_screen_position_not_direction() {
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            0.01,
+            Material::gray(),
+        ));
+        // A two degree view, so every ray leaves in almost the same direction
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            2.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let options = RenderOptions {
+            backdrop: Some(|u, _| Vector::new(u, 0.0, 0.0, VectorType::Color)),
+            ..RenderOptions::default()
+        };
+
+        let image = render_with_options(11, 11, 1, &world, camera, 4, &options);
+
+        assert!(image.get(0, 5).data.0 < 0.2);
+        assert!(image.get(10, 5).data.0 > 0.9);
+        assert_eq!(image.get(10, 5).data.2, 0.0);
+        // The sphere in the middle is lit by the sky as usual
+        assert!(image.get(5, 5).data.2 > 0.0);
+    }
+}
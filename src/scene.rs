@@ -0,0 +1,706 @@
+use crate::aabb::Aabb;
+use crate::camera::Camera;
+use crate::json::{self, JsonValue};
+use crate::light::PointLight;
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::quad::Quad;
+use crate::sphere::Sphere;
+use crate::utils::SkyGradient;
+use crate::vector::{Scalar, Vector, VectorType};
+use crate::world::World;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+// Relative odds of each material being picked by the scene generators. The weights
+// don't need to sum to one.
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialWeights {
+    pub lambertian: Scalar,
+    pub metal: Scalar,
+    pub dielectric: Scalar,
+}
+
+impl Default for MaterialWeights {
+    // The same 80/15/5 split random_scene uses
+    fn default() -> Self {
+        Self {
+            lambertian: 0.8,
+            metal: 0.15,
+            dielectric: 0.05,
+        }
+    }
+}
+
+// The cover scene: a field of small random spheres around three large ones. The same
+// seed always produces the same layout and materials.
+pub fn random_scene(seed: u64) -> Vec<Sphere> {
+    random_scene_with(&mut StdRng::seed_from_u64(seed))
+}
+
+// `random_scene` drawn from any generator
+pub fn random_scene_with<R: Rng>(rng: &mut R) -> Vec<Sphere> {
+    let mut world: Vec<Sphere> = vec![];
+
+    let ground_material = Lambertian::new(Vector::new(0.5, 0.5, 0.5, VectorType::Color));
+    world.push(Sphere::new(
+        Vector::new(0.0, -1000.0, 0.0, VectorType::Point),
+        1000.0,
+        Material::Lambertian(ground_material),
+    ));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Vector::new(
+                a as Scalar + 0.9 * rng.gen::<Scalar>(),
+                0.2,
+                b as Scalar + 0.9 * rng.gen::<Scalar>(),
+                VectorType::Point,
+            );
+
+            if (center - Vector::new(4.0, 0.2, 0.0, VectorType::Point)).len() > 0.9 {
+                let sphere_material = random_material(rng, &MaterialWeights::default());
+                world.push(Sphere::new(center, 0.2, sphere_material));
+            }
+        }
+    }
+
+    let material1 = Dielectric::new(1.5);
+    world.push(Sphere::new(
+        Vector::new(0.0, 1.0, 0.0, VectorType::Point),
+        1.0,
+        Material::Dielectric(material1),
+    ));
+
+    let material2 = Lambertian::new(Vector::new(0.4, 0.2, 0.1, VectorType::Color));
+    world.push(Sphere::new(
+        Vector::new(-4.0, 1.0, 0.0, VectorType::Point),
+        1.0,
+        Material::Lambertian(material2),
+    ));
+
+    let material3 = Metal::new(Vector::new(0.7, 0.6, 0.6, VectorType::Color), 0.0);
+    world.push(Sphere::new(
+        Vector::new(4.0, 1.0, 0.0, VectorType::Point),
+        1.0,
+        Material::Metal(material3),
+    ));
+
+    world
+}
+
+// Lays out an `nx` by `ny` grid of small spheres on the XZ plane, centered on the origin.
+// `jitter` moves each sphere by up to that fraction of `spacing`, 0 gives a regular grid.
+// The same seed always produces the same scene.
+pub fn grid_scene(
+    nx: u32,
+    ny: u32,
+    spacing: Scalar,
+    jitter: Scalar,
+    seed: u64,
+    material_weights: MaterialWeights,
+) -> Vec<Sphere> {
+    let mut world: Vec<Sphere> = vec![];
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let radius = 0.2 * spacing;
+    let offset_x = (nx as Scalar - 1.0) * spacing / 2.0;
+    let offset_z = (ny as Scalar - 1.0) * spacing / 2.0;
+
+    for a in 0..nx {
+        for b in 0..ny {
+            let center = Vector::new(
+                a as Scalar * spacing - offset_x + jitter * spacing * rng.gen_range(-0.5..0.5),
+                radius,
+                b as Scalar * spacing - offset_z + jitter * spacing * rng.gen_range(-0.5..0.5),
+                VectorType::Point,
+            );
+
+            world.push(Sphere::new(
+                center,
+                radius,
+                random_material(&mut rng, &material_weights),
+            ));
+        }
+    }
+
+    world
+}
+
+// Radii drawn by `packed_scene`, as fractions of the smallest side of its bounds
+const PACKED_MIN_RADIUS: Scalar = 0.02;
+const PACKED_MAX_RADIUS: Scalar = 0.1;
+// Places tried for each sphere before the bounds are taken to be full
+const PACKING_ATTEMPTS: u32 = 1000;
+
+// Up to `n` spheres of random sizes and materials inside `bounds`, none of them
+// overlapping each other or sinking below the ground, which is the bottom of
+// `bounds`. Each sphere is placed by trying random spots until one is free, so a
+// crowded volume can return fewer than `n`. The same seed always produces the same
+// scene.
+pub fn packed_scene(n: usize, seed: u64, bounds: Aabb) -> Vec<Sphere> {
+    let mut world: Vec<Sphere> = vec![];
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let size = bounds.max - bounds.min;
+    let smallest = size.data.0.min(size.data.1).min(size.data.2);
+    if smallest <= 0.0 {
+        return world;
+    }
+
+    while world.len() < n {
+        let radius = smallest * rng.gen_range(PACKED_MIN_RADIUS..PACKED_MAX_RADIUS);
+        let mut place = |min: Scalar, max: Scalar| rng.gen_range(min + radius..max - radius);
+
+        let free = (0..PACKING_ATTEMPTS)
+            .map(|_| {
+                Vector::new(
+                    place(bounds.min.data.0, bounds.max.data.0),
+                    place(bounds.min.data.1, bounds.max.data.1),
+                    place(bounds.min.data.2, bounds.max.data.2),
+                    VectorType::Point,
+                )
+            })
+            .find(|center| {
+                world
+                    .iter()
+                    .all(|other| (*center - other.center()).len() >= radius + other.radius())
+            });
+
+        match free {
+            Some(center) => world.push(Sphere::new(
+                center,
+                radius,
+                random_material(&mut rng, &MaterialWeights::default()),
+            )),
+            None => break,
+        }
+    }
+
+    world
+}
+
+fn random_material<R: Rng>(rng: &mut R, weights: &MaterialWeights) -> Material {
+    let total = weights.lambertian + weights.metal + weights.dielectric;
+    let choose_material = rng.gen::<Scalar>() * total;
+
+    if choose_material < weights.lambertian {
+        let albedo = Vector::new(rng.gen(), rng.gen(), rng.gen(), VectorType::Color)
+            * Vector::new(rng.gen(), rng.gen(), rng.gen(), VectorType::Color);
+        Material::Lambertian(Lambertian::new(albedo))
+    } else if choose_material < weights.lambertian + weights.metal {
+        let albedo = Vector::new(
+            rng.gen_range(0.5..1.0),
+            rng.gen_range(0.5..1.0),
+            rng.gen_range(0.5..1.0),
+            VectorType::Color,
+        );
+        let fuzz = rng.gen::<Scalar>();
+        Material::Metal(Metal::new(albedo, fuzz))
+    } else {
+        Material::Dielectric(Dielectric::new(1.5))
+    }
+}
+
+// Largest width or height a scene file may ask for
+const MAX_IMAGE_SIDE: u32 = 16384;
+
+// Everything needed to render a scene file
+pub struct SceneDescription {
+    pub world: World,
+    pub camera: Camera,
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u64,
+    pub materials: MaterialLibrary,
+}
+
+// The materials a scene file defines by name under "materials", for objects to
+// refer to instead of spelling out their own. Every object naming the same entry
+// gets a clone of it, which shares its textures.
+#[derive(Clone, Default)]
+pub struct MaterialLibrary {
+    pub materials: HashMap<String, Material>,
+}
+
+impl MaterialLibrary {
+    pub fn get(&self, name: &str) -> Option<&Material> {
+        self.materials.get(name)
+    }
+
+    fn parse(materials: &JsonValue) -> Result<Self, SceneError> {
+        let members = materials
+            .as_object()
+            .ok_or_else(|| invalid("'materials' must be an object"))?;
+
+        let mut library = Self::default();
+        for (name, material) in members {
+            library
+                .materials
+                .insert(name.clone(), parse_material(material)?);
+        }
+        Ok(library)
+    }
+
+    // The material an object's "material" stands for: the name of an entry in the
+    // library or a material of its own
+    fn resolve(&self, material: &JsonValue) -> Result<Material, SceneError> {
+        match material.as_str() {
+            Some(name) => self
+                .get(name)
+                .cloned()
+                .ok_or_else(|| invalid(&format!("undefined material '{}'", name))),
+            None => parse_material(material),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(json::ParseError),
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(error) => write!(f, "could not read scene: {}", error),
+            SceneError::Parse(error) => write!(f, "malformed scene JSON: {}", error),
+            SceneError::Invalid(message) => write!(f, "invalid scene: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(error: io::Error) -> Self {
+        SceneError::Io(error)
+    }
+}
+
+impl From<json::ParseError> for SceneError {
+    fn from(error: json::ParseError) -> Self {
+        SceneError::Parse(error)
+    }
+}
+
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<SceneDescription, SceneError> {
+    parse_scene(&std::fs::read_to_string(path)?)
+}
+
+// Scene files are JSON objects of the form
+//
+// {
+//   "width": 400, "aspect_ratio": 1.5, "samples_per_pixel": 16, "max_depth": 50,
+//   "camera": { "look_from": [13, 2, 3], "look_at": [0, 0, 0], "vfov": 20 },
+//   "materials": { "glass": { "type": "dielectric", "ir": 1.5 } },
+//   "objects": [
+//     { "type": "sphere", "center": [0, 1, 0], "radius": 1, "material": "glass" },
+//     { "type": "sphere", "center": [4, 1, 0], "radius": 1,
+//       "material": { "type": "metal", "albedo": [0.7, 0.6, 0.5] } }
+//   ],
+//   "point_lights": [ { "position": [0, 5, 0], "color": [1, 1, 1] } ],
+//   "sky": { "bottom": [1, 1, 1], "top": [0.5, 0.7, 1] }
+// }
+//
+// Everything except the camera's look_from/look_at has a default.
+pub fn parse_scene(text: &str) -> Result<SceneDescription, SceneError> {
+    let root = json::parse(text)?;
+
+    let width = number_or(&root, "width", 400.0)?;
+    let aspect_ratio = number_or(&root, "aspect_ratio", 3.0 / 2.0)?;
+    if !(aspect_ratio > 0.0 && aspect_ratio.is_finite()) {
+        return Err(invalid("'aspect_ratio' must be a positive number"));
+    }
+    let height = match root.get("height") {
+        Some(_) => number(&root, "height")?,
+        None => (width / aspect_ratio).floor(),
+    };
+    // Checked before anything is allocated for the image, so a bad file is reported
+    // instead of running out of memory
+    let sides = 2.0..=MAX_IMAGE_SIDE as Scalar;
+    if !sides.contains(&width) || !sides.contains(&height) {
+        return Err(invalid(&format!(
+            "the image must be from 2x2 to {0}x{0} pixels",
+            MAX_IMAGE_SIDE
+        )));
+    }
+    let (width, height) = (width as u32, height as u32);
+
+    // Every pixel is an average of its samples, which needs at least one
+    let samples_per_pixel = number_or(&root, "samples_per_pixel", 16.0)?;
+    if samples_per_pixel < 1.0 {
+        return Err(invalid("'samples_per_pixel' must be at least 1"));
+    }
+
+    let camera = parse_camera(field(&root, "camera")?, width as Scalar / height as Scalar)?;
+
+    let materials = match root.get("materials") {
+        Some(materials) => MaterialLibrary::parse(materials)?,
+        None => MaterialLibrary::default(),
+    };
+
+    let mut world = World::new();
+    if let Some(objects) = root.get("objects") {
+        for object in array(objects, "objects")? {
+            parse_object(object, &materials, &mut world)?;
+        }
+    }
+    if let Some(lights) = root.get("point_lights") {
+        for light in array(lights, "point_lights")? {
+            world.add_point_light(PointLight::new(
+                vector(light, "position", VectorType::Point)?,
+                vector(light, "color", VectorType::Color)?,
+            ));
+        }
+    }
+
+    if let Some(sky) = root.get("sky") {
+        world.sky = Some(SkyGradient::new(
+            vector(sky, "bottom", VectorType::Color)?,
+            vector(sky, "top", VectorType::Color)?,
+        ));
+    }
+
+    Ok(SceneDescription {
+        world,
+        camera,
+        width,
+        height,
+        samples_per_pixel: samples_per_pixel as u32,
+        max_depth: number_or(&root, "max_depth", 50.0)? as u64,
+        materials,
+    })
+}
+
+fn parse_camera(camera: &JsonValue, aspect_ratio: Scalar) -> Result<Camera, SceneError> {
+    let look_from = vector(camera, "look_from", VectorType::Point)?;
+    let look_at = vector(camera, "look_at", VectorType::Point)?;
+    let vup = match camera.get("vup") {
+        Some(_) => vector(camera, "vup", VectorType::Vector)?,
+        None => Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+    };
+
+    Ok(Camera::new(
+        look_from,
+        look_at,
+        vup,
+        number_or(camera, "vfov", 20.0)?,
+        aspect_ratio,
+        number_or(camera, "aperture", 0.0)?,
+        number_or(camera, "focus_distance", (look_from - look_at).len())?,
+    ))
+}
+
+fn parse_object(
+    object: &JsonValue,
+    materials: &MaterialLibrary,
+    world: &mut World,
+) -> Result<(), SceneError> {
+    let material = materials.resolve(field(object, "material")?)?;
+
+    match string(object, "type")? {
+        "sphere" => world.add(Sphere::new(
+            vector(object, "center", VectorType::Point)?,
+            number(object, "radius")?,
+            material,
+        )),
+        "quad" => world.add(Quad::new(
+            vector(object, "q", VectorType::Point)?,
+            vector(object, "u", VectorType::Vector)?,
+            vector(object, "v", VectorType::Vector)?,
+            material,
+        )),
+        other => return Err(invalid(&format!("unknown object type '{}'", other))),
+    }
+
+    Ok(())
+}
+
+fn parse_material(material: &JsonValue) -> Result<Material, SceneError> {
+    match string(material, "type")? {
+        "lambertian" => Ok(Material::Lambertian(Lambertian::new(vector(
+            material,
+            "albedo",
+            VectorType::Color,
+        )?))),
+        "metal" => Ok(Material::Metal(Metal::new(
+            vector(material, "albedo", VectorType::Color)?,
+            number_or(material, "fuzz", 0.0)?,
+        ))),
+        "dielectric" => Ok(Material::Dielectric(Dielectric::new(number(
+            material, "ir",
+        )?))),
+        other => Err(invalid(&format!("unknown material type '{}'", other))),
+    }
+}
+
+fn invalid(message: &str) -> SceneError {
+    SceneError::Invalid(message.to_string())
+}
+
+fn field<'a>(value: &'a JsonValue, key: &str) -> Result<&'a JsonValue, SceneError> {
+    value
+        .get(key)
+        .ok_or_else(|| invalid(&format!("missing '{}'", key)))
+}
+
+fn number(value: &JsonValue, key: &str) -> Result<Scalar, SceneError> {
+    field(value, key)?
+        .as_f64()
+        .map(|number| number as Scalar)
+        .ok_or_else(|| invalid(&format!("'{}' must be a number", key)))
+}
+
+fn number_or(value: &JsonValue, key: &str, default: Scalar) -> Result<Scalar, SceneError> {
+    match value.get(key) {
+        Some(_) => number(value, key),
+        None => Ok(default),
+    }
+}
+
+fn string<'a>(value: &'a JsonValue, key: &str) -> Result<&'a str, SceneError> {
+    field(value, key)?
+        .as_str()
+        .ok_or_else(|| invalid(&format!("'{}' must be a string", key)))
+}
+
+fn array<'a>(value: &'a JsonValue, key: &str) -> Result<&'a [JsonValue], SceneError> {
+    value
+        .as_array()
+        .ok_or_else(|| invalid(&format!("'{}' must be an array", key)))
+}
+
+fn vector(value: &JsonValue, key: &str, data_type: VectorType) -> Result<Vector, SceneError> {
+    let components = field(value, key)?
+        .as_array()
+        .filter(|components| components.len() == 3)
+        .and_then(|components| {
+            Some((
+                components[0].as_f64()? as Scalar,
+                components[1].as_f64()? as Scalar,
+                components[2].as_f64()? as Scalar,
+            ))
+        })
+        .ok_or_else(|| invalid(&format!("'{}' must be an array of three numbers", key)))?;
+
+    Ok(Vector::new(
+        components.0,
+        components.1,
+        components.2,
+        data_type,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn random_scene_is_reproducible_from_its_seed() {
+        let layout = |seed: u64| {
+            random_scene(seed)
+                .iter()
+                .map(|sphere| {
+                    (
+                        sphere.center().data,
+                        sphere.radius(),
+                        sphere.material().kind(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(layout(42), layout(42));
+        assert_ne!(layout(42), layout(43));
+    }
+
+    #[test]
+    fn grid_scene_has_one_sphere_per_cell() {
+        let world = grid_scene(3, 3, 1.0, 0.0, 7, MaterialWeights::default());
+
+        assert_eq!(world.len(), 9);
+    }
+
+    #[test]
+    fn grid_scene_is_centered_without_jitter() {
+        let world = grid_scene(3, 2, 2.0, 0.0, 7, MaterialWeights::default());
+
+        assert_eq!(world.len(), 6);
+        assert_eq!(
+            world[0].center(),
+            Vector::new(-2.0, 0.4, -1.0, VectorType::Point)
+        );
+        assert_eq!(
+            world[5].center(),
+            Vector::new(2.0, 0.4, 1.0, VectorType::Point)
+        );
+    }
+
+    #[test]
+    fn grid_scene_respects_material_weights() {
+        let weights = MaterialWeights {
+            lambertian: 0.0,
+            metal: 1.0,
+            dielectric: 0.0,
+        };
+        let world = grid_scene(4, 4, 1.0, 0.5, 7, weights);
+
+        assert!(world
+            .iter()
+            .all(|sphere| matches!(sphere.material(), Material::Metal(_))));
+    }
+
+    #[test]
+    fn packed_scene_spheres_never_overlap() {
+        let bounds = Aabb::new(
+            Vector::new(-2.0, 0.0, -2.0, VectorType::Point),
+            Vector::new(2.0, 2.0, 2.0, VectorType::Point),
+        );
+        let world = packed_scene(200, 5, bounds);
+        assert_eq!(world.len(), 200);
+
+        for (i, a) in world.iter().enumerate() {
+            assert!(a.center().data.1 - a.radius() >= 0.0);
+            for b in &world[i + 1..] {
+                assert!((a.center() - b.center()).len() >= a.radius() + b.radius());
+            }
+        }
+
+        let centers = |seed: u64| {
+            packed_scene(20, seed, bounds)
+                .iter()
+                .map(|sphere| sphere.center().data)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(centers(5), centers(5));
+        assert_ne!(centers(5), centers(6));
+    }
+
+    #[test]
+    fn parses_scene_file() {
+        let scene = parse_scene(
+            r#"{
+                "width": 40,
+                "aspect_ratio": 2,
+                "samples_per_pixel": 3,
+                "camera": { "look_from": [0, 0, 1], "look_at": [0, 0, 0] },
+                "objects": [
+                    { "type": "sphere", "center": [0, 0, -1], "radius": 0.5,
+                      "material": { "type": "metal", "albedo": [0.8, 0.8, 0.8], "fuzz": 0.1 } },
+                    { "type": "quad", "q": [-1, -1, -2], "u": [2, 0, 0], "v": [0, 2, 0],
+                      "material": { "type": "lambertian", "albedo": [0.5, 0.5, 0.5] } }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!((scene.width, scene.height), (40, 20));
+        assert_eq!(scene.samples_per_pixel, 3);
+        assert_eq!(scene.max_depth, 50);
+        assert_eq!(scene.world.objects().len(), 2);
+    }
+
+    #[test]
+    fn rejects_unknown_material() {
+        let result = parse_scene(
+            r#"{
+                "camera": { "look_from": [0, 0, 1], "look_at": [0, 0, 0] },
+                "objects": [
+                    { "type": "sphere", "center": [0, 0, -1], "radius": 0.5,
+                      "material": { "type": "velvet" } }
+                ]
+            }"#,
+        );
+
+        assert!(matches!(result, Err(SceneError::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_impossible_image_sizes() {
+        for size in [
+            r#""aspect_ratio": 0"#,
+            r#""aspect_ratio": -1.5"#,
+            r#""width": 100000"#,
+            r#""width": 400, "height": 1e12"#,
+            r#""width": 1"#,
+        ] {
+            let result = parse_scene(&format!(
+                r#"{{
+                    {},
+                    "camera": {{ "look_from": [0, 0, 1], "look_at": [0, 0, 0] }}
+                }}"#,
+                size
+            ));
+
+            assert!(matches!(result, Err(SceneError::Invalid(_))), "{}", size);
+        }
+    }
+
+    #[test]
+    fn rejects_scenes_without_samples() {
+        for samples in ["0", "-3", "0.5"] {
+            let result = parse_scene(&format!(
+                r#"{{
+                    "samples_per_pixel": {},
+                    "camera": {{ "look_from": [0, 0, 1], "look_at": [0, 0, 0] }}
+                }}"#,
+                samples
+            ));
+
+            assert!(matches!(result, Err(SceneError::Invalid(_))), "{}", samples);
+        }
+    }
+
+    #[test]
+    fn objects_share_named_materials() {
+        let scene = parse_scene(
+            r#"{
+                "camera": { "look_from": [0, 0, 1], "look_at": [0, 0, 0] },
+                "materials": {
+                    "chalk": { "type": "lambertian", "albedo": [0.9, 0.9, 0.9] }
+                },
+                "objects": [
+                    { "type": "sphere", "center": [0, 0, -1], "radius": 0.5, "material": "chalk" },
+                    { "type": "sphere", "center": [1, 0, -1], "radius": 0.5, "material": "chalk" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(scene.world.objects().len(), 2);
+        match scene.materials.get("chalk") {
+            // The library's copy and one for each sphere
+            Some(Material::Lambertian(chalk)) => assert_eq!(Arc::strong_count(&chalk.albedo), 3),
+            _ => panic!("chalk should be a Lambertian material"),
+        }
+    }
+
+    #[test]
+    fn undefined_material_names_are_reported() {
+        let result = parse_scene(
+            r#"{
+                "camera": { "look_from": [0, 0, 1], "look_at": [0, 0, 0] },
+                "materials": { "chalk": { "type": "lambertian", "albedo": [1, 1, 1] } },
+                "objects": [
+                    { "type": "sphere", "center": [0, 0, -1], "radius": 0.5, "material": "cheese" }
+                ]
+            }"#,
+        );
+
+        match result {
+            Err(error) => assert_eq!(
+                error.to_string(),
+                "invalid scene: undefined material 'cheese'"
+            ),
+            Ok(_) => panic!("the scene refers to a material it never defines"),
+        }
+    }
+}
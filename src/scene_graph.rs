@@ -0,0 +1,127 @@
+use crate::hit::Hittable;
+use crate::matrix::Matrix4;
+use crate::transform::Transform;
+use crate::world::World;
+
+// A node of a scene hierarchy. `transform` places the node relative to its parent,
+// so moving a node moves everything below it, e.g. a car body carrying its wheels.
+// Rendering works on the flattened world, where every object sits under a single
+// `Transform` composed all the way down from the root.
+pub struct Node {
+    pub transform: Matrix4,
+    pub object: Option<Box<dyn Hittable>>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(transform: Matrix4) -> Self {
+        Self {
+            transform,
+            object: None,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_object<H: Hittable + 'static>(mut self, object: H) -> Self {
+        self.object = Some(Box::new(object));
+        self
+    }
+
+    pub fn with_child(mut self, child: Node) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    // Panics if a composed transform is singular, like `Transform::new`
+    pub fn flatten(self) -> World {
+        let mut world = World::new();
+        self.flatten_into(Matrix4::identity(), &mut world);
+        world
+    }
+
+    fn flatten_into(self, parent: Matrix4, world: &mut World) {
+        // The parent's transform applies after the node's own
+        let transform = parent * self.transform;
+
+        if let Some(object) = self.object {
+            world.add(Transform::new(object, transform));
+        }
+        for child in self.children {
+            child.flatten_into(transform, world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::vector::{Scalar, Vector, VectorType};
+
+    fn unit_sphere() -> Sphere {
+        Sphere::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            1.0,
+            Material::gray(),
+        )
+    }
+
+    fn first_hit(world: &World, ray: &Ray) -> Option<Vector> {
+        world.hit(ray, 0.001, Scalar::INFINITY).map(|hit| hit.point)
+    }
+
+    #[test]
+    fn child_transform_composes_with_its_parent() {
+        // Turning then moving is not moving then turning, so the order shows
+        let parent = Matrix4::rotation_y(90.0);
+        let local = Matrix4::translation(3.0, 0.0, 0.0);
+
+        let world = Node::new(parent)
+            .with_child(Node::new(local).with_object(unit_sphere()))
+            .flatten();
+        let mut expected = World::new();
+        expected.add(Transform::new(unit_sphere(), parent * local));
+        let mut reversed = World::new();
+        reversed.add(Transform::new(unit_sphere(), local * parent));
+
+        assert_eq!(world.objects().len(), 1);
+
+        let rays = [
+            Ray::new(
+                Vector::new(0.0, 0.0, 10.0, VectorType::Point),
+                Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+            ),
+            Ray::new(
+                Vector::new(0.0, 10.0, -3.0, VectorType::Point),
+                Vector::new(0.0, -1.0, 0.0, VectorType::Vector),
+            ),
+            Ray::new(
+                Vector::new(10.0, 0.0, 0.0, VectorType::Point),
+                Vector::new(-1.0, 0.0, 0.0, VectorType::Vector),
+            ),
+        ];
+        for ray in &rays {
+            assert_eq!(first_hit(&world, ray), first_hit(&expected, ray));
+        }
+        assert!(rays
+            .iter()
+            .any(|ray| first_hit(&world, ray) != first_hit(&reversed, ray)));
+    }
+
+    #[test]
+    fn every_object_in_the_tree_is_kept() {
+        let world = Node::new(Matrix4::translation(0.0, 1.0, 0.0))
+            .with_object(unit_sphere())
+            .with_child(Node::new(Matrix4::scaling(2.0, 2.0, 2.0)))
+            .with_child(
+                Node::new(Matrix4::identity())
+                    .with_object(unit_sphere())
+                    .with_child(Node::new(Matrix4::identity()).with_object(unit_sphere())),
+            )
+            .flatten();
+
+        assert_eq!(world.objects().len(), 3);
+    }
+}
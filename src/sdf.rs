@@ -0,0 +1,176 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::validate::{self, Problem};
+use crate::vector::{Scalar, Vector, VectorType};
+
+// Closer to the surface than this counts as on it
+const EPSILON: Scalar = 1e-5;
+// Rays that have not found the surface after this many steps miss it, which is
+// where grazing rays crawling along the surface give up
+const MAX_STEPS: usize = 256;
+// Offset of the samples the normal is estimated from
+const NORMAL_DELTA: Scalar = 1e-4;
+
+pub type DistanceFn = Box<dyn Fn(&Vector) -> Scalar + Send + Sync>;
+
+// A shape given by a signed distance function: negative inside, positive outside, and
+// never more than the true distance to the surface. Found by sphere tracing, stepping
+// along the ray by the distance to the surface until it is close enough, so any shape
+// with such a function works, e.g. smooth unions or fractals. SDFs have no general
+// bounds, so the box the shape lies in has to be given; rays that miss it are skipped.
+pub struct Sdf {
+    distance: DistanceFn,
+    bounds: Aabb,
+    material: Material,
+}
+
+impl Sdf {
+    pub fn new<F>(distance: F, bounds: Aabb, material: Material) -> Self
+    where
+        F: Fn(&Vector) -> Scalar + Send + Sync + 'static,
+    {
+        Self {
+            distance: Box::new(distance),
+            bounds,
+            material,
+        }
+    }
+
+    pub fn distance(&self, p: &Vector) -> Scalar {
+        (self.distance)(p)
+    }
+
+    // Where `r` first comes within `EPSILON` of the surface. Rays starting inside the
+    // shape march out to it the same way, by the size of the distance.
+    fn march(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<Scalar> {
+        let speed = r.direction.len();
+        if !speed.is_normal() {
+            return None;
+        }
+
+        let mut t = t_min;
+        for _ in 0..MAX_STEPS {
+            let distance = self.distance(&r.at(t)).abs();
+            if distance < EPSILON {
+                return Some(t);
+            }
+
+            t += distance / speed;
+            if t > t_max {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    // Points out of the shape, estimated by central differences
+    fn outward_normal(&self, p: &Vector) -> Vector {
+        let axis = |x: Scalar, y: Scalar, z: Scalar| {
+            let offset = Vector::new(x, y, z, VectorType::Vector);
+            self.distance(&(*p + offset)) - self.distance(&(*p - offset))
+        };
+
+        Vector::new(
+            axis(NORMAL_DELTA, 0.0, 0.0),
+            axis(0.0, NORMAL_DELTA, 0.0),
+            axis(0.0, 0.0, NORMAL_DELTA),
+            VectorType::Vector,
+        )
+        .get_unit_vector()
+    }
+}
+
+impl Hittable for Sdf {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        if !self.bounds.hit(r, t_min, t_max) {
+            return None;
+        }
+
+        let t = self.march(r, t_min, t_max)?;
+        let point = r.at(t);
+        let outward = self.outward_normal(&point);
+        let front_face = r.direction.dot(&outward) < 0.0;
+        let normal = if front_face { outward } else { -outward };
+
+        Some(HitRecord {
+            point,
+            normal,
+            t,
+            u: 0.0,
+            v: 0.0,
+            uv_footprint: 0.0,
+            front_face,
+            material: &self.material,
+            tangent: None,
+            bitangent: None,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        let mut problems = self.material.problems();
+
+        if !validate::is_finite(&self.bounds.min) || !validate::is_finite(&self.bounds.max) {
+            problems.push(Problem::NonFinite);
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn unit_sphere_sdf_matches_sphere() {
+        let center = Vector::new(0.5, -0.25, -3.0, VectorType::Point);
+        let extent = Vector::new(1.0, 1.0, 1.0, VectorType::Vector);
+        let sphere = Sphere::new(center, 1.0, Material::gray());
+        let sdf = Sdf::new(
+            move |p| (*p - center).len() - 1.0,
+            Aabb::new(center - extent, center + extent),
+            Material::gray(),
+        );
+
+        let outside = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+        let rays = [
+            Ray::new(outside, Vector::new(0.0, 0.0, -1.0, VectorType::Vector)),
+            Ray::new(outside, Vector::new(0.3, -0.2, -1.0, VectorType::Vector)),
+            Ray::new(outside, Vector::new(0.4, 0.1, -2.0, VectorType::Vector)),
+            Ray::new(outside, Vector::new(2.0, 0.0, -1.0, VectorType::Vector)),
+            // From inside, as refracted rays are
+            Ray::new(center, Vector::new(0.2, 1.0, 0.3, VectorType::Vector)),
+        ];
+
+        for ray in &rays {
+            match (
+                sphere.hit(ray, 0.001, Scalar::INFINITY),
+                sdf.hit(ray, 0.001, Scalar::INFINITY),
+            ) {
+                (Some(expected), Some(actual)) => {
+                    assert!((expected.t - actual.t).abs() < 1e-3);
+                    assert!((expected.normal - actual.normal).len() < 1e-2);
+                    assert_eq!(expected.front_face, actual.front_face);
+                }
+                (None, None) => {}
+                (expected, actual) => panic!(
+                    "sphere hit {:?}, SDF hit {:?}",
+                    expected.map(|hit| hit.t),
+                    actual.map(|hit| hit.t)
+                ),
+            }
+        }
+
+        // Nothing past t_max
+        assert!(sdf.hit(&rays[0], 0.001, 1.5).is_none());
+    }
+}
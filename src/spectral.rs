@@ -0,0 +1,314 @@
+use crate::material::{Material, Scatterable};
+use crate::ray::Ray;
+use crate::utils::{sky_color, Caustics, DepthExhaustion};
+use crate::vector::{Scalar, Vector, VectorType};
+use crate::world::World;
+use rand::{Rng, RngCore};
+use std::sync::OnceLock;
+
+pub const WAVELENGTH_SAMPLES: usize = 4;
+const LAMBDA_MIN: Scalar = 380.0;
+const LAMBDA_MAX: Scalar = 720.0;
+
+// Hero wavelength sampling: one uniformly chosen wavelength plus the others evenly
+// rotated through the visible range, so every sample covers the whole spectrum
+#[derive(Debug, Clone, Copy)]
+pub struct SampledWavelengths {
+    pub lambda: [Scalar; WAVELENGTH_SAMPLES],
+}
+
+impl SampledWavelengths {
+    pub fn sample(u: Scalar) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = u * range;
+        let mut lambda = [0.0; WAVELENGTH_SAMPLES];
+
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = i as Scalar * range / WAVELENGTH_SAMPLES as Scalar;
+            *l = LAMBDA_MIN + (hero + offset) % range;
+        }
+
+        Self { lambda }
+    }
+
+    // All wavelengths are drawn uniformly
+    pub fn pdf(&self) -> Scalar {
+        1.0 / (LAMBDA_MAX - LAMBDA_MIN)
+    }
+}
+
+// Upsamples a linear RGB reflectance to a smooth spectrum using red, green and blue
+// basis functions that sum to one, so neutral colors become flat spectra
+pub fn rgb_to_spectrum(rgb: &Vector, lambda: Scalar) -> Scalar {
+    let blue = 1.0 - smoothstep(480.0, 510.0, lambda);
+    let red = smoothstep(570.0, 600.0, lambda);
+    let green = 1.0 - blue - red;
+
+    rgb.data.0 * red + rgb.data.1 * green + rgb.data.2 * blue
+}
+
+// Converts radiance sampled at the given wavelengths to linear sRGB, white balanced so
+// that a flat spectrum maps to a neutral color
+pub fn spectrum_to_rgb(
+    values: &[Scalar; WAVELENGTH_SAMPLES],
+    wavelengths: &SampledWavelengths,
+) -> Vector {
+    let mut xyz = (0.0, 0.0, 0.0);
+
+    for (value, lambda) in values.iter().zip(wavelengths.lambda.iter()) {
+        let (x, y, z) = cie_xyz(*lambda);
+        let weight = value / (wavelengths.pdf() * WAVELENGTH_SAMPLES as Scalar);
+        xyz = (xyz.0 + x * weight, xyz.1 + y * weight, xyz.2 + z * weight);
+    }
+
+    let rgb = xyz_to_linear_srgb(xyz);
+    let white = white_balance();
+
+    Vector::new(
+        rgb.0 / white.0,
+        rgb.1 / white.1,
+        rgb.2 / white.2,
+        VectorType::Color,
+    )
+}
+
+// Spectral drop-in for `utils::ray_color`: traces the ray for a set of wavelengths and
+// returns the result in RGB
+pub fn ray_color(
+    r: &Ray,
+    world: &World,
+    depth: u64,
+    sky_bottom: Vector,
+    sky_top: Vector,
+) -> Vector {
+    ray_color_sampled(
+        r,
+        world,
+        depth,
+        sky_bottom,
+        sky_top,
+        DepthExhaustion::Black,
+        None,
+        &mut rand::thread_rng(),
+    )
+}
+
+// `ray_color` drawing the wavelengths and every scattering decision from `rng`, with
+// the caustic light from `caustics` if set
+#[allow(clippy::too_many_arguments)]
+pub fn ray_color_sampled(
+    r: &Ray,
+    world: &World,
+    depth: u64,
+    sky_bottom: Vector,
+    sky_top: Vector,
+    exhaustion: DepthExhaustion,
+    caustics: Option<Caustics>,
+    rng: &mut dyn RngCore,
+) -> Vector {
+    let wavelengths = SampledWavelengths::sample(rng.gen());
+    let radiance = spectral_radiance(
+        r,
+        world,
+        depth,
+        &wavelengths,
+        sky_bottom,
+        sky_top,
+        exhaustion,
+        caustics,
+        rng,
+    );
+
+    spectrum_to_rgb(&radiance, &wavelengths)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn spectral_radiance(
+    r: &Ray,
+    world: &World,
+    depth: u64,
+    wavelengths: &SampledWavelengths,
+    sky_bottom: Vector,
+    sky_top: Vector,
+    exhaustion: DepthExhaustion,
+    caustics: Option<Caustics>,
+    rng: &mut dyn RngCore,
+) -> [Scalar; WAVELENGTH_SAMPLES] {
+    let (sky_bottom, sky_top) = world.sky_or(sky_bottom, sky_top);
+
+    if depth == 0 {
+        return match exhaustion {
+            DepthExhaustion::Black => [0.0; WAVELENGTH_SAMPLES],
+            DepthExhaustion::Background => upsample(
+                &sky_color(&r.direction, sky_bottom, sky_top, world.sky_mapping),
+                wavelengths,
+            ),
+        };
+    }
+
+    let color = match world.hit(r, world.t_min(r), Scalar::INFINITY) {
+        Some(hit_record) => {
+            // Holes in cutouts do not count as a bounce
+            if hit_record.material.passes_through(&hit_record) {
+                return spectral_radiance(
+                    &r.continued_from(hit_record.point),
+                    world,
+                    depth,
+                    wavelengths,
+                    sky_bottom,
+                    sky_top,
+                    exhaustion,
+                    caustics,
+                    rng,
+                );
+            }
+
+            if caustics.is_some_and(|caustics| caustics.stops_at(&hit_record)) {
+                return upsample(&hit_record.material.emitted(&hit_record), wavelengths);
+            }
+
+            if let Material::Phong(phong) = hit_record.material {
+                return upsample(&phong.shade(r, &hit_record, world), wavelengths);
+            }
+
+            let emitted = hit_record.material.emitted(&hit_record)
+                + caustics.map_or(Vector::new(0.0, 0.0, 0.0, VectorType::Color), |caustics| {
+                    caustics.light_at(&hit_record)
+                });
+
+            match hit_record.material.scatter(r, &hit_record, rng) {
+                Some((Some(scattered), albedo)) => {
+                    let incoming = spectral_radiance(
+                        &scattered,
+                        world,
+                        depth - 1,
+                        wavelengths,
+                        sky_bottom,
+                        sky_top,
+                        exhaustion,
+                        caustics.map(|caustics| caustics.after(&hit_record)),
+                        rng,
+                    );
+                    let emitted = upsample(&emitted, wavelengths);
+                    let mut reflected = upsample(&albedo, wavelengths);
+                    for ((value, incoming), emitted) in
+                        reflected.iter_mut().zip(incoming).zip(emitted)
+                    {
+                        *value = emitted + *value * incoming;
+                    }
+                    return reflected;
+                }
+                Some((None, albedo)) => albedo + emitted,
+                None => return upsample(&emitted, wavelengths),
+            }
+        }
+        None => sky_color(&r.direction, sky_bottom, sky_top, world.sky_mapping),
+    };
+
+    upsample(&color, wavelengths)
+}
+
+fn upsample(rgb: &Vector, wavelengths: &SampledWavelengths) -> [Scalar; WAVELENGTH_SAMPLES] {
+    wavelengths
+        .lambda
+        .map(|lambda| rgb_to_spectrum(rgb, lambda))
+}
+
+fn smoothstep(edge0: Scalar, edge1: Scalar, x: Scalar) -> Scalar {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Piecewise gaussian fit of the CIE 1931 color matching functions (Wyman, Sloan and
+// Shirley 2013)
+fn cie_xyz(lambda: Scalar) -> (Scalar, Scalar, Scalar) {
+    let g = |mu: Scalar, sigma_low: Scalar, sigma_high: Scalar| {
+        let sigma = if lambda < mu { sigma_low } else { sigma_high };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+
+    (
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+fn xyz_to_linear_srgb(xyz: (Scalar, Scalar, Scalar)) -> (Scalar, Scalar, Scalar) {
+    let (x, y, z) = xyz;
+
+    (
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+// RGB of a flat, unit spectrum, integrated once at 1nm steps
+fn white_balance() -> (Scalar, Scalar, Scalar) {
+    static WHITE: OnceLock<(Scalar, Scalar, Scalar)> = OnceLock::new();
+
+    *WHITE.get_or_init(|| {
+        let mut xyz = (0.0, 0.0, 0.0);
+        let mut lambda = LAMBDA_MIN;
+
+        while lambda < LAMBDA_MAX {
+            let (x, y, z) = cie_xyz(lambda + 0.5);
+            xyz = (xyz.0 + x, xyz.1 + y, xyz.2 + z);
+            lambda += 1.0;
+        }
+
+        xyz_to_linear_srgb(xyz)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::fuzzy_equal;
+
+    #[test]
+    fn hero_wavelengths_span_the_spectrum() {
+        let wavelengths = SampledWavelengths::sample(0.9);
+
+        for lambda in wavelengths.lambda {
+            assert!((LAMBDA_MIN..LAMBDA_MAX).contains(&lambda));
+        }
+        assert!(fuzzy_equal(wavelengths.lambda[0], 686.0));
+        assert!(fuzzy_equal(wavelengths.lambda[1], 431.0));
+    }
+
+    #[test]
+    fn neutral_gray_round_trips() {
+        let gray = Vector::new(0.5, 0.5, 0.5, VectorType::Color);
+        let mut sum = Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+        let samples = 4096;
+
+        for i in 0..samples {
+            let wavelengths = SampledWavelengths::sample((i as Scalar + 0.5) / samples as Scalar);
+            let spectrum = upsample(&gray, &wavelengths);
+            sum = sum + spectrum_to_rgb(&spectrum, &wavelengths);
+        }
+
+        let average = sum / samples as Scalar;
+        assert!((average.data.0 - 0.5).abs() < 0.01);
+        assert!((average.data.1 - 0.5).abs() < 0.01);
+        assert!((average.data.2 - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn saturated_red_stays_red() {
+        let red = Vector::new(1.0, 0.0, 0.0, VectorType::Color);
+        let mut sum = Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+        let samples = 1024;
+
+        for i in 0..samples {
+            let wavelengths = SampledWavelengths::sample((i as Scalar + 0.5) / samples as Scalar);
+            sum = sum + spectrum_to_rgb(&upsample(&red, &wavelengths), &wavelengths);
+        }
+
+        let average = sum / samples as Scalar;
+        assert!(average.data.0 > average.data.1);
+        assert!(average.data.0 > average.data.2);
+    }
+}
@@ -1,28 +1,138 @@
+use crate::aabb::Aabb;
 use crate::hit::{HitRecord, Hittable};
 use crate::material::Material;
+use crate::onb::Onb;
 use crate::ray::Ray;
-use crate::vector::Vector;
+use crate::validate::{self, Problem};
+use crate::vector::{consts::PI, Scalar, Vector, VectorType};
+use rand::{Rng, RngCore};
 
 pub struct Sphere {
     center: Vector,
-    radius: f64,
+    radius: Scalar,
     material: Material,
 }
 
 impl Sphere {
-    pub fn new(center: Vector, radius: f64, material: Material) -> Self {
+    pub fn new(center: Vector, radius: Scalar, material: Material) -> Self {
         Self {
             center,
             radius,
             material,
         }
     }
+
+    pub fn center(&self) -> Vector {
+        self.center
+    }
+
+    pub fn radius(&self) -> Scalar {
+        self.radius
+    }
+
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    // A unit direction from `origin` towards the sphere, uniform over the cone of
+    // directions the sphere subtends, for sampling spherical lights. From inside the
+    // sphere every direction hits it, so those are uniform over all directions.
+    pub fn random(&self, origin: &Vector) -> Vector {
+        Hittable::random_with(self, &mut rand::thread_rng(), origin)
+    }
+
+    // `hit` for four rays at once. The intersection tests run over one array per
+    // component, the layout SIMD works on, with the same operations for every lane so
+    // the compiler can vectorize them; only the hits are then filled in one by one.
+    // Gives exactly what four calls to `hit` would.
+    pub fn hit4(
+        &self,
+        rays: &[Ray; 4],
+        t_min: Scalar,
+        t_max: Scalar,
+    ) -> [Option<HitRecord<'_>>; 4] {
+        let (cx, cy, cz) = self.center.data;
+        let ox = rays.each_ref().map(|r| r.origin.data.0 - cx);
+        let oy = rays.each_ref().map(|r| r.origin.data.1 - cy);
+        let oz = rays.each_ref().map(|r| r.origin.data.2 - cz);
+        let dx = rays.each_ref().map(|r| r.direction.data.0);
+        let dy = rays.each_ref().map(|r| r.direction.data.1);
+        let dz = rays.each_ref().map(|r| r.direction.data.2);
+
+        let mut a = [0.0; 4];
+        let mut half_b = [0.0; 4];
+        let mut discriminant = [0.0; 4];
+        for i in 0..4 {
+            a[i] = dx[i] * dx[i] + dy[i] * dy[i] + dz[i] * dz[i];
+            half_b[i] = ox[i] * dx[i] + oy[i] * dy[i] + oz[i] * dz[i];
+            let c = ox[i] * ox[i] + oy[i] * oy[i] + oz[i] * oz[i] - self.radius * self.radius;
+            discriminant[i] = half_b[i] * half_b[i] - a[i] * c;
+        }
+
+        std::array::from_fn(|i| {
+            if !a[i].is_normal() || self.radius == 0.0 || discriminant[i] < 0.0 {
+                return None;
+            }
+
+            let root = nearest_root(a[i], half_b[i], discriminant[i], t_min, t_max)?;
+            Some(self.record(&rays[i], root))
+        })
+    }
+
+    // The hit of `r` with the sphere at `root`
+    fn record(&self, r: &Ray, root: Scalar) -> HitRecord<'_> {
+        let point = r.at(root);
+        let normal = (point - self.center) / self.radius;
+        let front_face = r.direction.dot(&normal) < 0.0;
+        let (u, v) = get_sphere_uv(&normal);
+        let normal = if front_face { normal } else { -normal };
+
+        // dp/du points around the Y axis and is well defined even at the poles
+        let phi = 2.0 * crate::vector::consts::PI * u;
+        let tangent = Vector::new(phi.sin(), 0.0, phi.cos(), VectorType::Vector);
+
+        // u wraps a circle of circumference 2πr·sinθ, v a half circle of length πr
+        let footprint = r.footprint_at(root, &normal);
+        let sin_theta = (crate::vector::consts::PI * v).sin().max(1e-3);
+        let uv_footprint = (footprint
+            / (2.0 * crate::vector::consts::PI * self.radius * sin_theta))
+            .max(footprint / (crate::vector::consts::PI * self.radius));
+
+        HitRecord {
+            t: root,
+            u,
+            v,
+            uv_footprint,
+            point,
+            normal,
+            material: &self.material,
+            front_face,
+            tangent: Some(tangent),
+            bitangent: Some(normal.cross(&tangent)),
+        }
+    }
+
+    // Cosine of the half angle of the cone the sphere subtends from a point this far
+    // from its center; -1 from inside, where the "cone" is the whole sphere
+    fn cos_theta_max(&self, distance_squared: Scalar) -> Scalar {
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            -1.0
+        } else {
+            (1.0 - radius_squared / distance_squared).sqrt()
+        }
+    }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
         let oc = r.origin - self.center;
         let a = r.direction.length_squared();
+        // Zero, NaN or infinite directions have no meaningful intersection, and a
+        // sphere of radius 0 is a point with no surface to hit
+        if !a.is_normal() || self.radius == 0.0 {
+            return None;
+        }
         let half_b = oc.dot(&r.direction);
         let c = oc.length_squared() - self.radius * self.radius;
 
@@ -32,47 +142,298 @@ impl Hittable for Sphere {
             return None;
         }
 
-        let discriminant_sqrt = discriminant.sqrt();
+        let root = nearest_root(a, half_b, discriminant, t_min, t_max)?;
+        Some(self.record(r, root))
+    }
 
-        // Find the nearest root that lies in the acceptable range
+    fn random_with(&self, rng: &mut dyn RngCore, origin: &Vector) -> Vector {
+        let to_center = self.center - *origin;
+        let cos_theta_max = self.cos_theta_max(to_center.length_squared());
+        let (r1, r2): (Scalar, Scalar) = (rng.gen(), rng.gen());
 
-        let mut root = (-half_b - discriminant_sqrt) / a;
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
 
-        if root < t_min || t_max < root {
-            root = (-half_b + discriminant_sqrt) / a;
+        // Orthonormal frame with w pointing at the center
+        let w = to_center.get_unit_vector();
+        let w = if w.near_zero() {
+            Vector::new(0.0, 0.0, 1.0, VectorType::Vector)
+        } else {
+            w
+        };
 
-            if root < t_min || t_max < root {
-                return None;
-            }
+        Onb::from_w(&w).local(sin_theta * phi.cos(), sin_theta * phi.sin(), z)
+    }
+
+    fn pdf_value(&self, origin: &Vector, direction: &Vector) -> Scalar {
+        if self
+            .hit(&Ray::new(*origin, *direction), 0.001, Scalar::INFINITY)
+            .is_none()
+        {
+            return 0.0;
         }
 
-        let point = r.at(root);
-        let normal = (point - self.center) / self.radius;
-        let front_face = r.direction.dot(&normal) < 0.0;
+        let distance_squared = (self.center - *origin).length_squared();
+        let solid_angle = 2.0 * PI * (1.0 - self.cos_theta_max(distance_squared));
+        1.0 / solid_angle
+    }
 
-        return Some(HitRecord {
-            t: root,
-            point,
-            normal: if front_face { normal } else { -normal },
-            material: &self.material,
-            front_face,
-        });
+    fn sample_surface(&self, rng: &mut dyn RngCore) -> Option<(HitRecord<'_>, Scalar)> {
+        if self.radius == 0.0 {
+            return None;
+        }
+
+        let outward = Vector::random_in_unit_sphere_with(rng).get_unit_vector();
+        let r = Ray::new(self.center + 2.0 * self.radius * outward, -outward);
+        let area = 4.0 * PI * self.radius * self.radius;
+
+        Some((self.record(&r, self.radius), 1.0 / area))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = Vector::new(self.radius, self.radius, self.radius, VectorType::Vector);
+        Some(Aabb::new(self.center - extent, self.center + extent))
     }
+
+    fn problems(&self) -> Vec<Problem> {
+        let mut problems = self.material.problems();
+
+        if !validate::is_finite(&self.center) || !self.radius.is_finite() {
+            problems.push(Problem::NonFinite);
+        } else if self.radius == 0.0 {
+            problems.push(Problem::ZeroRadius);
+        } else if self.radius < 0.0 && !matches!(self.material, Material::Dielectric(_)) {
+            problems.push(Problem::NegativeRadius(self.radius));
+        }
+
+        problems
+    }
+}
+
+// The nearer root in [t_min, t_max] of the ray-sphere quadratic with the given terms,
+// if either is
+fn nearest_root(
+    a: Scalar,
+    half_b: Scalar,
+    discriminant: Scalar,
+    t_min: Scalar,
+    t_max: Scalar,
+) -> Option<Scalar> {
+    let discriminant_sqrt = discriminant.sqrt();
+
+    // Find the nearest root that lies in the acceptable range
+
+    let mut root = (-half_b - discriminant_sqrt) / a;
+
+    // Written so that a NaN root, e.g. from a NaN origin, is never in range
+    let in_range = |t: Scalar| t_min <= t && t <= t_max;
+    if !in_range(root) {
+        root = (-half_b + discriminant_sqrt) / a;
+
+        if !in_range(root) {
+            return None;
+        }
+    }
+
+    Some(root)
 }
 
-pub fn hit_world<'material>(
-    world: &'material Vec<Sphere>,
-    r: &Ray,
-    t_min: f64,
-    t_max: f64,
-) -> Option<HitRecord<'material>> {
-    let mut closest_so_far = t_max;
-    let mut hit_record = None;
-    for sphere in world {
-        if let Some(hit) = sphere.hit(r, t_min, closest_so_far) {
-            closest_so_far = hit.t;
-            hit_record = Some(hit);
+// Maps a point on the unit sphere to texture coordinates: u wraps around the Y axis
+// starting from -X, v runs from the bottom pole (0) to the top pole (1)
+fn get_sphere_uv(p: &Vector) -> (Scalar, Scalar) {
+    let theta = (-p.data.1).acos();
+    let phi = (-p.data.2).atan2(p.data.0) + crate::vector::consts::PI;
+
+    (
+        phi / (2.0 * crate::vector::consts::PI),
+        theta / crate::vector::consts::PI,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+    use crate::vector::fuzzy_equal;
+    use rand::SeedableRng;
+
+    #[test]
+    fn degenerate_rays_miss() {
+        let sphere = Sphere::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            1.0,
+            Material::gray(),
+        );
+        let origin = Vector::new(0.0, 0.0, 3.0, VectorType::Point);
+        let zero = Ray::new(origin, Vector::new(0.0, 0.0, 0.0, VectorType::Vector));
+        let nan_direction = Ray::new(
+            origin,
+            Vector::new(Scalar::NAN, 0.0, -1.0, VectorType::Vector),
+        );
+        let nan_origin = Ray::new(
+            Vector::new(0.0, Scalar::NAN, 3.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+        );
+
+        for ray in [&zero, &nan_direction, &nan_origin] {
+            assert!(sphere.hit(ray, 0.001, Scalar::INFINITY).is_none());
+        }
+
+        // And the pixel sees the sky instead of turning NaN
+        let world = crate::world::World::from(vec![sphere]);
+        let color = crate::utils::ray_color(
+            &zero,
+            &world,
+            8,
+            crate::utils::SKY_BOTTOM,
+            crate::utils::SKY_TOP,
+        );
+        assert!(color.data.0.is_finite() && color.data.1.is_finite() && color.data.2.is_finite());
+    }
+
+    #[test]
+    fn zero_radius_spheres_are_never_hit() {
+        let point = Sphere::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            0.0,
+            Material::gray(),
+        );
+        let through_center = || {
+            Ray::new(
+                Vector::new(0.0, 0.0, 3.0, VectorType::Point),
+                Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+            )
+        };
+
+        assert!(point
+            .hit(&through_center(), 0.001, Scalar::INFINITY)
+            .is_none());
+        let rays = std::array::from_fn(|_| through_center());
+        assert!(point
+            .hit4(&rays, 0.001, Scalar::INFINITY)
+            .iter()
+            .all(Option::is_none));
+        assert!(point
+            .sample_surface(&mut rand::rngs::StdRng::seed_from_u64(1))
+            .is_none());
+    }
+
+    #[test]
+    fn hit4_matches_four_single_hits() {
+        let sphere = light(Vector::new(0.5, -0.2, -3.0, VectorType::Point), 1.2);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(6);
+
+        for _ in 0..200 {
+            // Rays from outside and inside the sphere, with one degenerate lane
+            let rays: [Ray; 4] = std::array::from_fn(|lane| {
+                let origin = if lane == 1 {
+                    sphere.center()
+                } else {
+                    Vector::random_with(&mut rng, -4.0, 4.0)
+                };
+                let direction = if lane == 3 {
+                    Vector::new(0.0, 0.0, 0.0, VectorType::Vector)
+                } else {
+                    Vector::random_with(&mut rng, -1.0, 1.0)
+                };
+                Ray::new(origin, direction)
+            });
+
+            let batched = sphere.hit4(&rays, 0.001, 10.0);
+            for (ray, hit) in rays.iter().zip(batched) {
+                let single = sphere.hit(ray, 0.001, 10.0);
+                match (single, hit) {
+                    (Some(expected), Some(actual)) => {
+                        assert_eq!(actual.t, expected.t);
+                        assert_eq!(actual.point.data, expected.point.data);
+                        assert_eq!(actual.normal.data, expected.normal.data);
+                        assert_eq!((actual.u, actual.v), (expected.u, expected.v));
+                        assert_eq!(actual.front_face, expected.front_face);
+                    }
+                    (None, None) => {}
+                    _ => panic!("batched and single hits disagree for {:?}", ray),
+                }
+            }
+        }
+    }
+
+    fn light(center: Vector, radius: Scalar) -> Sphere {
+        Sphere::new(
+            center,
+            radius,
+            Material::DiffuseLight(DiffuseLight::new(Vector::new(
+                1.0,
+                1.0,
+                1.0,
+                VectorType::Color,
+            ))),
+        )
+    }
+
+    #[test]
+    fn cone_samples_hit_the_sphere() {
+        use rand::SeedableRng;
+
+        let sphere = light(Vector::new(1.0, 2.0, -5.0, VectorType::Point), 0.75);
+        let origin = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+
+        for _ in 0..500 {
+            let direction = sphere.random_with(&mut rng, &origin);
+
+            assert!(fuzzy_equal(direction.len(), 1.0));
+            assert!(sphere.pdf_value(&origin, &direction) > 0.0);
+        }
+
+        let away = Vector::new(0.0, 0.0, 1.0, VectorType::Vector);
+        assert_eq!(sphere.pdf_value(&origin, &away), 0.0);
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        use rand::SeedableRng;
+
+        let sphere = light(Vector::new(0.5, -1.0, -3.0, VectorType::Point), 1.5);
+        let origin = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(6);
+
+        // Monte Carlo estimate of the integral over the sphere with uniform directions
+        let n = 40000;
+        let total: Scalar = (0..n)
+            .map(|_| {
+                let direction = Vector::random_in_unit_sphere_with(&mut rng).get_unit_vector();
+                sphere.pdf_value(&origin, &direction) * 4.0 * PI
+            })
+            .sum();
+
+        assert!((total / n as Scalar - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn sphere_tangent_frame_is_orthonormal() {
+        let sphere = Sphere::new(
+            Vector::new(0.0, 0.0, -3.0, VectorType::Point),
+            1.0,
+            Material::gray(),
+        );
+
+        let directions = [
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+            Vector::new(0.1, 0.2, -1.0, VectorType::Vector),
+            Vector::new(-0.25, -0.05, -1.0, VectorType::Vector),
+        ];
+
+        for direction in directions {
+            let ray = Ray::new(Vector::new(0.0, 0.0, 0.0, VectorType::Point), direction);
+            let hit = sphere.hit(&ray, 0.001, Scalar::INFINITY).unwrap();
+            let tangent = hit.tangent.unwrap();
+            let bitangent = hit.bitangent.unwrap();
+
+            assert!(fuzzy_equal(tangent.dot(&hit.normal), 0.0));
+            assert!(fuzzy_equal(bitangent.dot(&hit.normal), 0.0));
+            assert!(fuzzy_equal(tangent.dot(&bitangent), 0.0));
+            assert!(fuzzy_equal(tangent.len(), 1.0));
         }
     }
-    hit_record
 }
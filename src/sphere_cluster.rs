@@ -0,0 +1,170 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::validate::Problem;
+use crate::vector::Scalar;
+use std::collections::BTreeMap;
+
+#[cfg(test)]
+thread_local! {
+    static SPHERE_TESTS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+// A flat group of spheres behind one shared bounding box. Rays that miss the box skip
+// every sphere in it, which is most of the win of a hierarchy for scenes made of
+// many small spheres, without building one.
+pub struct SphereCluster {
+    spheres: Vec<Sphere>,
+    bounds: Option<Aabb>,
+}
+
+impl SphereCluster {
+    pub fn new(spheres: Vec<Sphere>) -> Self {
+        let bounds = spheres
+            .iter()
+            .filter_map(Sphere::bounding_box)
+            .reduce(|a, b| a.surrounding(&b));
+
+        Self { spheres, bounds }
+    }
+
+    // Sorts spheres into clusters by which cube of side `cell_size` their center
+    // falls in, so each cluster's box stays about that size. Spheres much larger
+    // than a cell (like the ground in `random_scene`) still end up alone in theirs.
+    pub fn group(spheres: Vec<Sphere>, cell_size: Scalar) -> Vec<Self> {
+        let mut cells: BTreeMap<(i64, i64, i64), Vec<Sphere>> = BTreeMap::new();
+
+        for sphere in spheres {
+            let (x, y, z) = sphere.center().data;
+            let cell = |v: Scalar| (v / cell_size).floor() as i64;
+            cells
+                .entry((cell(x), cell(y), cell(z)))
+                .or_default()
+                .push(sphere);
+        }
+
+        cells.into_values().map(Self::new).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.spheres.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spheres.is_empty()
+    }
+}
+
+impl Hittable for SphereCluster {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        if !self.bounds?.hit(r, t_min, t_max) {
+            return None;
+        }
+
+        let mut closest_so_far = t_max;
+        let mut closest = None;
+        for sphere in &self.spheres {
+            #[cfg(test)]
+            SPHERE_TESTS.with(|count| count.set(count.get() + 1));
+
+            if let Some(hit) = sphere.hit(r, t_min, closest_so_far) {
+                closest_so_far = hit.t;
+                closest = Some(hit);
+            }
+        }
+
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        self.spheres.iter().flat_map(Sphere::problems).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::random_scene;
+    use crate::vector::{Vector, VectorType};
+    use crate::world::World;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn sphere_tests() -> usize {
+        SPHERE_TESTS.with(|count| count.get())
+    }
+
+    #[test]
+    fn matches_the_spheres_one_by_one() {
+        let clusters = SphereCluster::group(random_scene(3), 4.0);
+        let world = World::from(random_scene(3));
+        let mut rng = StdRng::seed_from_u64(9);
+
+        assert!(clusters.len() > 1);
+        assert_eq!(
+            clusters.iter().map(SphereCluster::len).sum::<usize>(),
+            random_scene(3).len()
+        );
+
+        for _ in 0..500 {
+            let origin = Vector::new(
+                rng.gen_range(-12.0..12.0),
+                rng.gen_range(0.5..4.0),
+                rng.gen_range(-12.0..12.0),
+                VectorType::Point,
+            );
+            let target = Vector::new(
+                rng.gen_range(-11.0..11.0),
+                0.2,
+                rng.gen_range(-11.0..11.0),
+                VectorType::Point,
+            );
+            let ray = Ray::new(origin, target - origin);
+
+            let expected = world.hit(&ray, 0.001, Scalar::INFINITY).map(|hit| hit.t);
+            let mut closest_so_far = Scalar::INFINITY;
+            for cluster in &clusters {
+                if let Some(hit) = cluster.hit(&ray, 0.001, closest_so_far) {
+                    closest_so_far = hit.t;
+                }
+            }
+            let actual = Some(closest_so_far).filter(|t| t.is_finite());
+
+            match (expected, actual) {
+                (Some(e), Some(a)) => assert!((e - a).abs() < 1e-9),
+                (None, None) => {}
+                _ => panic!(
+                    "clusters and naive loop disagree: {:?} {:?}",
+                    expected, actual
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn missing_the_box_tests_no_spheres() {
+        let cluster = SphereCluster::new(random_scene(3).into_iter().skip(1).collect());
+        // Straight up from beside the grid, well clear of every small sphere
+        let ray = Ray::new(
+            Vector::new(30.0, 0.2, 0.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+        );
+
+        let before = sphere_tests();
+        assert!(cluster.hit(&ray, 0.001, Scalar::INFINITY).is_none());
+        assert_eq!(sphere_tests(), before);
+
+        // A ray through the box does look at the spheres
+        let ray = Ray::new(
+            Vector::new(30.0, 0.2, 0.0, VectorType::Point),
+            Vector::new(-1.0, 0.0, 0.0, VectorType::Vector),
+        );
+        cluster.hit(&ray, 0.001, Scalar::INFINITY);
+        assert_eq!(sphere_tests(), before + cluster.len());
+    }
+}
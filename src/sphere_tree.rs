@@ -0,0 +1,258 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::validate::Problem;
+use crate::vector::{Scalar, Vector, VectorType};
+
+#[derive(Debug, Clone, Copy)]
+pub struct BoundingSphere {
+    pub center: Vector,
+    pub radius: Scalar,
+}
+
+impl BoundingSphere {
+    pub fn new(center: Vector, radius: Scalar) -> Self {
+        Self { center, radius }
+    }
+
+    pub fn of(sphere: &Sphere) -> Self {
+        Self::new(sphere.center(), sphere.radius())
+    }
+
+    // Smallest sphere containing both `self` and `other`
+    pub fn enclosing(&self, other: &BoundingSphere) -> Self {
+        let offset = other.center - self.center;
+        let distance = offset.len();
+
+        if distance + other.radius <= self.radius {
+            return *self;
+        }
+        if distance + self.radius <= other.radius {
+            return *other;
+        }
+
+        let radius = 0.5 * (distance + self.radius + other.radius);
+        let center = self.center + ((radius - self.radius) / distance) * offset;
+
+        Self::new(center, radius)
+    }
+
+    // Whether the ray passes through the sphere anywhere in [t_min, t_max]
+    pub fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> bool {
+        let oc = r.origin - self.center;
+        let a = r.direction.length_squared();
+        let half_b = oc.dot(&r.direction);
+        let c = oc.length_squared() - self.radius * self.radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return false;
+        }
+
+        let discriminant_sqrt = discriminant.sqrt();
+        let near = (-half_b - discriminant_sqrt) / a;
+        let far = (-half_b + discriminant_sqrt) / a;
+
+        near <= t_max && far >= t_min
+    }
+}
+
+enum Node {
+    Leaf(Sphere),
+    Branch {
+        bounds: BoundingSphere,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> BoundingSphere {
+        match self {
+            Node::Leaf(sphere) => BoundingSphere::of(sphere),
+            Node::Branch { bounds, .. } => *bounds,
+        }
+    }
+
+    // Splits at the median center along the axis where the centers spread the most
+    fn build(mut spheres: Vec<Sphere>) -> Node {
+        if spheres.len() == 1 {
+            return Node::Leaf(spheres.pop().unwrap());
+        }
+
+        let axis = |v: Vector, axis: usize| match axis {
+            0 => v.data.0,
+            1 => v.data.1,
+            _ => v.data.2,
+        };
+        let extent = |a: usize| {
+            let values = spheres.iter().map(|s| axis(s.center(), a));
+            let min = values.clone().fold(Scalar::INFINITY, Scalar::min);
+            let max = values.fold(Scalar::NEG_INFINITY, Scalar::max);
+            max - min
+        };
+        let split_axis = (0..3)
+            .max_by(|&a, &b| extent(a).total_cmp(&extent(b)))
+            .unwrap();
+
+        spheres
+            .sort_by(|a, b| axis(a.center(), split_axis).total_cmp(&axis(b.center(), split_axis)));
+        let right = spheres.split_off(spheres.len() / 2);
+
+        let left = Box::new(Node::build(spheres));
+        let right = Box::new(Node::build(right));
+
+        Node::Branch {
+            bounds: left.bounds().enclosing(&right.bounds()),
+            left,
+            right,
+        }
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        match self {
+            Node::Leaf(sphere) => sphere.problems(),
+            Node::Branch { left, right, .. } => {
+                let mut problems = left.problems();
+                problems.extend(right.problems());
+                problems
+            }
+        }
+    }
+
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        match self {
+            Node::Leaf(sphere) => sphere.hit(r, t_min, t_max),
+            Node::Branch {
+                bounds,
+                left,
+                right,
+            } => {
+                if !bounds.hit(r, t_min, t_max) {
+                    return None;
+                }
+
+                let left_hit = left.hit(r, t_min, t_max);
+                let closest = left_hit.as_ref().map_or(t_max, |hit| hit.t);
+
+                right.hit(r, t_min, closest).or(left_hit)
+            }
+        }
+    }
+}
+
+// A hierarchy of bounding spheres over sphere primitives. Cheaper to build than an
+// AABB hierarchy and just as tight for purely spherical content.
+pub struct SphereTree {
+    root: Option<Node>,
+}
+
+impl SphereTree {
+    pub fn new(spheres: Vec<Sphere>) -> Self {
+        let root = if spheres.is_empty() {
+            None
+        } else {
+            Some(Node::build(spheres))
+        };
+
+        Self { root }
+    }
+
+    pub fn bounds(&self) -> Option<BoundingSphere> {
+        self.root.as_ref().map(Node::bounds)
+    }
+}
+
+impl Hittable for SphereTree {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        self.root.as_ref()?.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bounds = self.bounds()?;
+        let extent = Vector::new(
+            bounds.radius,
+            bounds.radius,
+            bounds.radius,
+            VectorType::Vector,
+        );
+
+        Some(Aabb::new(bounds.center - extent, bounds.center + extent))
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        self.root.as_ref().map_or_else(Vec::new, Node::problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::world::World;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn cluster(rng: &mut StdRng) -> Vec<Sphere> {
+        (0..60)
+            .map(|_| {
+                Sphere::new(
+                    Vector::new(
+                        rng.gen_range(-3.0..3.0),
+                        rng.gen_range(-3.0..3.0),
+                        rng.gen_range(-3.0..3.0),
+                        VectorType::Point,
+                    ),
+                    rng.gen_range(0.05..0.6),
+                    Material::gray(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn enclosing_sphere_contains_both() {
+        let a = BoundingSphere::new(Vector::new(0.0, 0.0, 0.0, VectorType::Point), 1.0);
+        let b = BoundingSphere::new(Vector::new(4.0, 0.0, 0.0, VectorType::Point), 1.0);
+
+        let both = a.enclosing(&b);
+
+        assert!((both.radius - 3.0).abs() < 1e-9);
+        assert!((both.center.data.0 - 2.0).abs() < 1e-9);
+        // Nested spheres enclose to the outer one
+        assert!((both.enclosing(&a).radius - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn matches_naive_closest_hit() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let tree = SphereTree::new(cluster(&mut rng));
+        let world = World::from(cluster(&mut StdRng::seed_from_u64(7)));
+
+        for _ in 0..500 {
+            let origin = Vector::new(
+                rng.gen_range(-8.0..8.0),
+                rng.gen_range(-8.0..8.0),
+                rng.gen_range(-8.0..8.0),
+                VectorType::Point,
+            );
+            let target = Vector::new(
+                rng.gen_range(-2.0..2.0),
+                rng.gen_range(-2.0..2.0),
+                rng.gen_range(-2.0..2.0),
+                VectorType::Point,
+            );
+            let ray = Ray::new(origin, target - origin);
+
+            let expected = world.hit(&ray, 0.001, Scalar::INFINITY).map(|hit| hit.t);
+            let actual = tree.hit(&ray, 0.001, Scalar::INFINITY).map(|hit| hit.t);
+
+            match (expected, actual) {
+                (Some(e), Some(a)) => assert!((e - a).abs() < 1e-9),
+                (None, None) => {}
+                _ => panic!("tree and naive loop disagree: {:?} {:?}", expected, actual),
+            }
+        }
+    }
+}
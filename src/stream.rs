@@ -0,0 +1,111 @@
+use crate::camera::Camera;
+use crate::postprocess::PostProcess;
+use crate::render::{path_tracer, render_tile, tile_origins, PixelSampler, RenderOptions};
+use crate::world::World;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::Mutex;
+
+// Renders straight into a binary PPM (P6) file at `path` without ever holding the
+// whole frame in memory, for frames too large to keep around as an `Image`. Every
+// pixel has a fixed offset in a P6 file, so each tile is quantized with `post` and
+// written into its own rectangle as soon as it is done; only the tiles in flight are
+// kept. The file matches writing `render_with_options(..).to_rgb8_with(post)` out in
+// one go, whatever `options.tile_size` is (32 pixels when unset).
+#[allow(clippy::too_many_arguments)]
+pub fn render_to_ppm<P: AsRef<Path>>(
+    path: P,
+    height: u32,
+    width: u32,
+    samples_per_pixel: u32,
+    world: &World,
+    camera: Camera,
+    max_depth: u64,
+    options: &RenderOptions,
+    post: &PostProcess,
+) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    let header = format!("P6\n{} {}\n255\n", width, height);
+    file.write_all(header.as_bytes())?;
+    // Reserve the pixel data up front so tiles can land anywhere in it
+    file.set_len(header.len() as u64 + 3 * width as u64 * height as u64)?;
+
+    let shade = path_tracer(world, max_depth, options);
+    let sampler = PixelSampler {
+        world: Some(world),
+        camera: &camera,
+        width,
+        height,
+        samples_per_pixel,
+        options,
+        shade: &shade,
+        discarded: AtomicU64::new(0),
+    };
+    let tile_size = options.tile_size.unwrap_or(32).max(1);
+    let file = Mutex::new(file);
+
+    tile_origins(width, height, tile_size)
+        .into_par_iter()
+        .try_for_each(|(x0, y0)| {
+            let pixels = render_tile(&sampler, x0, y0, tile_size);
+            let tile_width = ((x0 + tile_size).min(width) - x0) as usize;
+
+            let mut file = file.lock().unwrap();
+            for (row, colors) in pixels.chunks(tile_width).enumerate() {
+                // Tile rows count up from the bottom of the frame, the file stores
+                // rows top first
+                let y = height - 1 - (y0 + row as u32);
+                let offset = 3 * (y as u64 * width as u64 + x0 as u64);
+                let bytes: Vec<u8> = colors.iter().flat_map(|&c| post.to_rgb8(c)).collect();
+
+                file.seek(SeekFrom::Start(header.len() as u64 + offset))?;
+                file.write_all(&bytes)?;
+            }
+
+            Ok(())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::render_with_options;
+    use crate::scene::random_scene_with;
+    use crate::vector::{Vector, VectorType};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn streamed_file_matches_the_in_memory_render() {
+        let world = World::from(random_scene_with(&mut StdRng::seed_from_u64(1)));
+        let camera = Camera::new(
+            Vector::new(13.0, 2.0, 3.0, VectorType::Point),
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            20.0,
+            37.0 / 23.0,
+            0.0,
+            10.0,
+        );
+        let options = RenderOptions {
+            tile_size: Some(8),
+            seed: 4,
+            ..RenderOptions::default()
+        };
+        let post = PostProcess::default();
+        let path = crate::utils::temp_path("streamed.ppm");
+
+        render_to_ppm(&path, 23, 37, 2, &world, camera.clone(), 5, &options, &post).unwrap();
+        let streamed = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let image = render_with_options(23, 37, 2, &world, camera, 5, &options);
+        let mut expected = b"P6\n37 23\n255\n".to_vec();
+        expected.extend(image.to_rgb8_with(&post));
+
+        assert_eq!(streamed, expected);
+    }
+}
@@ -0,0 +1,491 @@
+use crate::utils;
+use crate::vector::{Scalar, Vector, VectorType};
+use std::sync::Arc;
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: Scalar, v: Scalar, point: &Vector) -> Vector;
+
+    // Averages the texture over a region `footprint` wide in texture space. Textures
+    // without prefiltered data simply point sample.
+    fn filtered_value(&self, u: Scalar, v: Scalar, point: &Vector, _footprint: Scalar) -> Vector {
+        self.value(u, v, point)
+    }
+
+    // The color everywhere, for textures that do not vary
+    fn constant(&self) -> Option<Vector> {
+        None
+    }
+}
+
+// Textures are shared between materials (and render threads) by reference counting,
+// so a large image is only stored once no matter how many spheres use it.
+pub type SharedTexture = Arc<dyn Texture + Send + Sync>;
+
+pub struct SolidColor {
+    color: Vector,
+}
+
+impl SolidColor {
+    pub fn new(color: Vector) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: Scalar, _v: Scalar, _point: &Vector) -> Vector {
+        self.color
+    }
+
+    fn constant(&self) -> Option<Vector> {
+        Some(self.color)
+    }
+}
+
+// What a `GradientTexture` runs along
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientAxis {
+    U,
+    V,
+    // World space height, from the first y to the second
+    Height(Scalar, Scalar),
+}
+
+// A linear blend from `start` to `end` along `axis`, held at the end colors beyond
+// either end. For stylized grounds or skies painted onto geometry.
+pub struct GradientTexture {
+    start: Vector,
+    end: Vector,
+    axis: GradientAxis,
+}
+
+impl GradientTexture {
+    pub fn new(start: Vector, end: Vector, axis: GradientAxis) -> Self {
+        Self { start, end, axis }
+    }
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, u: Scalar, v: Scalar, point: &Vector) -> Vector {
+        let t = match self.axis {
+            GradientAxis::U => u,
+            GradientAxis::V => v,
+            GradientAxis::Height(bottom, top) if top != bottom => {
+                (point.data.1 - bottom) / (top - bottom)
+            }
+            GradientAxis::Height(bottom, _) => {
+                if point.data.1 < bottom {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+        }
+        .clamp(0.0, 1.0);
+
+        (1.0 - t) * self.start + t * self.end
+    }
+}
+
+// For checking how a primitive lays out its UVs: a checker of `cells` by `cells`
+// squares per unit of u and v with thin dark lines along the cell edges, and red
+// ones where u or v is a whole number. Stretching shows as squashed squares and
+// seams as broken lines. Use it in a `Lambertian` or `Unlit` material.
+pub struct UvGrid {
+    cells: u32,
+}
+
+// Width of the grid lines as a fraction of a cell
+const UV_GRID_LINE_WIDTH: Scalar = 0.05;
+const UV_GRID_LIGHT: Vector = Vector {
+    data: (0.8, 0.8, 0.8),
+    data_type: VectorType::Color,
+};
+const UV_GRID_DARK: Vector = Vector {
+    data: (0.4, 0.4, 0.4),
+    data_type: VectorType::Color,
+};
+const UV_GRID_LINE: Vector = Vector {
+    data: (0.05, 0.05, 0.05),
+    data_type: VectorType::Color,
+};
+const UV_GRID_UNIT_LINE: Vector = Vector {
+    data: (0.9, 0.1, 0.1),
+    data_type: VectorType::Color,
+};
+
+impl UvGrid {
+    pub fn new(cells: u32) -> Self {
+        Self {
+            cells: cells.max(1),
+        }
+    }
+}
+
+impl Texture for UvGrid {
+    fn value(&self, u: Scalar, v: Scalar, _point: &Vector) -> Vector {
+        let cells = self.cells as Scalar;
+        let (x, y) = (u * cells, v * cells);
+        let on_line = |t: Scalar| (t - t.round()).abs() <= 0.5 * UV_GRID_LINE_WIDTH;
+        let on_unit_line = |t: Scalar| on_line(t) && t.round() as i64 % self.cells as i64 == 0;
+
+        if on_unit_line(x) || on_unit_line(y) {
+            UV_GRID_UNIT_LINE
+        } else if on_line(x) || on_line(y) {
+            UV_GRID_LINE
+        } else if (x.floor() as i64 + y.floor() as i64).rem_euclid(2) == 0 {
+            UV_GRID_LIGHT
+        } else {
+            UV_GRID_DARK
+        }
+    }
+}
+
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<Vector>,
+}
+
+impl MipLevel {
+    fn texel(&self, i: isize, j: isize) -> Vector {
+        let i = i.clamp(0, self.width as isize - 1) as usize;
+        let j = j.clamp(0, self.height as isize - 1) as usize;
+        self.texels[j * self.width + i]
+    }
+
+    fn bilinear(&self, u: Scalar, v: Scalar) -> Vector {
+        let x = u * self.width as Scalar - 0.5;
+        let y = v * self.height as Scalar - 0.5;
+        let (i, j) = (x.floor(), y.floor());
+        let (fx, fy) = (x - i, y - j);
+        let (i, j) = (i as isize, j as isize);
+
+        (1.0 - fy) * ((1.0 - fx) * self.texel(i, j) + fx * self.texel(i + 1, j))
+            + fy * ((1.0 - fx) * self.texel(i, j + 1) + fx * self.texel(i + 1, j + 1))
+    }
+
+    // Box filters 2x2 blocks into the next smaller level
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut texels = Vec::with_capacity(width * height);
+
+        for j in 0..height {
+            for i in 0..width {
+                let (x, y) = (2 * i as isize, 2 * j as isize);
+                let sum = self.texel(x, y)
+                    + self.texel(x + 1, y)
+                    + self.texel(x, y + 1)
+                    + self.texel(x + 1, y + 1);
+                texels.push(sum / 4.0);
+            }
+        }
+
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+}
+
+// An 8-bit RGB image with a prefiltered mip pyramid for filtered lookups. Texels are
+// decoded to linear once up front, so the pyramid averages linear values.
+pub struct ImageTexture {
+    levels: Vec<MipLevel>,
+    is_srgb: bool,
+}
+
+impl ImageTexture {
+    // `data` holds tightly packed 8-bit RGB triplets, top row first, sRGB encoded as
+    // color images normally are
+    pub fn new(width: usize, height: usize, data: Vec<u8>) -> Self {
+        Self::with_encoding(width, height, data, true)
+    }
+
+    // Like `new`, for data that is already linear such as normal or roughness maps
+    pub fn linear(width: usize, height: usize, data: Vec<u8>) -> Self {
+        Self::with_encoding(width, height, data, false)
+    }
+
+    // `is_srgb` decides whether texels go through the sRGB to linear transfer function
+    pub fn with_encoding(width: usize, height: usize, data: Vec<u8>, is_srgb: bool) -> Self {
+        assert_eq!(data.len(), width * height * 3);
+
+        if data.is_empty() {
+            return Self {
+                levels: vec![],
+                is_srgb,
+            };
+        }
+
+        let color_scale = 1.0 / 255.0;
+        let texels = data
+            .chunks(3)
+            .map(|texel| {
+                let color = Vector::new(
+                    color_scale * texel[0] as Scalar,
+                    color_scale * texel[1] as Scalar,
+                    color_scale * texel[2] as Scalar,
+                    VectorType::Color,
+                );
+                if is_srgb {
+                    color.from_srgb()
+                } else {
+                    color
+                }
+            })
+            .collect();
+
+        let mut levels = vec![MipLevel {
+            width,
+            height,
+            texels,
+        }];
+
+        while let Some(last) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+            let next = last.downsample();
+            levels.push(next);
+        }
+
+        Self { levels, is_srgb }
+    }
+
+    pub fn is_srgb(&self) -> bool {
+        self.is_srgb
+    }
+
+    pub fn mip_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    // Fractional pyramid level whose texels are about `footprint` wide
+    pub fn mip_level(&self, footprint: Scalar) -> Scalar {
+        let base = match self.levels.first() {
+            Some(base) => base,
+            None => return 0.0,
+        };
+
+        let texels = footprint * base.width.max(base.height) as Scalar;
+        if texels <= 1.0 {
+            return 0.0;
+        }
+
+        texels.log2().min((self.levels.len() - 1) as Scalar)
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: Scalar, v: Scalar, _point: &Vector) -> Vector {
+        let base = match self.levels.first() {
+            Some(base) => base,
+            None => return Vector::new(0.0, 1.0, 1.0, VectorType::Color),
+        };
+
+        let u = utils::clamp(u, 0.0, 1.0);
+        // Flip v to image coordinates
+        let v = 1.0 - utils::clamp(v, 0.0, 1.0);
+
+        let i = ((u * base.width as Scalar) as usize).min(base.width - 1);
+        let j = ((v * base.height as Scalar) as usize).min(base.height - 1);
+
+        base.texels[j * base.width + i]
+    }
+
+    // Trilinear lookup between the two pyramid levels closest to the footprint
+    fn filtered_value(&self, u: Scalar, v: Scalar, point: &Vector, footprint: Scalar) -> Vector {
+        if footprint <= 0.0 || self.levels.is_empty() {
+            return self.value(u, v, point);
+        }
+
+        let u = utils::clamp(u, 0.0, 1.0);
+        let v = 1.0 - utils::clamp(v, 0.0, 1.0);
+
+        let level = self.mip_level(footprint);
+        let lower = level.floor() as usize;
+        let upper = (lower + 1).min(self.levels.len() - 1);
+        let t = level - lower as Scalar;
+
+        (1.0 - t) * self.levels[lower].bilinear(u, v) + t * self.levels[upper].bilinear(u, v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradient_endpoints_are_the_two_colors() {
+        let start = Vector::new(0.9, 0.2, 0.1, VectorType::Color);
+        let end = Vector::new(0.1, 0.3, 0.8, VectorType::Color);
+        let at_height = |y: Scalar| Vector::new(4.0, y, -2.0, VectorType::Point);
+        let origin = at_height(0.0);
+
+        let along_u = GradientTexture::new(start, end, GradientAxis::U);
+        assert_eq!(along_u.value(0.0, 0.7, &origin), start);
+        assert_eq!(along_u.value(1.0, 0.7, &origin), end);
+        assert_eq!(along_u.value(0.5, 0.7, &origin), 0.5 * (start + end));
+
+        let along_v = GradientTexture::new(start, end, GradientAxis::V);
+        assert_eq!(along_v.value(0.3, 0.0, &origin), start);
+        assert_eq!(along_v.value(0.3, 1.0, &origin), end);
+
+        let by_height = GradientTexture::new(start, end, GradientAxis::Height(-1.0, 3.0));
+        assert_eq!(by_height.value(0.5, 0.5, &at_height(-1.0)), start);
+        assert_eq!(by_height.value(0.5, 0.5, &at_height(3.0)), end);
+        // Held at the end colors outside the range
+        assert_eq!(by_height.value(0.5, 0.5, &at_height(-10.0)), start);
+        assert_eq!(by_height.value(0.5, 0.5, &at_height(10.0)), end);
+    }
+
+    #[test]
+    fn uv_grid_draws_lines_on_cell_edges() {
+        let grid = UvGrid::new(10);
+        let point = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+        let at = |u: Scalar, v: Scalar| grid.value(u, v, &point);
+
+        // Cell centers alternate like a checker
+        assert_eq!(at(0.05, 0.05), UV_GRID_LIGHT);
+        assert_eq!(at(0.15, 0.05), UV_GRID_DARK);
+        assert_eq!(at(0.15, 0.15), UV_GRID_LIGHT);
+        assert_eq!(at(0.95, 0.55), UV_GRID_LIGHT);
+        assert_eq!(at(-0.05, 0.05), UV_GRID_DARK);
+
+        // Tenths get the thin lines, whole numbers the red ones
+        assert_eq!(at(0.3, 0.45), UV_GRID_LINE);
+        assert_eq!(at(0.45, 0.7), UV_GRID_LINE);
+        assert_eq!(at(0.0, 0.45), UV_GRID_UNIT_LINE);
+        assert_eq!(at(0.45, 1.0), UV_GRID_UNIT_LINE);
+        assert_eq!(at(0.3, 1.0), UV_GRID_UNIT_LINE);
+    }
+
+    #[test]
+    fn solid_color_ignores_coordinates() {
+        let color = Vector::new(0.2, 0.4, 0.6, VectorType::Color);
+        let texture = SolidColor::new(color);
+        let point = Vector::new(1.0, 2.0, 3.0, VectorType::Point);
+
+        assert_eq!(texture.value(0.0, 0.0, &point), color);
+        assert_eq!(texture.value(0.7, 0.3, &point), color);
+    }
+
+    #[test]
+    fn image_texture_samples_texels() {
+        // 2x1 image: red on the left, blue on the right
+        let texture = ImageTexture::new(2, 1, vec![255, 0, 0, 0, 0, 255]);
+        let point = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+
+        assert_eq!(
+            texture.value(0.25, 0.5, &point),
+            Vector::new(1.0, 0.0, 0.0, VectorType::Color)
+        );
+        assert_eq!(
+            texture.value(0.75, 0.5, &point),
+            Vector::new(0.0, 0.0, 1.0, VectorType::Color)
+        );
+    }
+
+    #[test]
+    fn srgb_texels_are_decoded_to_linear() {
+        let point = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+
+        let srgb = ImageTexture::new(1, 1, vec![187, 187, 187]);
+        let linear = ImageTexture::linear(1, 1, vec![187, 187, 187]);
+
+        assert!(srgb.is_srgb());
+        assert!((srgb.value(0.5, 0.5, &point).data.0 - 0.5).abs() < 0.005);
+        assert!(!linear.is_srgb());
+        assert!((linear.value(0.5, 0.5, &point).data.0 - 187.0 / 255.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn builds_full_mip_pyramid() {
+        let texture = ImageTexture::new(8, 4, vec![128; 8 * 4 * 3]);
+
+        // 8x4, 4x2, 2x1, 1x1
+        assert_eq!(texture.mip_levels(), 4);
+        assert_eq!(texture.mip_level(0.0), 0.0);
+        assert_eq!(texture.mip_level(1.0), 3.0);
+    }
+
+    #[test]
+    fn far_surfaces_sample_coarser_mips() {
+        use crate::camera::Camera;
+        use crate::hit::Hittable;
+        use crate::material::Material;
+        use crate::quad::Quad;
+
+        let texture = ImageTexture::new(64, 64, vec![200; 64 * 64 * 3]);
+        let material = Material::gray();
+        let plane_at = |z: Scalar| {
+            Quad::new(
+                Vector::new(-1.0, -1.0, z, VectorType::Point),
+                Vector::new(2.0, 0.0, 0.0, VectorType::Vector),
+                Vector::new(0.0, 2.0, 0.0, VectorType::Vector),
+                material.clone(),
+            )
+        };
+
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let ray =
+            camera.get_ray_differential(0.5, 0.5, 1.0 / 99.0, 1.0 / 99.0, &mut rand::thread_rng());
+
+        let near = plane_at(-2.0);
+        let far = plane_at(-40.0);
+        let near_hit = near.hit(&ray, 0.001, Scalar::INFINITY).unwrap();
+        let far_hit = far.hit(&ray, 0.001, Scalar::INFINITY).unwrap();
+
+        assert!(far_hit.uv_footprint > near_hit.uv_footprint);
+        assert!(texture.mip_level(far_hit.uv_footprint) > texture.mip_level(near_hit.uv_footprint));
+    }
+
+    #[test]
+    fn rendered_texture_is_filtered_over_the_pixel() {
+        use crate::camera::Camera;
+        use crate::material::{Lambertian, Material};
+        use crate::quad::Quad;
+        use crate::render::{render_albedo, RenderOptions};
+        use crate::world::World;
+
+        // Black and white texels, far finer than the pixels they end up in
+        let size = 256;
+        let data = (0..size * size)
+            .flat_map(|i| {
+                let white = (i % size + i / size) % 2 == 0;
+                [if white { 255 } else { 0 }; 3]
+            })
+            .collect();
+        let texture = Arc::new(ImageTexture::linear(size, size, data));
+        let mut world = World::new();
+        world.add(Quad::new(
+            Vector::new(-1.0, -1.0, -2.0, VectorType::Point),
+            Vector::new(2.0, 0.0, 0.0, VectorType::Vector),
+            Vector::new(0.0, 2.0, 0.0, VectorType::Vector),
+            Material::Lambertian(Lambertian::with_texture(texture)),
+        ));
+        let camera = Camera::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Point),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+            20.0,
+            1.0,
+            0.0,
+            2.0,
+        );
+
+        // One sample per pixel: point sampling would give each pixel a single texel,
+        // black or white, while the filtered lookup averages them to gray
+        let image = render_albedo(8, 8, 1, &world, &camera, &RenderOptions::default());
+        for pixel in &image.pixels {
+            assert!((pixel.data.0 - 0.5).abs() < 0.1, "{:?}", pixel);
+        }
+    }
+}
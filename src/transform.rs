@@ -0,0 +1,337 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hittable};
+use crate::matrix::Matrix4;
+use crate::ray::Ray;
+use crate::validate::Problem;
+use crate::vector::{Scalar, Vector, VectorType};
+
+// An instance of `object` placed in the world by an affine `matrix`: any mix of
+// rotation, scale, shear and translation. Rays are moved into object space with the
+// inverse and the hit is moved back out.
+pub struct Transform<H: Hittable> {
+    object: H,
+    matrix: Matrix4,
+    inverse: Matrix4,
+}
+
+impl<H: Hittable> Transform<H> {
+    // Panics if `matrix` is singular, e.g. a scale of zero along some axis
+    pub fn new(object: H, matrix: Matrix4) -> Self {
+        Self {
+            object,
+            matrix,
+            inverse: matrix
+                .inverse()
+                .expect("transform matrix must be invertible"),
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for Transform<H> {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        hit_transformed(&self.object, &self.matrix, &self.inverse, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        transformed_box(&self.object.bounding_box()?, &self.matrix)
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        self.object.problems()
+    }
+}
+
+// `object` hit by `r` once it is moved into object space by `inverse`, with the hit
+// moved back out by `matrix`
+fn hit_transformed<'a, H: Hittable>(
+    object: &'a H,
+    matrix: &Matrix4,
+    inverse: &Matrix4,
+    r: &Ray,
+    t_min: Scalar,
+    t_max: Scalar,
+) -> Option<HitRecord<'a>> {
+    // The direction is not renormalized, so `t` means the same in both spaces
+    let mut local = Ray::new(
+        inverse.mul_point(&r.origin),
+        inverse.mul_vector(&r.direction),
+    )
+    .with_time(r.time);
+    if let Some(differentials) = &r.differentials {
+        local = local.with_differentials(
+            inverse.mul_vector(&differentials.dx),
+            inverse.mul_vector(&differentials.dy),
+        );
+    }
+
+    let mut hit = object.hit(&local, t_min, t_max)?;
+
+    // Normals follow the inverse transpose so they stay perpendicular under
+    // non-uniform scale and shear
+    hit.point = matrix.mul_point(&hit.point);
+    hit.normal = inverse
+        .transpose()
+        .mul_vector(&hit.normal)
+        .get_unit_vector();
+    hit.tangent = hit
+        .tangent
+        .map(|tangent| matrix.mul_vector(&tangent).get_unit_vector());
+    hit.bitangent = hit
+        .bitangent
+        .map(|bitangent| matrix.mul_vector(&bitangent).get_unit_vector());
+
+    Some(hit)
+}
+
+// The eight corners of `local`
+fn corners(local: &Aabb) -> impl Iterator<Item = Vector> + '_ {
+    (0..8).map(|i: usize| {
+        let pick = |bit: usize, min: Scalar, max: Scalar| if i & bit == 0 { min } else { max };
+        Vector::new(
+            pick(1, local.min.data.0, local.max.data.0),
+            pick(2, local.min.data.1, local.max.data.1),
+            pick(4, local.min.data.2, local.max.data.2),
+            VectorType::Point,
+        )
+    })
+}
+
+// The box around the corners of `local` moved by `matrix`
+fn transformed_box(local: &Aabb, matrix: &Matrix4) -> Option<Aabb> {
+    corners(local)
+        .map(|corner| matrix.mul_point(&corner))
+        .map(|p| Aabb::new(p, p))
+        .reduce(|a, b| a.surrounding(&b))
+}
+
+// An instance of `object` that moves from `start` at `time0` to `end` at `time1`
+// while the shutter is open, e.g. a whole mesh sliding or turning for motion blur.
+// A ray sees the object placed by the blend of the two matrices at its time, see
+// `Matrix4::interpolate`; outside `[time0, time1]` it rests at the nearer end.
+pub struct MovingTransform<H: Hittable> {
+    object: H,
+    start: Matrix4,
+    end: Matrix4,
+    time0: Scalar,
+    time1: Scalar,
+}
+
+impl<H: Hittable> MovingTransform<H> {
+    pub fn new(object: H, start: Matrix4, end: Matrix4, time0: Scalar, time1: Scalar) -> Self {
+        Self {
+            object,
+            start,
+            end,
+            time0,
+            time1,
+        }
+    }
+
+    // Where the object is placed at `time`
+    pub fn matrix_at(&self, time: Scalar) -> Matrix4 {
+        let f = if self.time1 > self.time0 {
+            ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        self.start.interpolate(&self.end, f)
+    }
+}
+
+impl<H: Hittable> Hittable for MovingTransform<H> {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        // Only a stretch that squashes the object flat on the way has nothing to hit
+        let matrix = self.matrix_at(r.time);
+        let inverse = matrix.inverse()?;
+
+        hit_transformed(&self.object, &matrix, &inverse, r, t_min, t_max)
+    }
+
+    // Without a turn each point of the object moves along the line between where the
+    // two ends place it, so the boxes at the ends cover the whole sweep. A turning
+    // object can swing out past them, but never further from the moving translation
+    // than the farthest stretched corner, so a ball of that radius swept between the
+    // ends' translations covers it.
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = self.object.bounding_box()?;
+        let ends =
+            transformed_box(&local, &self.start)?.surrounding(&transformed_box(&local, &self.end)?);
+
+        let (t0, r0, s0) = self.start.decompose();
+        let (t1, r1, s1) = self.end.decompose();
+        if r0.dot(&r1).abs() >= 1.0 - 1e-6 {
+            return Some(ends);
+        }
+
+        let radius = corners(&local)
+            .map(|corner| s0.mul_point(&corner).len().max(s1.mul_point(&corner).len()))
+            .fold(0.0 as Scalar, Scalar::max);
+        let reach = Vector::new(radius, radius, radius, VectorType::Vector);
+        let ball = |t: Vector| Aabb::new(t - reach, t + reach);
+
+        Some(ball(t0).surrounding(&ball(t1)))
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        self.object.problems()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::sphere::Sphere;
+    use crate::vector::fuzzy_equal;
+
+    fn assert_matrix_eq(actual: &Matrix4, expected: &Matrix4) {
+        for (actual_row, expected_row) in actual.m.iter().zip(expected.m.iter()) {
+            for (a, e) in actual_row.iter().zip(expected_row.iter()) {
+                assert!(fuzzy_equal(*a, *e), "{:?} != {:?}", actual, expected);
+            }
+        }
+    }
+
+    fn unit_sphere() -> Sphere {
+        Sphere::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            1.0,
+            Material::gray(),
+        )
+    }
+
+    #[test]
+    fn translated_and_scaled_sphere() {
+        // An ellipsoid with radii 2, 1, 1 centered at (0, 0, -5)
+        let ellipsoid = Transform::new(
+            unit_sphere(),
+            Matrix4::translation(0.0, 0.0, -5.0) * Matrix4::scaling(2.0, 1.0, 1.0),
+        );
+        let origin = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+
+        let head_on = Ray::new(origin, Vector::new(0.0, 0.0, -1.0, VectorType::Vector));
+        let hit = ellipsoid.hit(&head_on, 0.001, Scalar::INFINITY).unwrap();
+        assert!(fuzzy_equal(hit.t, 4.0));
+        assert_eq!(hit.point, Vector::new(0.0, 0.0, -4.0, VectorType::Point));
+        assert!(fuzzy_equal(hit.normal.data.2, 1.0));
+
+        // Wider than the sphere along x only
+        let side = Ray::new(
+            Vector::new(1.5, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+        );
+        let above = Ray::new(
+            Vector::new(0.0, 1.5, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+        );
+        let hit = ellipsoid.hit(&side, 0.001, Scalar::INFINITY).unwrap();
+        assert!(ellipsoid.hit(&above, 0.001, Scalar::INFINITY).is_none());
+
+        // The normal of (x/2)² + y² + z² = 1 is proportional to (x/4, y, z)
+        let local = Vector::new(
+            hit.point.data.0 / 2.0,
+            0.0,
+            hit.point.data.2 + 5.0,
+            VectorType::Point,
+        );
+        let expected = Vector::new(local.data.0 / 2.0, 0.0, local.data.2, VectorType::Vector)
+            .get_unit_vector();
+        assert!(fuzzy_equal(hit.normal.dot(&expected), 1.0));
+    }
+
+    #[test]
+    fn bounding_box_covers_the_transformed_object() {
+        let rotated = Transform::new(
+            unit_sphere(),
+            Matrix4::translation(3.0, 0.0, 0.0) * Matrix4::rotation_z(45.0),
+        );
+
+        let bounds = rotated.bounding_box().unwrap();
+
+        // The rotated box of the sphere reaches out to the diagonal of its cube
+        let reach = 2.0 as Scalar;
+        assert!(fuzzy_equal(bounds.min.data.0, 3.0 - reach.sqrt()));
+        assert!(fuzzy_equal(bounds.max.data.0, 3.0 + reach.sqrt()));
+        assert!(fuzzy_equal(bounds.max.data.2, 1.0));
+    }
+
+    #[test]
+    fn moving_transform_blends_the_ends_at_mid_exposure() {
+        let start = Matrix4::translation(-2.0, 0.0, -5.0);
+        let end = Matrix4::translation(2.0, 1.0, -5.0) * Matrix4::rotation_y(30.0);
+        let moving = MovingTransform::new(unit_sphere(), start, end, 0.0, 1.0);
+        let halfway = start.interpolate(&end, 0.5);
+
+        assert_eq!(moving.matrix_at(0.5), halfway);
+        assert_matrix_eq(&moving.matrix_at(-1.0), &start);
+        assert_matrix_eq(&moving.matrix_at(2.0), &end);
+
+        // Mid-exposure rays see the object where the blended transform puts it
+        let fixed = Transform::new(unit_sphere(), halfway);
+        let origin = Vector::new(0.0, 0.5, 0.0, VectorType::Point);
+        for x in [-0.15, -0.05, 0.0, 0.05, 0.15] {
+            let direction = Vector::new(x, 0.0, -1.0, VectorType::Vector);
+            let ray = Ray::new(origin, direction).with_time(0.5);
+            let moved = moving
+                .hit(&ray, 0.001, Scalar::INFINITY)
+                .map(|hit| hit.point);
+            let expected = fixed
+                .hit(&ray, 0.001, Scalar::INFINITY)
+                .map(|hit| hit.point);
+
+            assert!(expected.is_some());
+            assert_eq!(moved, expected);
+        }
+
+        // The box spans the whole sweep, from the left end to the right one
+        let bounds = moving.bounding_box().unwrap();
+        assert!(bounds.min.data.0 <= -3.0);
+        assert!(bounds.max.data.0 >= 3.0);
+        assert!(bounds.min.data.1 <= -1.0);
+        assert!(bounds.max.data.1 >= 2.0);
+    }
+
+    #[test]
+    fn half_turns_stay_rigid_halfway() {
+        // A ball two units out along x, swung round to the other side
+        let ball = || {
+            Sphere::new(
+                Vector::new(2.0, 0.0, 0.0, VectorType::Point),
+                0.5,
+                Material::gray(),
+            )
+        };
+        let end = Matrix4::rotation_y(180.0);
+        let moving = MovingTransform::new(ball(), Matrix4::identity(), end, 0.0, 1.0);
+
+        // A quarter turn in, a rotation that keeps the ball round and two units out
+        let halfway = moving.matrix_at(0.5);
+        let center = halfway.mul_point(&Vector::new(2.0, 0.0, 0.0, VectorType::Point));
+        assert!(fuzzy_equal(center.len(), 2.0));
+        assert!(fuzzy_equal(center.data.0, 0.0));
+        assert!(fuzzy_equal(center.data.1, 0.0));
+
+        // Mid-exposure rays find it there, full size
+        let origin = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+        let toward = Vector::new(0.0, 0.0, center.data.2.signum(), VectorType::Vector);
+        let hit = moving
+            .hit(
+                &Ray::new(origin, toward).with_time(0.5),
+                0.001,
+                Scalar::INFINITY,
+            )
+            .unwrap();
+        assert!(fuzzy_equal(hit.t, 1.5));
+
+        // The box covers the ball all the way round, not just at the ends
+        let bounds = moving.bounding_box().unwrap();
+        for time in [0.0, 0.25, 0.5, 0.75, 1.0] {
+            let swept =
+                transformed_box(&ball().bounding_box().unwrap(), &moving.matrix_at(time)).unwrap();
+            let grown = bounds.surrounding(&swept);
+            assert_eq!(grown.min.data, bounds.min.data, "{}", time);
+            assert_eq!(grown.max.data, bounds.max.data, "{}", time);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::validate::{self, Problem};
+use crate::vector::{Scalar, Vector};
+
+// The triangle with corners `a`, `b` and `c`, e.g. a face of a mesh. Its front is the
+// side the corners wind counterclockwise around, like the faces of an OBJ file.
+pub struct Triangle {
+    a: Vector,
+    u: Vector,
+    v: Vector,
+    material: Material,
+    normal: Vector,
+    d: Scalar,
+    w: Vector,
+}
+
+impl Triangle {
+    pub fn new(a: Vector, b: Vector, c: Vector, material: Material) -> Self {
+        let (u, v) = (b - a, c - a);
+        let n = u.cross(&v);
+        let normal = n.get_unit_vector();
+        let d = normal.dot(&a);
+        // A degenerate shape keeps a zero `w`; its zero normal already makes `hit` miss
+        let area_squared = n.dot(&n);
+        let w = if area_squared == 0.0 {
+            n
+        } else {
+            n / area_squared
+        };
+
+        Self {
+            a,
+            u,
+            v,
+            material,
+            normal,
+            d,
+            w,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        let denominator = self.normal.dot(&r.direction);
+
+        // The ray is parallel to the plane
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        // Also rejects the NaN of a degenerate ray
+        let t = (self.d - self.normal.dot(&r.origin)) / denominator;
+        if !(t_min <= t && t <= t_max) {
+            return None;
+        }
+
+        // Barycentric coordinates of the hit point towards `b` and `c`
+        let point = r.at(t);
+        let planar = point - self.a;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+
+        if alpha < 0.0 || beta < 0.0 || alpha + beta > 1.0 {
+            return None;
+        }
+
+        let front_face = r.direction.dot(&self.normal) < 0.0;
+        let normal = if front_face {
+            self.normal
+        } else {
+            -self.normal
+        };
+        let tangent = self.u.get_unit_vector();
+        let footprint = r.footprint_at(t, &normal);
+
+        Some(HitRecord {
+            point,
+            normal,
+            t,
+            u: alpha,
+            v: beta,
+            uv_footprint: (footprint / self.u.len()).max(footprint / self.v.len()),
+            front_face,
+            material: &self.material,
+            tangent: Some(tangent),
+            bitangent: Some(normal.cross(&tangent)),
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (b, c) = (self.a + self.u, self.a + self.v);
+
+        Some(
+            Aabb::new(self.a, b)
+                .surrounding(&Aabb::new(self.a, c))
+                .pad(1e-4),
+        )
+    }
+
+    fn problems(&self) -> Vec<Problem> {
+        let mut problems = self.material.problems();
+
+        if ![self.a, self.u, self.v].iter().all(validate::is_finite) {
+            problems.push(Problem::NonFinite);
+        } else if self.u.cross(&self.v).near_zero() {
+            problems.push(Problem::ZeroArea);
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vector::{fuzzy_equal, VectorType};
+
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            Vector::new(2.0, 0.0, -2.0, VectorType::Point),
+            Vector::new(0.0, 2.0, -2.0, VectorType::Point),
+            Material::gray(),
+        )
+    }
+
+    fn towards(x: Scalar, y: Scalar) -> Ray {
+        Ray::new(
+            Vector::new(x, y, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+        )
+    }
+
+    #[test]
+    fn hit_reports_barycentric_uv_and_faces_the_winding() {
+        let triangle = triangle();
+        let hit = triangle
+            .hit(&towards(0.5, 1.0), 0.001, Scalar::INFINITY)
+            .unwrap();
+
+        assert!(fuzzy_equal(hit.t, 2.0));
+        assert!(fuzzy_equal(hit.u, 0.25));
+        assert!(fuzzy_equal(hit.v, 0.5));
+        // Counterclockwise as seen from the ray
+        assert!(hit.front_face);
+        assert!(fuzzy_equal(hit.normal.data.2, 1.0));
+    }
+
+    #[test]
+    fn rays_past_the_edges_miss() {
+        let triangle = triangle();
+
+        // Inside the bounding square but beyond the hypotenuse
+        assert!(triangle
+            .hit(&towards(1.5, 1.5), 0.001, Scalar::INFINITY)
+            .is_none());
+        // Just outside the edge along Y
+        assert!(triangle
+            .hit(&towards(-0.1, 1.0), 0.001, Scalar::INFINITY)
+            .is_none());
+    }
+}
@@ -1,43 +1,327 @@
-use crate::material::Scatterable;
+use crate::hit::HitRecord;
+use crate::log;
+use crate::material::{Material, Scatterable};
+use crate::pdf::{CosinePdf, HittablePdf, MixturePdf, Pdf};
 use crate::ray::Ray;
-use crate::sphere::hit_world;
-use crate::sphere::Sphere;
-use crate::vector::Vector;
 use crate::vector::VectorType;
+use crate::vector::{Scalar, Vector};
+use crate::world::World;
 
-use rand::Rng;
+use rand::{Rng, RngCore};
 
-pub fn ray_color(r: &Ray, world: &Vec<Sphere>, depth: u64) -> Vector {
-    if depth <= 0 {
-        return Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+// The default sky: white at the horizon fading to light blue overhead
+pub const SKY_BOTTOM: Vector = Vector {
+    data: (1.0, 1.0, 1.0),
+    data_type: VectorType::Color,
+};
+pub const SKY_TOP: Vector = Vector {
+    data: (0.5, 0.7, 1.0),
+    data_type: VectorType::Color,
+};
+
+// A scene's own sky, running from `bottom` to `top` straight up as laid out by a
+// `SkyMapping`, e.g. near black for a night scene. Set on a `World` it takes the place of the
+// sky colors the renderer is given.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkyGradient {
+    pub bottom: Vector,
+    pub top: Vector,
+}
+
+impl SkyGradient {
+    pub fn new(bottom: Vector, top: Vector) -> Self {
+        Self { bottom, top }
+    }
+
+    pub fn color(&self, direction: &Vector, mapping: SkyMapping) -> Vector {
+        sky_color(direction, self.bottom, self.top, mapping)
     }
+}
+
+impl Default for SkyGradient {
+    fn default() -> Self {
+        Self::new(SKY_BOTTOM, SKY_TOP)
+    }
+}
 
-    match hit_world(world, r, 0.0001, f64::INFINITY) {
+// What a path that runs out of bounces returns
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DepthExhaustion {
+    // Nothing, which loses energy in deep reflections
+    #[default]
+    Black,
+    // The sky in the direction of the last ray, as if it had escaped
+    Background,
+}
+
+// Radiance along `r`. Rays that escape the scene see a vertical gradient from
+// `sky_bottom` to `sky_top` straight up, laid out by the world's `sky_mapping`.
+pub fn ray_color(
+    r: &Ray,
+    world: &World,
+    depth: u64,
+    sky_bottom: Vector,
+    sky_top: Vector,
+) -> Vector {
+    ray_color_with(
+        r,
+        world,
+        depth,
+        sky_bottom,
+        sky_top,
+        DepthExhaustion::Black,
+        None,
+        None,
+        &mut rand::thread_rng(),
+    )
+}
+
+// Caustic light a path tracer takes from elsewhere instead of tracing it, e.g. from a
+// photon map. Every diffuse surface a path reaches adds the light `light` gives for it,
+// and paths stop where a diffuse bounce is followed by a specular one, since that light
+// is already counted. Light bounced diffusely before it reaches glass or a mirror is
+// lost with those paths.
+#[derive(Clone, Copy)]
+pub struct Caustics<'a> {
+    light: &'a (dyn Fn(&HitRecord) -> Vector + Sync),
+    // Whether the path has bounced off a diffuse surface yet
+    after_diffuse: bool,
+}
+
+impl<'a> Caustics<'a> {
+    pub fn new(light: &'a (dyn Fn(&HitRecord) -> Vector + Sync)) -> Self {
+        Self {
+            light,
+            after_diffuse: false,
+        }
+    }
+
+    // Whether the path ends at `hit_record`, keeping only what it emits
+    pub(crate) fn stops_at(&self, hit_record: &HitRecord) -> bool {
+        self.after_diffuse && hit_record.material.is_specular()
+    }
+
+    // The caustic light leaving `hit_record` along the path
+    pub(crate) fn light_at(&self, hit_record: &HitRecord) -> Vector {
+        if hit_record.material.is_specular() {
+            Vector::new(0.0, 0.0, 0.0, VectorType::Color)
+        } else {
+            (self.light)(hit_record)
+        }
+    }
+
+    // The state for the path scattered at `hit_record`
+    pub(crate) fn after(self, hit_record: &HitRecord) -> Self {
+        Self {
+            after_diffuse: self.after_diffuse || !hit_record.material.is_specular(),
+            ..self
+        }
+    }
+}
+
+// `ray_color` with a choice of what paths return once `depth` runs out, drawing
+// every random decision along the path from `rng`. With `max_indirect_luminance` set,
+// the radiance arriving along every scattered ray is scaled down to at most that
+// luminance, trading a little bias for fewer fireflies from deep glossy paths. What
+// the camera ray sees directly is never clamped. With `caustics` set, the caustic
+// light comes from there, see `Caustics`.
+#[allow(clippy::too_many_arguments)]
+pub fn ray_color_with(
+    r: &Ray,
+    world: &World,
+    depth: u64,
+    sky_bottom: Vector,
+    sky_top: Vector,
+    exhaustion: DepthExhaustion,
+    max_indirect_luminance: Option<Scalar>,
+    caustics: Option<Caustics>,
+    rng: &mut dyn RngCore,
+) -> Vector {
+    let (sky_bottom, sky_top) = world.sky_or(sky_bottom, sky_top);
+
+    if depth == 0 {
+        return match exhaustion {
+            DepthExhaustion::Black => Vector::new(0.0, 0.0, 0.0, VectorType::Color),
+            DepthExhaustion::Background => {
+                sky_color(&r.direction, sky_bottom, sky_top, world.sky_mapping)
+            }
+        };
+    }
+
+    match world.hit(r, world.t_min(r), Scalar::INFINITY) {
         Some(hit_record) => {
-            let scattered = hit_record.material.scatter(r, &hit_record);
+            // Holes in cutouts do not count as a bounce
+            if hit_record.material.passes_through(&hit_record) {
+                return ray_color_with(
+                    &r.continued_from(hit_record.point),
+                    world,
+                    depth,
+                    sky_bottom,
+                    sky_top,
+                    exhaustion,
+                    max_indirect_luminance,
+                    caustics,
+                    rng,
+                );
+            }
+
+            if caustics.is_some_and(|caustics| caustics.stops_at(&hit_record)) {
+                return hit_record.material.emitted(&hit_record);
+            }
+
+            if let Material::Phong(phong) = hit_record.material {
+                return phong.shade(r, &hit_record, world);
+            }
 
-            match scattered {
-                Some((scattered_ray, albedo)) => match scattered_ray {
-                    Some(sr) => {
-                        return albedo * ray_color(&sr, world, depth - 1);
+            let emitted = hit_record.material.emitted(&hit_record)
+                + caustics.map_or(Vector::new(0.0, 0.0, 0.0, VectorType::Color), |caustics| {
+                    caustics.light_at(&hit_record)
+                });
+
+            match hit_record.material.scatter(r, &hit_record, rng) {
+                Some((Some(sr), albedo)) => {
+                    let (sr, weight) =
+                        sample_lights(r, &hit_record, world, rng).unwrap_or((sr, 1.0));
+                    if weight == 0.0 {
+                        return emitted;
                     }
-                    None => albedo,
-                },
-                None => {
-                    return Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+
+                    let incoming = ray_color_with(
+                        &sr,
+                        world,
+                        depth - 1,
+                        sky_bottom,
+                        sky_top,
+                        exhaustion,
+                        max_indirect_luminance,
+                        caustics.map(|caustics| caustics.after(&hit_record)),
+                        rng,
+                    );
+
+                    emitted
+                        + weight
+                            * albedo
+                            * match max_indirect_luminance {
+                                Some(max) => clamp_luminance(incoming, max),
+                                None => incoming,
+                            }
                 }
+                Some((None, albedo)) => emitted + albedo,
+                None => emitted,
             }
         }
-        None => {
-            let unit_direction = r.direction.get_unit_vector();
-            let t = 0.5 * (unit_direction.data.1 + 1.0);
-            return (1.0 - t) * Vector::new(1.0, 1.0, 1.0, VectorType::Color)
-                + t * Vector::new(0.5, 0.7, 1.0, VectorType::Color);
+        None => sky_color(&r.direction, sky_bottom, sky_top, world.sky_mapping),
+    }
+}
+
+// For diffuse bounces in scenes with lights, a scattered ray drawn half the time
+// towards the lights and half the time from the cosine lobe, with the weight
+// `scattering_pdf / pdf` that keeps the estimate unbiased. `None` for specular
+// materials and scenes without lights, where the material's own bounce is used.
+pub(crate) fn sample_lights(
+    r: &Ray,
+    hit_record: &HitRecord,
+    world: &World,
+    rng: &mut dyn RngCore,
+) -> Option<(Ray, Scalar)> {
+    if world.lights.is_empty() || hit_record.material.is_specular() {
+        return None;
+    }
+
+    let toward_lights = HittablePdf::new(world, &world.lights, hit_record.point);
+    let cosine = CosinePdf::new(&hit_record.normal);
+    let mixture = MixturePdf::new(&cosine, &toward_lights);
+
+    let scattered = Ray::new(hit_record.point, mixture.generate(rng)).with_time(r.time);
+    let pdf = mixture.value(&scattered.direction);
+    let scattering_pdf = hit_record
+        .material
+        .scattering_pdf(r, hit_record, &scattered);
+    let weight = if pdf > 0.0 { scattering_pdf / pdf } else { 0.0 };
+
+    Some((scattered, weight))
+}
+
+// `color` scaled down, keeping its hue, so its luminance is at most `max`
+pub(crate) fn clamp_luminance(color: Vector, max: Scalar) -> Vector {
+    let luminance = color.luminance();
+    if luminance > max {
+        color * (max / luminance)
+    } else {
+        color
+    }
+}
+
+// How a sky gradient is spread over the directions rays escape in
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SkyMapping {
+    // `bottom` at the horizon blending to `top` straight up. Everything below the
+    // horizon is `bottom` too, which the ground usually hides anyway.
+    #[default]
+    Horizon,
+    // `bottom` straight down blending to `top` straight up, so the horizon is halfway
+    // between them. How the sky looked before its colors could be chosen, and what
+    // `output.ppm` was rendered with.
+    Sphere,
+}
+
+// The sky seen along `direction`, see `SkyMapping`
+pub fn sky_color(direction: &Vector, bottom: Vector, top: Vector, mapping: SkyMapping) -> Vector {
+    let y = direction.get_unit_vector().data.1;
+    let t = match mapping {
+        SkyMapping::Horizon => y.max(0.0),
+        SkyMapping::Sphere => 0.5 * (y + 1.0),
+    };
+    (1.0 - t) * bottom + t * top
+}
+
+// Parses `value`, the contents of the setting `name`, falling back to `default`
+// with a warning when it is garbage. A missing value silently uses the default.
+pub fn parse_or<T: std::str::FromStr>(name: &str, value: Option<&str>, default: T) -> T {
+    match value.map(|v| v.trim().parse()) {
+        Some(Ok(parsed)) => parsed,
+        Some(Err(_)) => {
+            log::info(format_args!(
+                "warning: ignoring unparseable {}={:?}",
+                name,
+                value.unwrap()
+            ));
+            default
         }
+        None => default,
     }
 }
 
-pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
+// Reads the environment variable `name`, see `parse_or`
+pub fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    parse_or(name, std::env::var(name).ok().as_deref(), default)
+}
+
+// Seed for one sample of one pixel. Every sample gets its own generator keyed only on
+// its coordinates and the render's `base` seed, so a pixel's color does not depend on
+// how rayon splits the work.
+pub fn seed_for(base: u64, x: u32, y: u32, sample: u32) -> u64 {
+    // Each part is mixed in after the ones before are scrambled, so no two keys
+    // differing in any part can line up their bits and cancel
+    mix(mix(mix(mix(base) ^ x as u64) ^ y as u64) ^ sample as u64)
+}
+
+// SplitMix64 step: spreads every input bit over the whole output
+fn mix(key: u64) -> u64 {
+    let mut z = key.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// A path in the temp directory for the test file `name`, unique to this process so
+// test runs going on at once do not overwrite each other's files
+#[cfg(test)]
+pub(crate) fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("ray-tracer-{}-{}", std::process::id(), name))
+}
+
+pub fn clamp(x: Scalar, min: Scalar, max: Scalar) -> Scalar {
     if x < min {
         return min;
     }
@@ -49,12 +333,11 @@ pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     x
 }
 
-pub fn degrees_to_radians(degree: f64) -> f64 {
-    degree * std::f64::consts::PI / 180.0
+pub fn degrees_to_radians(degree: Scalar) -> Scalar {
+    degree * crate::vector::consts::PI / 180.0
 }
 
-pub fn random_in_unit_disk() -> Vector {
-    let mut rng = rand::thread_rng();
+pub fn random_in_unit_disk_with<R: Rng + ?Sized>(rng: &mut R) -> Vector {
     loop {
         let p = Vector::new(
             rng.gen_range(-1.0..1.0),
@@ -70,3 +353,398 @@ pub fn random_in_unit_disk() -> Vector {
         return p;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::fuzzy_equal;
+
+    #[test]
+    fn world_sky_replaces_the_default_one() {
+        let red = Vector::new(1.0, 0.0, 0.0, VectorType::Color);
+        let black = Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+        let world = World::new().with_sky(SkyGradient::new(red, black));
+        let color = |x: Scalar, y: Scalar| {
+            let ray = Ray::new(
+                Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+                Vector::new(x, y, 0.0, VectorType::Vector),
+            );
+            ray_color(&ray, &world, 10, SKY_BOTTOM, SKY_TOP)
+        };
+
+        assert_eq!(color(1.0, 0.0), red);
+        assert_eq!(color(0.0, 1.0), black);
+        assert_eq!(color(3.0, 4.0), 0.2 * red);
+
+        // Without one, the sky passed in is used
+        let ray = Ray::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(1.0, 0.0, 0.0, VectorType::Vector),
+        );
+        assert_eq!(
+            ray_color(&ray, &World::new(), 10, SKY_BOTTOM, SKY_TOP),
+            SKY_BOTTOM
+        );
+    }
+
+    #[test]
+    fn parse_or_falls_back_to_default() {
+        assert_eq!(parse_or("RT_WIDTH", None, 1200u32), 1200);
+        assert_eq!(parse_or("RT_WIDTH", Some("wide"), 1200u32), 1200);
+        assert_eq!(parse_or("RT_WIDTH", Some("-4"), 1200u32), 1200);
+        assert_eq!(parse_or("RT_WIDTH", Some(" 640\n"), 1200u32), 640);
+        assert_eq!(parse_or("RT_SEED", Some("42"), 0u64), 42);
+    }
+
+    #[test]
+    fn pixel_seeds_differ() {
+        assert_ne!(seed_for(0, 0, 0, 0), seed_for(0, 1, 0, 0));
+        assert_ne!(seed_for(0, 0, 0, 0), seed_for(0, 0, 1, 0));
+        assert_ne!(seed_for(0, 0, 0, 0), seed_for(0, 0, 0, 1));
+        assert_ne!(seed_for(0, 0, 0, 0), seed_for(1, 0, 0, 0));
+        // Swapping coordinates gives a different seed
+        assert_ne!(seed_for(0, 1, 2, 3), seed_for(0, 2, 1, 3));
+        assert_ne!(seed_for(0, 1, 2, 3), seed_for(0, 1, 3, 2));
+    }
+
+    #[test]
+    fn seed_keys_never_collide() {
+        use std::collections::HashSet;
+
+        // Parts that a shifted or XOR-packed key would line up with each other
+        let bases = [0, 1, 2, 256, 1 << 20, 1 << 32, 1 << 40, u64::MAX];
+        let coordinates = [0, 1, 2, 3, 255, 256, 1 << 12, 1 << 20, u32::MAX];
+        let mut seeds = HashSet::new();
+        let mut count = 0;
+        for &base in &bases {
+            for &x in &coordinates {
+                for &y in &coordinates {
+                    for &sample in &coordinates {
+                        seeds.insert(seed_for(base, x, y, sample));
+                        count += 1;
+                    }
+                }
+            }
+        }
+
+        assert_eq!(seeds.len(), count);
+        assert_ne!(seed_for(256, 0, 0, 0), seed_for(0, 1, 0, 0));
+        assert_ne!(seed_for(0, 0, 1, 0), seed_for(0, 0, 0, 1 << 20));
+    }
+
+    #[test]
+    fn seeds_are_deterministic_and_order_independent() {
+        use rayon::prelude::*;
+
+        let keys: Vec<(u32, u32)> = (0..64).flat_map(|y| (0..64).map(move |x| (x, y))).collect();
+        let forward: Vec<u64> = keys.iter().map(|&(x, y)| seed_for(9, x, y, 5)).collect();
+        let backward: Vec<u64> = keys
+            .iter()
+            .rev()
+            .map(|&(x, y)| seed_for(9, x, y, 5))
+            .rev()
+            .collect();
+        let parallel: Vec<u64> = keys
+            .par_iter()
+            .map(|&(x, y)| seed_for(9, x, y, 5))
+            .collect();
+
+        assert_eq!(forward, backward);
+        assert_eq!(forward, parallel);
+    }
+
+    #[test]
+    fn seeds_are_evenly_distributed() {
+        // Chi-square of the low and high bits of the seeds of a 64x64 frame over 16
+        // buckets. With 15 degrees of freedom, 37.7 is exceeded by chance 0.1% of the
+        // time.
+        let seeds: Vec<u64> = (0..64)
+            .flat_map(|y| (0..64).map(move |x| seed_for(0, x, y, 0)))
+            .collect();
+        let expected = seeds.len() as Scalar / 16.0;
+
+        for bucket_of in [|s: u64| s % 16, |s: u64| s >> 60] {
+            let mut counts = [0u32; 16];
+            for &seed in &seeds {
+                counts[bucket_of(seed) as usize] += 1;
+            }
+            let chi_square: Scalar = counts
+                .iter()
+                .map(|&count| (count as Scalar - expected).powi(2) / expected)
+                .sum();
+
+            assert!(chi_square < 37.7, "chi-square {}", chi_square);
+        }
+    }
+
+    #[test]
+    fn exhausted_paths_can_see_the_background() {
+        use crate::material::Metal;
+        use crate::sphere::Sphere;
+
+        // The camera sits inside a perfect mirror, so every path runs out of depth
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            2.0,
+            Material::Metal(Metal::new(
+                Vector::new(1.0, 1.0, 1.0, VectorType::Color),
+                0.0,
+            )),
+        ));
+        let sky = Vector::new(0.3, 0.6, 0.9, VectorType::Color);
+        let ray = Ray::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.3, 0.2, -1.0, VectorType::Vector),
+        );
+
+        let black = ray_color_with(
+            &ray,
+            &world,
+            10,
+            sky,
+            sky,
+            DepthExhaustion::Black,
+            None,
+            None,
+            &mut rand::thread_rng(),
+        );
+        let background = ray_color_with(
+            &ray,
+            &world,
+            10,
+            sky,
+            sky,
+            DepthExhaustion::Background,
+            None,
+            None,
+            &mut rand::thread_rng(),
+        );
+
+        assert_eq!(black, Vector::new(0.0, 0.0, 0.0, VectorType::Color));
+        assert_eq!(background, sky);
+    }
+
+    #[test]
+    fn indirect_light_is_clamped_but_direct_is_not() {
+        use crate::material::{DiffuseLight, Metal};
+        use crate::sphere::Sphere;
+
+        // A bright light straight ahead, and a mirror below that reflects it
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -5.0, VectorType::Point),
+            1.0,
+            Material::DiffuseLight(DiffuseLight::new(Vector::new(
+                50.0,
+                50.0,
+                50.0,
+                VectorType::Color,
+            ))),
+        ));
+        world.add(Sphere::new(
+            Vector::new(0.0, -1001.0, 0.0, VectorType::Point),
+            1000.0,
+            Material::Metal(Metal::new(
+                Vector::new(1.0, 1.0, 1.0, VectorType::Color),
+                0.0,
+            )),
+        ));
+        let black = Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+        let origin = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+        let direct = Ray::new(origin, Vector::new(0.0, 0.0, -1.0, VectorType::Vector));
+        let reflected = Ray::new(origin, Vector::new(0.0, -0.4, -1.0, VectorType::Vector));
+        let trace = |r: &Ray| {
+            ray_color_with(
+                r,
+                &world,
+                10,
+                black,
+                black,
+                DepthExhaustion::Black,
+                Some(2.0),
+                None,
+                &mut rand::thread_rng(),
+            )
+        };
+
+        assert!(fuzzy_equal(trace(&direct).luminance(), 50.0));
+        assert!(fuzzy_equal(trace(&reflected).luminance(), 2.0));
+    }
+
+    #[test]
+    fn light_sampling_is_unbiased_and_less_noisy() {
+        use crate::light::area_light;
+
+        use crate::quad::Quad;
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        // A gray floor lit only by a small panel overhead
+        let mut world = World::new();
+        world.add(Quad::new(
+            Vector::new(-50.0, 0.0, -50.0, VectorType::Point),
+            Vector::new(100.0, 0.0, 0.0, VectorType::Vector),
+            Vector::new(0.0, 0.0, 100.0, VectorType::Vector),
+            Material::gray(),
+        ));
+        let panel = Quad::new(
+            Vector::new(-0.25, 2.0, -0.25, VectorType::Point),
+            Vector::new(0.5, 0.0, 0.0, VectorType::Vector),
+            Vector::new(0.0, 0.0, 0.5, VectorType::Vector),
+            Material::gray(),
+        );
+        area_light(
+            &mut world,
+            panel,
+            Vector::new(1.0, 1.0, 1.0, VectorType::Color),
+            20.0,
+        );
+
+        let black = Vector::new(0.0, 0.0, 0.0, VectorType::Color);
+        let ray = Ray::new(
+            Vector::new(0.0, 1.0, 1.0, VectorType::Point),
+            Vector::new(0.0, -1.0, -1.0, VectorType::Vector),
+        );
+        let estimate = |world: &World, n: usize| {
+            let mut rng = StdRng::seed_from_u64(3);
+            let samples: Vec<Scalar> = (0..n)
+                .map(|_| {
+                    ray_color_with(
+                        &ray,
+                        world,
+                        2,
+                        black,
+                        black,
+                        DepthExhaustion::Black,
+                        None,
+                        None,
+                        &mut rng,
+                    )
+                    .luminance()
+                })
+                .collect();
+            let mean = samples.iter().sum::<Scalar>() / n as Scalar;
+            let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<Scalar>() / n as Scalar;
+            (mean, variance)
+        };
+
+        let (sampled, sampled_variance) = estimate(&world, 20000);
+        world.lights.clear();
+        let (_, brute_force_variance) = estimate(&world, 20000);
+
+        // The panel is small enough to treat as a point: irradiance L·A·cos²θ/d² on the
+        // floor, reflected as albedo/π of it
+        let expected = 0.5 / crate::vector::consts::PI * 20.0 * 0.25 / 4.0;
+        assert!((sampled / expected - 1.0).abs() < 0.05, "{}", sampled);
+        assert!(sampled_variance * 10.0 < brute_force_variance);
+    }
+
+    #[test]
+    fn unlit_objects_show_their_color_exactly() {
+        use crate::light::area_light;
+        use crate::material::Unlit;
+        use crate::sphere::Sphere;
+
+        let red = Vector::new(1.0, 0.0, 0.0, VectorType::Color);
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -3.0, VectorType::Point),
+            1.0,
+            Material::Unlit(Unlit::new(red)),
+        ));
+        area_light(
+            &mut world,
+            Sphere::new(
+                Vector::new(0.0, 3.0, -3.0, VectorType::Point),
+                1.0,
+                Material::gray(),
+            ),
+            Vector::new(1.0, 1.0, 1.0, VectorType::Color),
+            50.0,
+        );
+        let ray = Ray::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+        );
+        let sky = Vector::new(0.3, 0.6, 0.9, VectorType::Color);
+
+        for depth in [1, 2, 50] {
+            let color = ray_color_with(
+                &ray,
+                &world,
+                depth,
+                sky,
+                sky,
+                DepthExhaustion::Background,
+                None,
+                None,
+                &mut rand::thread_rng(),
+            );
+            assert_eq!(color.data, red.data);
+        }
+    }
+
+    #[test]
+    fn sky_gradient_endpoints() {
+        let world = World::new();
+        let bottom = Vector::new(1.0, 0.4, 0.1, VectorType::Color);
+        let top = Vector::new(0.1, 0.0, 0.3, VectorType::Color);
+        let origin = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+
+        let horizon = Ray::new(origin, Vector::new(1.0, 0.0, -1.0, VectorType::Vector));
+        let up = Ray::new(origin, Vector::new(0.0, 1.0, 0.0, VectorType::Vector));
+
+        assert_eq!(ray_color(&horizon, &world, 5, bottom, top), bottom);
+        assert_eq!(ray_color(&up, &world, 5, bottom, top), top);
+        let down = Ray::new(origin, Vector::new(0.0, -1.0, 0.0, VectorType::Vector));
+        assert_eq!(ray_color(&down, &world, 5, bottom, top), bottom);
+
+        // The original sky carries on below the horizon and meets it halfway
+        let world = world.with_sky_mapping(SkyMapping::Sphere);
+        assert_eq!(ray_color(&down, &world, 5, bottom, top), bottom);
+        assert_eq!(
+            ray_color(&horizon, &world, 5, bottom, top),
+            0.5 * (bottom + top)
+        );
+        assert_eq!(ray_color(&up, &world, 5, bottom, top), top);
+    }
+
+    #[test]
+    fn rays_pass_through_cutout_holes() {
+        use crate::material::Cutout;
+        use crate::quad::Quad;
+        use crate::texture::ImageTexture;
+        use std::sync::Arc;
+
+        // Transparent on the left half, opaque on the right
+        let alpha = Arc::new(ImageTexture::linear(2, 1, vec![0, 0, 0, 255, 255, 255]));
+        let mut world = World::new();
+        world.add(Quad::new(
+            Vector::new(-1.0, -1.0, -1.0, VectorType::Point),
+            Vector::new(2.0, 0.0, 0.0, VectorType::Vector),
+            Vector::new(0.0, 2.0, 0.0, VectorType::Vector),
+            Material::Cutout(Cutout::new(Material::gray(), alpha, 0.5)),
+        ));
+        let sky = Vector::new(0.3, 0.6, 0.9, VectorType::Color);
+        let origin = Vector::new(0.0, 0.0, 0.0, VectorType::Point);
+        // A single bounce: the hole must not use it up, the opaque side scatters and
+        // runs out of depth
+        let trace = |x: Scalar| {
+            ray_color_with(
+                &Ray::new(origin, Vector::new(x, 0.0, -1.0, VectorType::Vector)),
+                &world,
+                1,
+                sky,
+                sky,
+                DepthExhaustion::Black,
+                None,
+                None,
+                &mut rand::thread_rng(),
+            )
+        };
+
+        assert_eq!(trace(-0.5), sky);
+        assert_eq!(trace(0.5), Vector::new(0.0, 0.0, 0.0, VectorType::Color));
+    }
+}
@@ -0,0 +1,55 @@
+use crate::vector::{Scalar, Vector};
+use std::fmt;
+
+// Something about a primitive or its material that is almost certainly a scene
+// authoring mistake. These usually render as black pixels rather than failing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Problem {
+    // A NaN or infinite position, size or coefficient
+    NonFinite,
+    ZeroRadius,
+    // Only legitimate for dielectrics, where it makes a hollow glass shell
+    NegativeRadius(Scalar),
+    ZeroArea,
+    IndexOfRefraction(Scalar),
+    AlbedoOutOfRange(Vector),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::NonFinite => write!(f, "non-finite coordinates"),
+            Problem::ZeroRadius => write!(f, "zero radius"),
+            Problem::NegativeRadius(radius) => {
+                write!(f, "negative radius {} on a solid material", radius)
+            }
+            Problem::ZeroArea => write!(f, "zero area"),
+            Problem::IndexOfRefraction(ir) => write!(f, "index of refraction {} below 1", ir),
+            Problem::AlbedoOutOfRange(albedo) => {
+                write!(f, "albedo {:?} outside [0, 1]", albedo.data)
+            }
+        }
+    }
+}
+
+// A `Problem` with the index into `World::objects` of the primitive it was found on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Warning {
+    pub primitive: usize,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "primitive {}: {}", self.primitive, self.problem)
+    }
+}
+
+pub fn is_finite(v: &Vector) -> bool {
+    v.is_finite()
+}
+
+pub fn in_unit_range(v: &Vector) -> bool {
+    let in_range = |c: Scalar| (0.0..=1.0).contains(&c);
+    in_range(v.data.0) && in_range(v.data.1) && in_range(v.data.2)
+}
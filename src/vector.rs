@@ -2,6 +2,21 @@ use rand::Rng;
 use std::cmp::PartialEq;
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
+// Precision of every coordinate and color channel. The `f32` feature halves the
+// memory traffic of the hot loop at the cost of some accuracy.
+#[cfg(not(feature = "f32"))]
+pub type Scalar = f64;
+#[cfg(feature = "f32")]
+pub type Scalar = f32;
+
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
+
+// Offsets `Vector::reflect_perturbed` draws before giving up
+const REFLECT_ATTEMPTS: usize = 8;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum VectorType {
     Vector,
@@ -11,23 +26,23 @@ pub enum VectorType {
 
 #[derive(Debug, Clone, Copy)]
 pub struct Vector {
-    pub data: (f64, f64, f64),
+    pub data: (Scalar, Scalar, Scalar),
     pub data_type: VectorType,
 }
 
 impl Vector {
-    pub fn new(x: f64, y: f64, z: f64, data_type: VectorType) -> Self {
+    pub fn new(x: Scalar, y: Scalar, z: Scalar, data_type: VectorType) -> Self {
         Self {
             data: (x, y, z),
             data_type,
         }
     }
 
-    pub fn len(&self) -> f64 {
+    pub fn len(&self) -> Scalar {
         self.length_squared().sqrt()
     }
 
-    pub fn dot(&self, other: &Self) -> f64 {
+    pub fn dot(&self, other: &Self) -> Scalar {
         self.data.0 * other.data.0 + self.data.1 * other.data.1 + self.data.2 * other.data.2
     }
 
@@ -40,17 +55,124 @@ impl Vector {
         )
     }
 
+    // The component of `self` along `other`. Nothing lies along the zero vector, so
+    // projecting onto it gives zero rather than NaN.
+    pub fn project_onto(&self, other: &Self) -> Self {
+        let length_squared = other.length_squared();
+        if length_squared == 0.0 {
+            return Self::new(0.0, 0.0, 0.0, self.data_type);
+        }
+
+        let scale = self.dot(other) / length_squared;
+        Self::new(
+            scale * other.data.0,
+            scale * other.data.1,
+            scale * other.data.2,
+            self.data_type,
+        )
+    }
+
+    // Componentwise `self / other`, with 0 wherever `other` is 0 instead of the
+    // infinity or NaN that would spread through later color math
+    pub fn safe_div(&self, other: &Self) -> Self {
+        self.safe_div_or(other, 0.0)
+    }
+
+    // `safe_div` with `fallback` for the components that would divide by 0
+    pub fn safe_div_or(&self, other: &Self, fallback: Scalar) -> Self {
+        let div = |a: Scalar, b: Scalar| if b == 0.0 { fallback } else { a / b };
+
+        Self::new(
+            div(self.data.0, other.data.0),
+            div(self.data.1, other.data.1),
+            div(self.data.2, other.data.2),
+            self.data_type,
+        )
+    }
+
+    // Angle in radians between the directions of `self` and `other`, from 0 to π.
+    // Rounding can push the cosine of nearly parallel vectors just past ±1, where
+    // `acos` is NaN, so it is clamped. Zero vectors have no direction and give 0.
+    pub fn angle_between(&self, other: &Self) -> Scalar {
+        let lengths = self.len() * other.len();
+        if lengths == 0.0 {
+            return 0.0;
+        }
+
+        (self.dot(other) / lengths).clamp(-1.0, 1.0).acos()
+    }
+
+    // The part of `self` perpendicular to `other`, what `project_onto` leaves over
+    pub fn reject_from(&self, other: &Self) -> Self {
+        *self - self.project_onto(other)
+    }
+
+    // Mirror image of `self` about the plane with unit `normal`
+    pub fn reflect(&self, normal: &Self) -> Self {
+        *self - *normal * (2.0 * self.dot(normal))
+    }
+
+    // The reflection of `incident` blurred by a random offset within `roughness` of
+    // it, as for glossy metals and coats. Offsets that would send the ray into the
+    // surface are redrawn; near grazing angles every draw can, and there is `None`.
+    pub fn reflect_rough<R: Rng + ?Sized>(
+        incident: &Self,
+        normal: &Self,
+        roughness: Scalar,
+        rng: &mut R,
+    ) -> Option<Self> {
+        Self::reflect_perturbed(incident, normal, rng, |rng| {
+            roughness * Self::random_in_unit_sphere_with(rng)
+        })
+    }
+
+    // `reflect_rough` with any distribution of offsets, e.g. one stretched along a
+    // tangent frame
+    pub fn reflect_perturbed<R: Rng + ?Sized>(
+        incident: &Self,
+        normal: &Self,
+        rng: &mut R,
+        mut offset: impl FnMut(&mut R) -> Self,
+    ) -> Option<Self> {
+        let reflected = incident.get_unit_vector().reflect(normal);
+
+        (0..REFLECT_ATTEMPTS)
+            .map(|_| reflected + offset(rng))
+            .find(|direction| direction.is_finite() && direction.dot(normal) > 0.0)
+    }
+
+    // The zero vector has no direction and is returned as is, rather than NaN
     pub fn get_unit_vector(&self) -> Self {
-        *self / self.len()
+        let len = self.len();
+        if len == 0.0 {
+            return *self;
+        }
+
+        *self / len
+    }
+
+    // Copies with one component replaced, keeping the type
+    pub fn with_x(&self, x: Scalar) -> Self {
+        Self::new(x, self.data.1, self.data.2, self.data_type)
+    }
+
+    pub fn with_y(&self, y: Scalar) -> Self {
+        Self::new(self.data.0, y, self.data.2, self.data_type)
+    }
+
+    pub fn with_z(&self, z: Scalar) -> Self {
+        Self::new(self.data.0, self.data.1, z, self.data_type)
     }
 
-    pub fn length_squared(&self) -> f64 {
+    pub fn length_squared(&self) -> Scalar {
         self.data.0 * self.data.0 + self.data.1 * self.data.1 + self.data.2 * self.data.2
     }
 
-    pub fn random(min: f64, max: f64) -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn random(min: Scalar, max: Scalar) -> Self {
+        Self::random_with(&mut rand::thread_rng(), min, max)
+    }
 
+    pub fn random_with<R: Rng + ?Sized>(rng: &mut R, min: Scalar, max: Scalar) -> Self {
         Self::new(
             rng.gen_range(min..max),
             rng.gen_range(min..max),
@@ -59,32 +181,78 @@ impl Vector {
         )
     }
 
-    pub fn random_in_unit_sphere() -> Self {
+    pub fn random_in_unit_sphere_with<R: Rng + ?Sized>(rng: &mut R) -> Self {
         loop {
-            let p = Self::random(-1.0, 1.0);
+            let p = Self::random_with(rng, -1.0, 1.0);
             if p.length_squared() < 1.0 {
                 return p;
             }
         }
     }
 
-    pub fn random_unit_vector() -> Self {
-        Self::random_in_unit_sphere().get_unit_vector()
+    // Relative luminance of a linear Rec. 709 color
+    pub fn luminance(&self) -> Scalar {
+        0.2126 * self.data.0 + 0.7152 * self.data.1 + 0.0722 * self.data.2
     }
 
-    pub fn random_in_hemisphere(normal: &Self) -> Self {
-        let in_unit_sphere = Vector::random_in_unit_sphere();
-        if in_unit_sphere.dot(normal) > 0.0 {
-            return in_unit_sphere;
-        } else {
-            return -in_unit_sphere;
-        }
+    // Clamps every component to [0, 1]
+    pub fn clamp01(&self) -> Self {
+        Self::new(
+            self.data.0.clamp(0.0, 1.0),
+            self.data.1.clamp(0.0, 1.0),
+            self.data.2.clamp(0.0, 1.0),
+            self.data_type,
+        )
+    }
+
+    // Encodes a linear color with the piecewise sRGB transfer function. Components
+    // are clamped to [0, 1] first.
+    pub fn to_srgb(&self) -> Self {
+        let encode = |c: Scalar| {
+            let c = c.clamp(0.0, 1.0);
+            if c <= 0.0031308 {
+                12.92 * c
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            }
+        };
+
+        Self::new(
+            encode(self.data.0),
+            encode(self.data.1),
+            encode(self.data.2),
+            self.data_type,
+        )
+    }
+
+    // Decodes an sRGB encoded color to linear, the inverse of `to_srgb`
+    pub fn from_srgb(&self) -> Self {
+        let decode = |c: Scalar| {
+            let c = c.clamp(0.0, 1.0);
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+
+        Self::new(
+            decode(self.data.0),
+            decode(self.data.1),
+            decode(self.data.2),
+            self.data_type,
+        )
+    }
+
+    // No component is NaN or infinite
+    pub fn is_finite(&self) -> bool {
+        self.data.0.is_finite() && self.data.1.is_finite() && self.data.2.is_finite()
     }
 
     pub fn near_zero(&self) -> bool {
-        self.data.0.abs() < f64::EPSILON
-            && self.data.1.abs() < f64::EPSILON
-            && self.data.2.abs() < f64::EPSILON
+        self.data.0.abs() < Scalar::EPSILON
+            && self.data.1.abs() < Scalar::EPSILON
+            && self.data.2.abs() < Scalar::EPSILON
     }
 }
 
@@ -135,10 +303,10 @@ impl Mul for Vector {
     }
 }
 
-impl Mul<f64> for Vector {
+impl Mul<Scalar> for Vector {
     type Output = Self;
 
-    fn mul(self, other: f64) -> Self::Output {
+    fn mul(self, other: Scalar) -> Self::Output {
         Self::new(
             self.data.0 * other,
             self.data.1 * other,
@@ -148,7 +316,7 @@ impl Mul<f64> for Vector {
     }
 }
 
-impl Mul<Vector> for f64 {
+impl Mul<Vector> for Scalar {
     type Output = Vector;
 
     fn mul(self, other: Vector) -> Self::Output {
@@ -159,7 +327,15 @@ impl Mul<Vector> for f64 {
 impl Div for Vector {
     type Output = Self;
 
+    // Componentwise. Dividing by a zero component is a bug upstream; use `safe_div`
+    // where it can happen
     fn div(self, other: Self) -> Self::Output {
+        debug_assert!(
+            other.data.0 != 0.0 && other.data.1 != 0.0 && other.data.2 != 0.0,
+            "dividing {:?} by a vector with a zero component, {:?}",
+            self.data,
+            other.data
+        );
         Self::new(
             self.data.0 / other.data.0,
             self.data.1 / other.data.1,
@@ -169,10 +345,12 @@ impl Div for Vector {
     }
 }
 
-impl Div<f64> for Vector {
+impl Div<Scalar> for Vector {
     type Output = Self;
 
-    fn div(self, other: f64) -> Self::Output {
+    // Like dividing by a vector, dividing by zero is a bug upstream
+    fn div(self, other: Scalar) -> Self::Output {
+        debug_assert!(other != 0.0, "dividing {:?} by zero", self.data);
         Self::new(
             self.data.0 / other,
             self.data.1 / other,
@@ -191,13 +369,16 @@ impl PartialEq for Vector {
     }
 }
 
-pub fn fuzzy_equal(lhs: f64, rhs: f64) -> bool {
+pub fn fuzzy_equal(lhs: Scalar, rhs: Scalar) -> bool {
     let epsilon = 0.0001;
     (lhs - rhs).abs() < epsilon
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn create_vector() {
@@ -234,6 +415,65 @@ mod tests {
         assert_eq!(result.data_type, VectorType::Vector);
     }
 
+    #[test]
+    fn project_and_reject() {
+        let vector = Vector::new(3.0, 4.0, 0.0, VectorType::Vector);
+        let x_axis = Vector::new(1.0, 0.0, 0.0, VectorType::Vector);
+
+        assert_eq!(vector.project_onto(&x_axis).data, (3.0, 0.0, 0.0));
+        assert_eq!(vector.reject_from(&x_axis).data, (0.0, 4.0, 0.0));
+
+        // Only the direction of `other` matters
+        let long_axis = Vector::new(0.0, -5.0, 0.0, VectorType::Vector);
+        assert_eq!(vector.project_onto(&long_axis).data, (0.0, 4.0, 0.0));
+
+        let zero = Vector::new(0.0, 0.0, 0.0, VectorType::Vector);
+        assert_eq!(vector.project_onto(&zero).data, (0.0, 0.0, 0.0));
+        assert_eq!(vector.reject_from(&zero).data, vector.data);
+    }
+
+    #[test]
+    fn angle_between_directions() {
+        let x = Vector::new(2.0, 0.0, 0.0, VectorType::Vector);
+        let y = Vector::new(0.0, 0.5, 0.0, VectorType::Vector);
+
+        assert!(fuzzy_equal(x.angle_between(&y), consts::FRAC_PI_2));
+        assert!(fuzzy_equal(x.angle_between(&-x), consts::PI));
+        assert_eq!(x.angle_between(&x), 0.0);
+
+        // Rounding can put the cosine of parallel vectors a hair past 1
+        let v = Vector::new(0.1, 0.2, 0.3, VectorType::Vector);
+        let angle = v.angle_between(&(3.0 * v));
+        assert!(!angle.is_nan());
+        assert!(angle < 1e-3);
+
+        let zero = Vector::new(0.0, 0.0, 0.0, VectorType::Vector);
+        assert_eq!(zero.angle_between(&x), 0.0);
+    }
+
+    #[test]
+    fn rough_reflection_stays_above_the_surface() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let normal = Vector::new(0.0, 1.0, 0.0, VectorType::Vector);
+        let incident = Vector::new(1.0, -1.0, 0.0, VectorType::Vector);
+
+        let smooth = Vector::reflect_rough(&incident, &normal, 0.0, &mut rng).unwrap();
+        assert_eq!(smooth, incident.get_unit_vector().reflect(&normal));
+
+        for _ in 0..1000 {
+            if let Some(direction) = Vector::reflect_rough(&incident, &normal, 1.0, &mut rng) {
+                assert!(direction.dot(&normal) > 0.0);
+            }
+        }
+
+        // Skimming the surface, every offset is as likely to point in as out
+        let grazing = Vector::new(1.0, -1e-3, 0.0, VectorType::Vector);
+        let kept = (0..1000)
+            .filter(|_| Vector::reflect_rough(&grazing, &normal, 1.0, &mut rng).is_some())
+            .count();
+        assert!(kept > 900);
+    }
+
     #[test]
     fn negate_vector() {
         let color = Vector::new(1.0, 2.0, 3.2, VectorType::Color);
@@ -245,6 +485,25 @@ mod tests {
         assert_eq!(color.data_type, VectorType::Color);
     }
 
+    #[test]
+    fn unit_vector_of_zero_is_zero() {
+        let zero = Vector::new(0.0, 0.0, 0.0, VectorType::Vector);
+
+        assert_eq!(zero.get_unit_vector().data, (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn with_replaces_one_component() {
+        let point = Vector::new(1.0, 2.0, 3.0, VectorType::Point);
+
+        let moved = point.with_y(5.0);
+
+        assert_eq!(moved.data, (1.0, 5.0, 3.0));
+        assert_eq!(moved.data_type, VectorType::Point);
+        assert_eq!(point.with_x(-1.0).data, (-1.0, 2.0, 3.0));
+        assert_eq!(point.with_z(0.0).data, (1.0, 2.0, 0.0));
+    }
+
     #[test]
     fn scale_vector_by_f64() {
         let point = Vector::new(1.0, 2.0, 3.6, VectorType::Point);
@@ -300,7 +559,7 @@ mod tests {
     fn check_fuzzy_equal_on_vectors() {
         let vector = Vector::new(5.0, 0.9, 0.15, VectorType::Vector);
         let vector = vector / 3.0;
-        let cpm_vector = Vector::new(1.666666666666, 0.3, 0.05, VectorType::Vector);
+        let cpm_vector = Vector::new(1.666666, 0.3, 0.05, VectorType::Vector);
 
         assert_eq!(vector, cpm_vector);
     }
@@ -348,4 +607,71 @@ mod tests {
 
         assert_eq!(result, expected_result)
     }
+
+    #[test]
+    fn luminance_of_colors() {
+        let white = Vector::new(1.0, 1.0, 1.0, VectorType::Color);
+        let green = Vector::new(0.0, 1.0, 0.0, VectorType::Color);
+
+        assert!(fuzzy_equal(white.luminance(), 1.0));
+        assert!(fuzzy_equal(green.luminance(), 0.7152));
+    }
+
+    #[test]
+    fn clamp01_limits_components() {
+        let color = Vector::new(-0.5, 0.25, 3.0, VectorType::Color).clamp01();
+
+        assert_eq!(color, Vector::new(0.0, 0.25, 1.0, VectorType::Color));
+    }
+
+    #[test]
+    fn linear_to_srgb() {
+        let encoded = Vector::new(0.0031308, 0.5, 1.0, VectorType::Color).to_srgb();
+
+        assert!(fuzzy_equal(encoded.data.0, 0.04045));
+        assert!(fuzzy_equal(encoded.data.1, 0.73536));
+        assert!(fuzzy_equal(encoded.data.2, 1.0));
+        assert_eq!(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Color).to_srgb(),
+            Vector::new(0.0, 0.0, 0.0, VectorType::Color)
+        );
+    }
+
+    #[test]
+    fn finite_vectors() {
+        assert!(Vector::new(1.0, -2.0, 0.0, VectorType::Color).is_finite());
+        assert!(!Vector::new(Scalar::NAN, 0.0, 0.0, VectorType::Color).is_finite());
+        assert!(!Vector::new(0.0, Scalar::INFINITY, 0.0, VectorType::Color).is_finite());
+        assert!(!Vector::new(0.0, 0.0, Scalar::NEG_INFINITY, VectorType::Color).is_finite());
+    }
+
+    #[test]
+    fn safe_div_skips_zero_components() {
+        let a = Vector::new(1.0, 2.0, 3.0, VectorType::Color);
+        let b = Vector::new(0.0, 2.0, 0.0, VectorType::Color);
+
+        assert_eq!(
+            a.safe_div(&b),
+            Vector::new(0.0, 1.0, 0.0, VectorType::Color)
+        );
+        assert_eq!(
+            a.safe_div_or(&b, 5.0),
+            Vector::new(5.0, 1.0, 5.0, VectorType::Color)
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "zero component")]
+    fn dividing_by_a_zero_component_is_caught() {
+        let _ = Vector::new(1.0, 2.0, 3.0, VectorType::Color)
+            / Vector::new(1.0, 0.0, 1.0, VectorType::Color);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "by zero")]
+    fn dividing_by_zero_is_caught() {
+        let _ = Vector::new(1.0, 2.0, 3.0, VectorType::Color) / 0.0;
+    }
 }
@@ -0,0 +1,454 @@
+use crate::aabb::Aabb;
+use crate::hit::{HitRecord, Hittable};
+use crate::light::PointLight;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::utils::{SkyGradient, SkyMapping};
+use crate::validate::Warning;
+use crate::vector::{Scalar, Vector};
+
+#[derive(Default)]
+pub struct World {
+    // Only grows, through `add`, so indices into it stay valid
+    objects: Vec<Box<dyn Hittable>>,
+    // Only used by the direct lighting of non-physical materials such as Phong;
+    // the path tracer itself never hits them
+    pub point_lights: Vec<PointLight>,
+    // Indices into `objects` of the emitters, for sampling lights directly
+    pub lights: Vec<usize>,
+    // When set, every hit reports this material instead of the primitive's own
+    pub override_material: Option<Material>,
+    // Distance rays leaving a surface skip before they can hit anything, in place of
+    // the one worked out from the size of the scene, see `epsilon`
+    pub epsilon: Option<Scalar>,
+    // The scene's own sky, used instead of the one in the render options
+    pub sky: Option<SkyGradient>,
+    // How the sky colors are spread over the directions that miss everything
+    pub sky_mapping: SkyMapping,
+    // Box around every bounded object added so far
+    bounds: Option<Aabb>,
+    // The bounding box of each object, in the order of `objects`
+    boxes: Vec<Option<Aabb>>,
+}
+
+// Fraction of the scene's diagonal that rays leaving a surface skip. A ground
+// sphere like `random_scene`'s makes the bounds far larger than the detail in the
+// scene: its diagonal is about 3500, so 1e-4 of it would skip 0.35, past the whole
+// of its 0.2 radius spheres. 1e-6 keeps such a scene near the old fixed 1e-4.
+const EPSILON_PER_UNIT: Scalar = 1e-6;
+// The epsilon of worlds without any bounded objects to measure
+const DEFAULT_EPSILON: Scalar = 1e-4;
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<H: Hittable + 'static>(&mut self, object: H) {
+        self.add_boxed(Box::new(object));
+    }
+
+    // `add` for an object that is already boxed, e.g. one of a list of mixed shapes
+    pub fn add_boxed(&mut self, object: Box<dyn Hittable>) {
+        let bounding_box = object.bounding_box();
+        if let Some(bounds) = bounding_box {
+            self.bounds = Some(match self.bounds {
+                Some(all) => all.surrounding(&bounds),
+                None => bounds,
+            });
+        }
+        self.boxes.push(bounding_box);
+        self.objects.push(object);
+    }
+
+    // Everything added to the world, in the order it was added
+    pub fn objects(&self) -> &[Box<dyn Hittable>] {
+        &self.objects
+    }
+
+    pub fn with_sky(mut self, sky: SkyGradient) -> Self {
+        self.sky = Some(sky);
+        self
+    }
+
+    pub fn with_sky_mapping(mut self, mapping: SkyMapping) -> Self {
+        self.sky_mapping = mapping;
+        self
+    }
+
+    // The world's own sky as (bottom, top) when it has one, otherwise the given one
+    pub fn sky_or(&self, bottom: Vector, top: Vector) -> (Vector, Vector) {
+        self.sky.map_or((bottom, top), |sky| (sky.bottom, sky.top))
+    }
+
+    // Sets the epsilon explicitly, e.g. for scenes whose bounds say little about the
+    // size of their details
+    pub fn with_epsilon(mut self, epsilon: Scalar) -> Self {
+        self.epsilon = Some(epsilon);
+        self
+    }
+
+    // The `t_min` of rays leaving surfaces, so they do not hit the surface they left
+    // again through rounding error (shadow acne). That error grows with the size of
+    // the coordinates, so unless set explicitly the epsilon grows with the scene:
+    // a scene built 1000 times larger skips 1000 times as far.
+    pub fn epsilon(&self) -> Scalar {
+        if let Some(epsilon) = self.epsilon {
+            return epsilon;
+        }
+
+        match self.bounds {
+            Some(bounds) => {
+                let diagonal = (bounds.max - bounds.min).len();
+                if diagonal.is_normal() {
+                    EPSILON_PER_UNIT * diagonal
+                } else {
+                    DEFAULT_EPSILON
+                }
+            }
+            None => DEFAULT_EPSILON,
+        }
+    }
+
+    // `epsilon` as a `t_min` for `r`, whose `t` counts lengths of its direction
+    // rather than distance: camera rays are often far from unit length
+    pub fn t_min(&self, r: &Ray) -> Scalar {
+        self.epsilon() / r.direction.len()
+    }
+
+    // Shades every object with `material`, e.g. a flat gray Lambertian to look at the
+    // geometry on its own. The objects themselves keep their materials.
+    pub fn with_override(mut self, material: Material) -> Self {
+        self.override_material = Some(material);
+        self
+    }
+
+    pub fn add_point_light(&mut self, light: PointLight) {
+        self.point_lights.push(light);
+    }
+
+    pub fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+        self.hit_indexed(r, t_min, t_max).map(|(_, hit)| hit)
+    }
+
+    // Like `hit`, but also reports the index into `objects` of the primitive hit
+    pub fn hit_indexed(
+        &self,
+        r: &Ray,
+        t_min: Scalar,
+        t_max: Scalar,
+    ) -> Option<(usize, HitRecord<'_>)> {
+        let mut closest_so_far = t_max;
+        let mut hit_record = None;
+        for (index, (object, bounds)) in self.objects.iter().zip(&self.boxes).enumerate() {
+            // The slab test is cheaper than most primitives' own, and rules out
+            // everything that cannot beat the closest hit so far
+            if let Some(bounds) = bounds {
+                if !bounds.hit(r, t_min, closest_so_far) {
+                    continue;
+                }
+            }
+
+            if let Some(hit) = object.hit(r, t_min, closest_so_far) {
+                closest_so_far = hit.t;
+                hit_record = Some((index, hit));
+            }
+        }
+
+        if let (Some((_, hit)), Some(material)) = (&mut hit_record, &self.override_material) {
+            hit.material = material;
+        }
+
+        hit_record
+    }
+
+    // Every likely authoring mistake in the scene, such as zero sized or NaN
+    // primitives and materials with impossible parameters. Nothing is changed; the
+    // caller decides whether to print the warnings or give up.
+    pub fn validate(&self) -> Vec<Warning> {
+        self.objects
+            .iter()
+            .enumerate()
+            .flat_map(|(primitive, object)| {
+                object
+                    .problems()
+                    .into_iter()
+                    .map(move |problem| Warning { primitive, problem })
+            })
+            .collect()
+    }
+}
+
+impl From<Vec<Sphere>> for World {
+    fn from(spheres: Vec<Sphere>) -> Self {
+        let mut world = World::new();
+        for sphere in spheres {
+            world.add(sphere);
+        }
+        world
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Metal, Scatterable};
+    use crate::vector::VectorType;
+
+    #[test]
+    fn override_replaces_hit_material() {
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            0.5,
+            Material::Metal(Metal::new(
+                Vector::new(0.8, 0.8, 0.8, VectorType::Color),
+                0.0,
+            )),
+        ));
+        let ray = Ray::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+        );
+
+        let hit = world.hit(&ray, 0.001, Scalar::INFINITY).unwrap();
+        assert!(matches!(hit.material, Material::Metal(_)));
+
+        let world = world.with_override(Material::Lambertian(Lambertian::new(Vector::new(
+            0.2,
+            0.4,
+            0.6,
+            VectorType::Color,
+        ))));
+
+        let hit = world.hit(&ray, 0.001, Scalar::INFINITY).unwrap();
+        assert!(matches!(hit.material, Material::Lambertian(_)));
+
+        // Scatters like the override: diffuse bounce tinted with its albedo
+        let (scattered, attenuation) = hit
+            .material
+            .scatter(&ray, &hit, &mut rand::thread_rng())
+            .unwrap();
+        assert_eq!(attenuation, Vector::new(0.2, 0.4, 0.6, VectorType::Color));
+        assert!(scattered.unwrap().direction.dot(&hit.normal) >= 0.0);
+    }
+
+    #[test]
+    fn boxes_skip_full_intersections_without_changing_hits() {
+        use crate::aabb::Aabb;
+        use crate::hit::Hittable;
+        use crate::scene::random_scene;
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static BOXES: AtomicUsize = AtomicUsize::new(0);
+
+        // Counts the full intersection tests and bounding boxes of the sphere it wraps
+        struct Counted(Sphere);
+
+        impl Hittable for Counted {
+            fn hit(&self, r: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitRecord<'_>> {
+                CALLS.fetch_add(1, Ordering::Relaxed);
+                self.0.hit(r, t_min, t_max)
+            }
+
+            fn bounding_box(&self) -> Option<Aabb> {
+                BOXES.fetch_add(1, Ordering::Relaxed);
+                self.0.bounding_box()
+            }
+        }
+
+        let spheres = random_scene(5);
+        let count = spheres.len();
+        let mut world = World::new();
+        for sphere in random_scene(5) {
+            world.add(Counted(sphere));
+        }
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for _ in 0..200 {
+            let origin = Vector::new(
+                rng.gen_range(-12.0..12.0),
+                rng.gen_range(0.5..3.0),
+                rng.gen_range(-12.0..12.0),
+                VectorType::Point,
+            );
+            let direction = Vector::random_in_unit_sphere_with(&mut rng);
+            let ray = Ray::new(origin, direction);
+
+            let closest = spheres
+                .iter()
+                .filter_map(|sphere| sphere.hit(&ray, 0.001, Scalar::INFINITY))
+                .map(|hit| hit.t)
+                .fold(None, |closest: Option<Scalar>, t| {
+                    Some(closest.map_or(t, |c| c.min(t)))
+                });
+            let hit = world.hit(&ray, 0.001, Scalar::INFINITY).map(|hit| hit.t);
+
+            assert_eq!(hit, closest);
+        }
+
+        // Most spheres are far off any one ray
+        let calls = CALLS.load(Ordering::Relaxed);
+        assert!(calls < 200 * count / 10, "{} of {}", calls, 200 * count);
+        // The boxes are worked out once, as the spheres are added
+        assert_eq!(BOXES.load(Ordering::Relaxed), count);
+    }
+
+    #[test]
+    fn boxed_and_generic_objects_keep_their_own_boxes() {
+        let sphere = |z: Scalar| {
+            Sphere::new(
+                Vector::new(0.0, 0.0, z, VectorType::Point),
+                0.5,
+                Material::gray(),
+            )
+        };
+        // The box of the sphere far off to the side must not stand in for the one
+        // straight ahead, whichever way each was added
+        let mut world = World::new();
+        world.add_boxed(Box::new(sphere(-2.0)));
+        world.add(Sphere::new(
+            Vector::new(50.0, 0.0, -2.0, VectorType::Point),
+            0.5,
+            Material::gray(),
+        ));
+        world.add_boxed(Box::new(sphere(-4.0)));
+        world.add(sphere(-6.0));
+        let ray = Ray::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(0.0, 0.0, -1.0, VectorType::Vector),
+        );
+
+        let (index, hit) = world.hit_indexed(&ray, 0.001, Scalar::INFINITY).unwrap();
+        assert_eq!(index, 0);
+        assert!((hit.t - 1.5).abs() < 1e-6);
+        assert_eq!(world.objects().len(), 4);
+    }
+
+    #[test]
+    fn validate_flags_degenerate_primitives() {
+        use crate::material::Dielectric;
+        use crate::quad::Quad;
+        use crate::validate::Problem;
+
+        let mut world = World::new();
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            0.5,
+            Material::gray(),
+        ));
+        // Parallel edges span no area
+        world.add(Quad::new(
+            Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+            Vector::new(1.0, 1.0, 0.0, VectorType::Vector),
+            Vector::new(2.0, 2.0, 0.0, VectorType::Vector),
+            Material::gray(),
+        ));
+        world.add(Sphere::new(
+            Vector::new(Scalar::NAN, 0.0, 0.0, VectorType::Point),
+            1.0,
+            Material::gray(),
+        ));
+        // A hollow glass shell is fine
+        world.add(Sphere::new(
+            Vector::new(0.0, 0.0, -2.0, VectorType::Point),
+            -0.4,
+            Material::Dielectric(Dielectric::new(1.5)),
+        ));
+
+        let warnings = world.validate();
+
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            (warnings[0].primitive, warnings[0].problem),
+            (1, Problem::ZeroArea)
+        );
+        assert_eq!(
+            (warnings[1].primitive, warnings[1].problem),
+            (2, Problem::NonFinite)
+        );
+    }
+
+    // A diffuse sphere on a ground sphere, `scale` times the size of the usual one
+    fn scaled_scene(scale: Scalar) -> World {
+        let point = |x: Scalar, y: Scalar, z: Scalar| {
+            Vector::new(scale * x, scale * y, scale * z, VectorType::Point)
+        };
+
+        let mut world = World::new();
+        world.add(Sphere::new(
+            point(0.0, 0.0, -1.0),
+            scale * 0.5,
+            Material::gray(),
+        ));
+        world.add(Sphere::new(
+            point(0.0, -100.5, -1.0),
+            scale * 100.0,
+            Material::gray(),
+        ));
+        world
+    }
+
+    #[test]
+    fn epsilon_grows_with_the_scene() {
+        let small = scaled_scene(1.0);
+        let large = scaled_scene(1000.0);
+
+        assert!((large.epsilon() / small.epsilon() - 1000.0).abs() < 1e-6);
+        assert_eq!(World::new().epsilon(), DEFAULT_EPSILON);
+        assert_eq!(large.with_epsilon(0.5).epsilon(), 0.5);
+
+        // Far short of the 0.2 radius of its smallest spheres
+        let mut random = World::new();
+        for sphere in crate::scene::random_scene(0) {
+            random.add(sphere);
+        }
+        assert!(random.epsilon() < 0.01, "{}", random.epsilon());
+    }
+
+    #[test]
+    fn large_scene_renders_like_the_small_one() {
+        use crate::config::CameraConfig;
+        use crate::render::{render_with_options, RenderOptions};
+
+        // Large enough for rounding error to outgrow a fixed epsilon of 1e-4
+        #[cfg(not(feature = "f32"))]
+        const LARGE: Scalar = 1e10;
+        #[cfg(feature = "f32")]
+        const LARGE: Scalar = 1e6;
+
+        let render = |scale: Scalar, epsilon: Option<Scalar>| {
+            let mut world = scaled_scene(scale);
+            world.epsilon = epsilon;
+            let camera = CameraConfig {
+                look_from: Vector::new(0.0, 0.0, 0.0, VectorType::Point),
+                look_at: Vector::new(0.0, 0.0, -scale, VectorType::Point),
+                vup: Vector::new(0.0, 1.0, 0.0, VectorType::Vector),
+                vfov: 60.0,
+                aspect_ratio: 1.0,
+                aperture: 0.0,
+                focus_distance: scale,
+            }
+            .camera();
+            let image =
+                render_with_options(16, 16, 16, &world, camera, 10, &RenderOptions::default());
+            image.pixels.iter().map(Vector::luminance).sum::<Scalar>() / 256.0
+        };
+
+        // Acne would darken the surfaces as rays hit where they left
+        let small = render(1.0, None);
+        let large = render(LARGE, None);
+        assert!(
+            (large - small).abs() < 0.01 * small,
+            "{} vs {}",
+            large,
+            small
+        );
+        let fixed = render(LARGE, Some(DEFAULT_EPSILON));
+        assert!(small - fixed > 0.03 * small, "{} vs {}", fixed, small);
+    }
+}